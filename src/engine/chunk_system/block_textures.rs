@@ -0,0 +1,259 @@
+use crate::engine::gpu::GpuCtx;
+use image::{Rgba, RgbaImage};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Extent3d, FilterMode, Origin3d, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+/// Width and height of a single block texture in texels
+pub const BLOCK_TEXTURE_SIZE: u32 = 16;
+
+/// Layers `block_emissive` in the chunk shader has room for, the default texture array layer limit
+const MAX_BLOCK_TEXTURES: usize = 256;
+
+/// Every block texture as one layer of a `texture_2d_array`, so mips are generated per texture
+/// and can't bleed into neighbouring ones like they would in a packed atlas
+pub struct BlockTextures {
+    _texture: Texture,
+    view: TextureView,
+    emissive: Buffer,
+    anisotropy: u16,
+    bind_group: BindGroup,
+}
+
+impl BlockTextures {
+    /// `textures` become the layers in order and must all be `BLOCK_TEXTURE_SIZE` squared,
+    /// `emissive` is how much light each layer gives off
+    pub fn new(
+        gpu_ctx: &GpuCtx,
+        textures: &[RgbaImage],
+        emissive: &[f32],
+        anisotropy: u16,
+    ) -> Self {
+        let (texture, view) = create_block_texture_array(gpu_ctx, textures);
+        let emissive = create_emissive_buffer(gpu_ctx, emissive);
+        let sampler = create_block_sampler(gpu_ctx, anisotropy);
+        let bind_group = create_block_texture_bind_group(gpu_ctx, &view, &emissive, &sampler);
+
+        Self {
+            _texture: texture,
+            view,
+            emissive,
+            anisotropy,
+            bind_group,
+        }
+    }
+
+    pub fn get_anisotropy(&self) -> u16 {
+        self.anisotropy
+    }
+
+    /// 1 turns anisotropic filtering off, the backend clamps anything above what it supports
+    pub fn set_anisotropy(&mut self, gpu_ctx: &GpuCtx, anisotropy: u16) {
+        self.anisotropy = anisotropy.clamp(1, 16);
+        let sampler = create_block_sampler(gpu_ctx, self.anisotropy);
+        self.bind_group =
+            create_block_texture_bind_group(gpu_ctx, &self.view, &self.emissive, &sampler);
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
+
+pub fn create_block_texture_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+}
+
+fn create_block_texture_bind_group(
+    gpu_ctx: &GpuCtx,
+    view: &TextureView,
+    emissive: &Buffer,
+    sampler: &Sampler,
+) -> BindGroup {
+    gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &create_block_texture_bind_group_layout(gpu_ctx),
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: emissive.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Always `MAX_BLOCK_TEXTURES` long, since uniform arrays have a fixed size in the shader
+fn create_emissive_buffer(gpu_ctx: &GpuCtx, emissive: &[f32]) -> Buffer {
+    let mut contents = [0.0f32; MAX_BLOCK_TEXTURES];
+    contents[..emissive.len()].copy_from_slice(emissive);
+
+    gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&contents),
+        usage: BufferUsages::UNIFORM,
+    })
+}
+
+fn create_block_texture_array(gpu_ctx: &GpuCtx, textures: &[RgbaImage]) -> (Texture, TextureView) {
+    let mip_level_count = BLOCK_TEXTURE_SIZE.ilog2() + 1;
+
+    let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: BLOCK_TEXTURE_SIZE,
+            height: BLOCK_TEXTURE_SIZE,
+            depth_or_array_layers: textures.len() as u32,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for (layer, texture_image) in textures.iter().enumerate() {
+        let mut mip = texture_image.clone();
+
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                mip = downsample(&mip);
+            }
+
+            gpu_ctx.queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &mip,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * mip.width()),
+                    rows_per_image: Some(mip.height()),
+                },
+                Extent3d {
+                    width: mip.width(),
+                    height: mip.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    let view = texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+/// Trilinear, the chunk shader keeps texels sharp up close on its own
+fn create_block_sampler(gpu_ctx: &GpuCtx, anisotropy: u16) -> Sampler {
+    gpu_ctx.device.create_sampler(&SamplerDescriptor {
+        label: None,
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Linear,
+        anisotropy_clamp: anisotropy.clamp(1, 16),
+        ..Default::default()
+    })
+}
+
+/// Halves the image with a 2x2 box filter, averaged in linear space so mips don't darken
+fn downsample(image: &RgbaImage) -> RgbaImage {
+    let width = (image.width() / 2).max(1);
+    let height = (image.height() / 2).max(1);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(d_x, d_y)| {
+            let s_x = (x * 2 + d_x).min(image.width() - 1);
+            let s_y = (y * 2 + d_y).min(image.height() - 1);
+            image.get_pixel(s_x, s_y)
+        });
+
+        let mut texel = [0; 4];
+        for (channel, value) in texel.iter_mut().enumerate() {
+            *value = if channel == 3 {
+                let sum: u32 = texels.iter().map(|t| t[3] as u32).sum();
+                ((sum + 2) / 4) as u8
+            } else {
+                let sum: f32 = texels.iter().map(|t| srgb_to_linear(t[channel])).sum();
+                linear_to_srgb(sum / 4.0)
+            };
+        }
+        Rgba(texel)
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
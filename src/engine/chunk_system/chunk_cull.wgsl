@@ -0,0 +1,121 @@
+struct CullParams {
+    view_proj: mat4x4f,
+    planes: array<vec4f, 6>,
+    viewport: vec2f,
+    chunk_count: u32,
+    // 0 when occlusion culling is disabled
+    hi_z_mip_count: u32,
+    // Hi-Z holds flipped depth when this is set, see hi_z.wgsl
+    reverse_z: u32,
+}
+
+struct ChunkBounds {
+    min: vec3f,
+    index_count: u32,
+    max: vec3f,
+    first_index: u32,
+    base_vertex: i32,
+}
+
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: CullParams;
+
+@group(0) @binding(1)
+var<storage, read> chunks: array<ChunkBounds>;
+
+@group(0) @binding(2)
+var<storage, read_write> draws: array<DrawIndexedIndirect>;
+
+@group(0) @binding(3)
+var hi_z: texture_2d<f32>;
+
+// Read through a nearest mip sampler instead of `textureLoad`, GL only fetches from levels
+// past the first when the texture is bound with a mipmapped filter
+@group(0) @binding(4)
+var hi_z_sampler: sampler;
+
+fn load_hi_z(texel: vec2u, dims: vec2u, level: i32) -> f32 {
+    let uv = (vec2f(texel) + 0.5) / vec2f(dims);
+    return textureSampleLevel(hi_z, hi_z_sampler, uv, f32(level)).r;
+}
+
+fn in_frustum(box_min: vec3f, box_max: vec3f) -> bool {
+    for (var i = 0; i < 6; i++) {
+        let plane = params.planes[i];
+        // Corner of the box furthest along the plane normal
+        let corner = select(box_min, box_max, plane.xyz >= vec3f(0.0));
+        if (dot(plane.xyz, corner) + plane.w < 0.0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn is_occluded(box_min: vec3f, box_max: vec3f) -> bool {
+    if (params.hi_z_mip_count == 0u) {
+        return false;
+    }
+
+    var ndc_min = vec3f(1.0e9);
+    var ndc_max = vec3f(-1.0e9);
+    for (var i = 0u; i < 8u; i++) {
+        let corner = select(box_min, box_max, vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip = params.view_proj * vec4f(corner, 1.0);
+
+        // Boxes crossing the near plane can't be projected, just draw them
+        if (clip.w <= 0.0) {
+            return false;
+        }
+
+        let ndc = clip.xyz / clip.w;
+        ndc_min = min(ndc_min, ndc);
+        ndc_max = max(ndc_max, ndc);
+    }
+
+    let uv_min = clamp(vec2f(ndc_min.x, -ndc_max.y) * 0.5 + 0.5, vec2f(0.0), vec2f(1.0));
+    let uv_max = clamp(vec2f(ndc_max.x, -ndc_min.y) * 0.5 + 0.5, vec2f(0.0), vec2f(1.0));
+
+    // Pick the level where the box covers at most 2x2 texels
+    let size = (uv_max - uv_min) * params.viewport;
+    let level = i32(clamp(ceil(log2(max(max(size.x, size.y), 1.0))), 0.0, f32(params.hi_z_mip_count - 1u)));
+    let dims = textureDimensions(hi_z, level);
+    let last = dims - vec2u(1u);
+    let p0 = min(vec2u(uv_min * vec2f(dims)), last);
+    let p1 = min(vec2u(uv_max * vec2f(dims)), last);
+
+    let max_depth = max(
+        max(load_hi_z(p0, dims, level), load_hi_z(vec2u(p1.x, p0.y), dims, level)),
+        max(load_hi_z(vec2u(p0.x, p1.y), dims, level), load_hi_z(p1, dims, level)),
+    );
+
+    let nearest_depth = select(ndc_min.z, 1.0 - ndc_max.z, params.reverse_z != 0u);
+    return nearest_depth > max_depth;
+}
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3u) {
+    let i = id.x;
+    if (i >= params.chunk_count) {
+        return;
+    }
+
+    let chunk = chunks[i];
+    let visible = in_frustum(chunk.min, chunk.max) && !is_occluded(chunk.min, chunk.max);
+
+    // Culled chunks keep their slot but draw zero instances
+    draws[i] = DrawIndexedIndirect(
+        chunk.index_count,
+        select(0u, 1u, visible),
+        chunk.first_index,
+        chunk.base_vertex,
+        0u,
+    );
+}
@@ -0,0 +1,81 @@
+// Extra geometry for the debug render modes, drawn in the opaque pass next to the chunks
+
+// Only the camera position is used, see chunk_shader.wgsl for the rest
+struct Environment {
+    inv_view_proj: mat4x4f,
+    camera_pos: vec4f,
+}
+
+// Chunks shown either side of the one the camera is in
+const BORDER_RADIUS = 2;
+// Chunk boundaries along each axis
+const BORDER_LINES = 6u;
+// Horizontal rings every 16 blocks, from the bottom of the world to the top
+const BORDER_LEVELS = 17u;
+const CHUNK_SIZE = 16.0;
+const WORLD_HEIGHT = 256.0;
+
+struct BorderOutput {
+    @builtin(position) pos: vec4f,
+    @location(0) @interpolate(flat) color: vec3f,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: mat4x4f;
+
+@group(2) @binding(0)
+var<uniform> environment: Environment;
+
+// Line list of the chunk grid around the camera. The first BORDER_LINES^2 lines run up the chunk
+// corners, the rest go around the chunks at every level. Corners of the camera's chunk stand out
+@vertex
+fn v_border(@builtin(vertex_index) index: u32) -> BorderOutput {
+    let line = index / 2u;
+    let end = f32(index % 2u);
+    let origin = (floor(environment.camera_pos.xz / CHUNK_SIZE) - f32(BORDER_RADIUS)) * CHUNK_SIZE;
+    let extent = f32(BORDER_LINES - 1u) * CHUNK_SIZE;
+
+    var pos: vec3f;
+    var color = vec3f(0.2, 0.5, 1.0);
+    let corner_lines = BORDER_LINES * BORDER_LINES;
+    if (line < corner_lines) {
+        let corner = vec2u(line % BORDER_LINES, line / BORDER_LINES);
+        pos = vec3f(origin.x + f32(corner.x) * CHUNK_SIZE, end * WORLD_HEIGHT, origin.y + f32(corner.y) * CHUNK_SIZE);
+
+        let own = vec2u(u32(BORDER_RADIUS));
+        if (all(corner >= own) && all(corner <= own + 1u)) {
+            color = vec3f(1.0, 0.85, 0.1);
+        }
+    } else {
+        let ring = line - corner_lines;
+        let level = f32(ring / (2u * BORDER_LINES)) * CHUNK_SIZE;
+        let boundary = f32(ring % BORDER_LINES) * CHUNK_SIZE;
+        if (ring % (2u * BORDER_LINES) < BORDER_LINES) {
+            pos = vec3f(origin.x + end * extent, level, origin.y + boundary);
+        } else {
+            pos = vec3f(origin.x + boundary, level, origin.y + end * extent);
+        }
+    }
+
+    var out: BorderOutput;
+    out.pos = camera * vec4f(pos, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn f_border(in: BorderOutput) -> @location(0) vec4f {
+    return vec4f(in.color, 1.0);
+}
+
+// One triangle covering the screen, clears the sky away before the overdraw heatmap adds up
+@vertex
+fn v_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    let ndc = vec2f(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    return vec4f(ndc, 0.0, 1.0);
+}
+
+@fragment
+fn f_black() -> @location(0) vec4f {
+    return vec4f(0.0, 0.0, 0.0, 1.0);
+}
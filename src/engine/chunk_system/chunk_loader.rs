@@ -1,8 +1,51 @@
-use crate::engine::gpu::GpuMesh;
+use crate::engine::chunk_system::chunk_vertex::ChunkVertex;
+use crate::engine::chunk_system::connectivity::{ChunkVisibility, SECTION_COUNT};
+use crate::engine::chunk_system::lod::Lod;
+#[cfg(feature = "hot-reload")]
+use crate::engine::chunk_system::resource_packs::BlockFaces;
+#[cfg(feature = "hot-reload")]
+use crate::engine::chunk_system::voxel_data::BlockType;
+#[cfg(feature = "hot-reload")]
+use std::collections::HashMap;
+use crate::engine::gpu::{Aabb, GpuMesh, MeshArena};
+use std::ops::Range;
+
+pub struct ChunkMesh {
+    pub mesh: GpuMesh,
+    pub bounds: Aabb,
+    /// Indices of each 16 block tall section, relative to the start of `mesh`
+    pub sections: [Range<u32>; SECTION_COUNT],
+}
+
+/// Where the chunks in render distance are, for the debug HUD
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChunkLoadStats {
+    /// Chunks with voxel data, whether or not their mesh is up to date
+    pub loaded: usize,
+    /// Waiting for their voxels to be generated
+    pub pending: usize,
+    /// Generated but waiting for a mesh, or a new one after a neighbour changed
+    pub meshing: usize,
+    /// Generation and meshing jobs no worker thread has started yet
+    pub queued_jobs: usize,
+}
 
 pub trait ChunkLoader {
-    fn queue_load_chunk(&mut self, pos: (i32, i32));
+    fn queue_load_chunk(&mut self, pos: (i32, i32), lod: Lod);
+    fn set_chunk_lod(&mut self, pos: (i32, i32), lod: Lod);
     fn queue_unload_chunk(&mut self, pos: (i32, i32));
     fn process_chunks(&mut self);
-    fn get_meshes(&self) -> Vec<&GpuMesh>;
+    fn get_meshes(&self) -> Vec<&ChunkMesh>;
+    fn get_mesh(&self, pos: (i32, i32)) -> Option<&ChunkMesh>;
+    /// Known for every meshed chunk, including all air chunks that have no mesh
+    fn get_visibility(&self, pos: (i32, i32)) -> Option<&ChunkVisibility>;
+    /// Changes whenever a mesh is added, replaced or removed
+    fn get_mesh_revision(&self) -> u64;
+    fn get_mesh_arena(&self) -> &MeshArena<ChunkVertex>;
+    /// Every queued chunk is meshed and uploaded, nothing changes until chunks are loaded or unloaded again
+    fn is_idle(&self) -> bool;
+    fn get_load_stats(&self) -> ChunkLoadStats;
+    /// Every loaded chunk gets remeshed with the new faces
+    #[cfg(feature = "hot-reload")]
+    fn set_block_faces(&mut self, faces: HashMap<BlockType, BlockFaces>);
 }
@@ -1,23 +1,112 @@
 struct VertexInput {
     @location(0) pos: vec3f,
     @location(1) normal: vec3f,
-    @location(2) tex_coords: vec2f
+    @location(2) tex_coords: vec2f,
+    @location(3) layer: u32
 }
 
 struct VertexOutput {
     @builtin(position) pos: vec4f,
     @location(0) normal: vec3f,
-    @location(1) tex_coords: vec2f
+    @location(1) tex_coords: vec2f,
+    @location(2) world_pos: vec3f,
+    @location(3) @interpolate(flat) layer: u32
 }
 
+// Filled in by the sky pass, see sky.wgsl
+struct Environment {
+    inv_view_proj: mat4x4f,
+    camera_pos: vec4f,
+    sun_direction: vec4f,
+    moon_direction: vec4f,
+    sun_color: vec4f,
+    zenith_color: vec4f,
+    horizon_color: vec4f,
+    // x: distance fog starts at, y: distance it is fully opaque at
+    fog: vec4f,
+    // x: multiplier for all lighting, y: sunlight strength, z: moonlight strength
+    light: vec4f,
+}
+
+// Filled in by the shadow pass, see shadows.rs
+struct Shadows {
+    light_view_proj: array<mat4x4f, 4>,
+    // Far view depth of each cascade
+    splits: vec4f,
+    // World size of a texel in each cascade
+    texel_sizes: vec4f,
+    camera_forward: vec4f,
+    cascade_count: u32,
+    resolution: f32,
+}
+
+// Width and height of a block texture in texels, see block_textures.rs
+const BLOCK_TEXTURE_SIZE = 16.0;
+
+// How lit faces turned away from the sun and moon are
+const AMBIENT = 0.55;
+
 @group(0) @binding(0)
 var<uniform> camera: mat4x4f;
 
 @group(1) @binding(0)
-var texture_atlas: texture_2d<f32>;
+var block_textures: texture_2d_array<f32>;
 
 @group(1) @binding(1)
-var texture_atlas_sampler: sampler;
+var block_sampler: sampler;
+
+// Light each block texture layer gives off, four layers to a vector, see block_textures.rs
+@group(1) @binding(2)
+var<uniform> block_emissive: array<vec4f, 64>;
+
+@group(2) @binding(0)
+var<uniform> environment: Environment;
+
+@group(3) @binding(0)
+var<uniform> shadows: Shadows;
+
+@group(3) @binding(1)
+var shadow_maps: texture_depth_2d_array;
+
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+// Moves the sample point to the nearest texel center unless it is within a screen pixel of a texel edge,
+// so magnified textures stay as sharp as nearest filtering while still using mips when minified
+fn sharp_tex_coords(tex_coords: vec2f) -> vec2f {
+    let texels = tex_coords * BLOCK_TEXTURE_SIZE;
+    let edge = floor(texels + 0.5);
+    let offset = clamp((texels - edge) / max(fwidth(texels), vec2f(1.0e-5)), vec2f(-0.5), vec2f(0.5));
+    return (edge + offset) / BLOCK_TEXTURE_SIZE;
+}
+
+// 1 when fully lit, 0 when fully in shadow, anything past the last cascade is lit
+fn shadow_factor(world_pos: vec3f, normal: vec3f) -> f32 {
+    let view_depth = dot(world_pos - environment.camera_pos.xyz, shadows.camera_forward.xyz);
+    var cascade = 0u;
+    while (cascade < shadows.cascade_count && view_depth > shadows.splits[cascade]) {
+        cascade++;
+    }
+    if (cascade == shadows.cascade_count) {
+        return 1.0;
+    }
+
+    // Pushing the sample point off the surface stops faces from shadowing themselves
+    let offset_pos = world_pos + normal * shadows.texel_sizes[cascade] * 1.5;
+    let clip = shadows.light_view_proj[cascade] * vec4f(offset_pos, 1.0);
+    let uv = clip.xy * vec2f(0.5, -0.5) + 0.5;
+    let texel = 1.0 / shadows.resolution;
+
+    // 3x3 PCF, each tap is already bilinearly filtered by the comparison sampler
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2f(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, cascade, clip.z);
+        }
+    }
+    return lit / 9.0;
+}
 
 @vertex
 fn v_main(in: VertexInput) -> VertexOutput {
@@ -25,13 +114,80 @@ fn v_main(in: VertexInput) -> VertexOutput {
     out.pos = camera * vec4f(in.pos, 1.0);
     out.normal = in.normal;
     out.tex_coords = in.tex_coords;
+    out.world_pos = in.pos;
+    out.layer = in.layer;
     return out;
 }
 
-@fragment
-fn f_main(in: VertexOutput) -> @location(0) vec4f {
+// Lit, shadowed and fogged block color
+fn shade(in: VertexOutput) -> vec4f {
 //    let inverse_depth = 1.0 / pow(abs(in.v_pos.z), 2.0);
 //    return vec4f(inverse_depth, 0.0, inverse_depth, 1.0);
 //    return vec4f(1.0, 0.0, 1.0, 1.0);
-    return textureSample(texture_atlas, texture_atlas_sampler, in.tex_coords);
-}
\ No newline at end of file
+    // Gradients of the unmodified coordinates pick the mip level and anisotropy
+    let color = textureSampleGrad(
+        block_textures,
+        block_sampler,
+        sharp_tex_coords(in.tex_coords),
+        in.layer,
+        dpdx(in.tex_coords),
+        dpdy(in.tex_coords),
+    );
+
+    let normal = normalize(in.normal);
+    let sun = environment.light.y * max(dot(normal, environment.sun_direction.xyz), 0.0);
+    let moon = environment.light.z * max(dot(normal, environment.moon_direction.xyz), 0.0);
+    let direct = max(sun, moon) * shadow_factor(in.world_pos, normal);
+    // Glowing blocks ignore the time of day, in HDR they can go past 1 and bloom
+    let emissive = block_emissive[in.layer / 4u][in.layer % 4u];
+    let lit = color.rgb * (mix(AMBIENT, 1.0, direct) * environment.light.x + emissive);
+
+    // Measured horizontally so fog lines up with the circle of loaded chunks
+    let distance = length(in.world_pos.xz - environment.camera_pos.xz);
+    let fog = smoothstep(environment.fog.x, environment.fog.y, distance);
+    return vec4f(mix(lit, environment.horizon_color.rgb, fog), color.a);
+}
+
+@fragment
+fn f_main(in: VertexOutput) -> @location(0) vec4f {
+    return shade(in);
+}
+
+// Debug render modes, see `DebugRenderMode`
+
+// Green stands out against both the day and the night sky
+@fragment
+fn f_wireframe(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(0.1, 0.9, 0.2, 1.0);
+}
+
+@fragment
+fn f_normals(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(normalize(in.normal) * 0.5 + 0.5, 1.0);
+}
+
+// Bright enough that no chunk turns black, different enough that neighbours stand apart
+fn chunk_tint(chunk: vec2i) -> vec3f {
+    var hash = bitcast<u32>(chunk.x) * 0x8da6b343u ^ bitcast<u32>(chunk.y) * 0xd8163841u;
+    hash = (hash ^ (hash >> 16u)) * 0x7feb352du;
+    hash = (hash ^ (hash >> 15u)) * 0x846ca68bu;
+    hash ^= hash >> 16u;
+    let random = vec3f(vec3u(hash, hash >> 8u, hash >> 16u) & vec3u(255u)) / 255.0;
+    return mix(vec3f(0.25), vec3f(1.0), random);
+}
+
+@fragment
+fn f_chunk_tint(in: VertexOutput) -> @location(0) vec4f {
+    // Faces lie on block boundaries, half a block back along the normal is inside the block they belong to
+    let block = floor(in.world_pos - in.normal * 0.5);
+    let chunk = vec2i(floor(block.xz / 16.0));
+    let color = shade(in);
+    return vec4f(color.rgb * chunk_tint(chunk), color.a);
+}
+
+// Blended additively without depth testing, so every layer of faces covering a pixel adds to it.
+// Tonemapping turns the sum into a ramp from dark red through orange to white
+@fragment
+fn f_overdraw(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(0.12, 0.04, 0.01, 1.0);
+}
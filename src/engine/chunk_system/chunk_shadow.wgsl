@@ -0,0 +1,14 @@
+struct VertexInput {
+    @location(0) pos: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) tex_coords: vec2f
+}
+
+@group(0) @binding(0)
+var<uniform> light_view_proj: mat4x4f;
+
+// Depth only, there is no fragment stage
+@vertex
+fn v_main(in: VertexInput) -> @builtin(position) vec4f {
+    return light_view_proj * vec4f(in.pos, 1.0);
+}
@@ -7,11 +7,13 @@ use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode};
 pub struct ChunkVertex {
     pub pos: [f32; 3],
     pub normal: [f32; 3],
-    pub tex_coords: [f32; 2]
+    pub tex_coords: [f32; 2],
+    /// Layer of the block texture array
+    pub layer: u32
 }
 
 impl ChunkVertex {
-    const ATTRIBS: [VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+    const ATTRIBS: [VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Uint32];
 }
 
 impl Vertex for ChunkVertex {
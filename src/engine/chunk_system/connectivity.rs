@@ -0,0 +1,344 @@
+use crate::engine::chunk_system::voxel_data::{BlockType, VoxelData};
+use std::collections::{HashSet, VecDeque};
+
+pub const SECTION_SIZE: usize = 16;
+pub const SECTION_COUNT: usize = 256 / SECTION_SIZE;
+
+/// A 16x16x16 piece of a chunk, `(chunk x, section y, chunk z)`
+pub type SectionPos = (i32, i32, i32);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::PosX,
+        Direction::NegX,
+        Direction::PosY,
+        Direction::NegY,
+        Direction::PosZ,
+        Direction::NegZ,
+    ];
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::PosX => Direction::NegX,
+            Direction::NegX => Direction::PosX,
+            Direction::PosY => Direction::NegY,
+            Direction::NegY => Direction::PosY,
+            Direction::PosZ => Direction::NegZ,
+            Direction::NegZ => Direction::PosZ,
+        }
+    }
+
+    pub fn step(self, (x, y, z): SectionPos) -> SectionPos {
+        match self {
+            Direction::PosX => (x + 1, y, z),
+            Direction::NegX => (x - 1, y, z),
+            Direction::PosY => (x, y + 1, z),
+            Direction::NegY => (x, y - 1, z),
+            Direction::PosZ => (x, y, z + 1),
+            Direction::NegZ => (x, y, z - 1),
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Which faces of a section can see each other through air
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct SectionVisibility {
+    connections: u64,
+}
+
+impl SectionVisibility {
+    pub fn connects(&self, from: Direction, to: Direction) -> bool {
+        self.connections & (1 << (from as u8 * 6 + to as u8)) != 0
+    }
+
+    /// Every face touched by the same pocket of air can see every other one
+    fn connect_all(&mut self, faces: u8) {
+        for from in Direction::ALL {
+            for to in Direction::ALL {
+                if faces & from.bit() != 0 && faces & to.bit() != 0 {
+                    self.connections |= 1 << (from as u8 * 6 + to as u8);
+                }
+            }
+        }
+    }
+}
+
+pub type ChunkVisibility = [SectionVisibility; SECTION_COUNT];
+
+pub fn compute_chunk_visibility(voxels: &VoxelData) -> ChunkVisibility {
+    std::array::from_fn(|section| compute_section_visibility(voxels, section))
+}
+
+/// Flood fills the air in a section, recording which faces each pocket of air touches
+pub fn compute_section_visibility(voxels: &VoxelData, section: usize) -> SectionVisibility {
+    const S: usize = SECTION_SIZE;
+    let data = voxels.data();
+    let base_y = section * S;
+    let is_air = |x: usize, y: usize, z: usize| matches!(data[z][base_y + y][x], BlockType::Air);
+
+    let mut visibility = SectionVisibility::default();
+    let mut visited = vec![false; S * S * S];
+    let mut stack = Vec::new();
+
+    for start_z in 0..S {
+        for start_y in 0..S {
+            for start_x in 0..S {
+                let start = (start_z * S + start_y) * S + start_x;
+                if visited[start] || !is_air(start_x, start_y, start_z) {
+                    continue;
+                }
+
+                let mut faces = 0;
+                visited[start] = true;
+                stack.push((start_x, start_y, start_z));
+
+                while let Some((x, y, z)) = stack.pop() {
+                    for (at_edge, direction) in [
+                        (x == S - 1, Direction::PosX),
+                        (x == 0, Direction::NegX),
+                        (y == S - 1, Direction::PosY),
+                        (y == 0, Direction::NegY),
+                        (z == S - 1, Direction::PosZ),
+                        (z == 0, Direction::NegZ),
+                    ] {
+                        if at_edge {
+                            faces |= direction.bit();
+                            continue;
+                        }
+
+                        let (n_x, n_y, n_z) = direction.step((x as i32, y as i32, z as i32));
+                        let (n_x, n_y, n_z) = (n_x as usize, n_y as usize, n_z as usize);
+                        let neighbour = (n_z * S + n_y) * S + n_x;
+                        if !visited[neighbour] && is_air(n_x, n_y, n_z) {
+                            visited[neighbour] = true;
+                            stack.push((n_x, n_y, n_z));
+                        }
+                    }
+                }
+
+                visibility.connect_all(faces);
+            }
+        }
+    }
+
+    visibility
+}
+
+/// Walks outwards from the camera's section, only stepping through faces that can see each other
+/// and never doubling back on a direction already taken. Sections sealed off from the camera are
+/// never reached. `visibility` returns `None` for sections that aren't loaded
+pub fn find_visible_sections(
+    start: SectionPos,
+    visibility: impl Fn(SectionPos) -> Option<SectionVisibility>,
+    in_view: impl Fn(SectionPos) -> bool,
+) -> Vec<SectionPos> {
+    let mut visible = Vec::new();
+    if visibility(start).is_none() {
+        return visible;
+    }
+
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, None::<Direction>, 0u8)]);
+
+    while let Some((pos, entered_from, travelled)) = queue.pop_front() {
+        visible.push(pos);
+        let Some(section) = visibility(pos) else {
+            continue;
+        };
+
+        for direction in Direction::ALL {
+            if travelled & direction.opposite().bit() != 0 {
+                continue;
+            }
+            if entered_from.is_some_and(|from| !section.connects(from, direction)) {
+                continue;
+            }
+
+            let next = direction.step(pos);
+            if next.1 < 0 || next.1 >= SECTION_COUNT as i32 || visited.contains(&next) {
+                continue;
+            }
+            if visibility(next).is_none() || !in_view(next) {
+                continue;
+            }
+
+            visited.insert(next);
+            queue.push_back((
+                next,
+                Some(direction.opposite()),
+                travelled | direction.bit(),
+            ));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Chunk of `block` with `carve` turned to air, positions are within the first section
+    fn chunk(block: BlockType, carve: impl Fn(usize, usize, usize) -> bool) -> VoxelData {
+        let mut voxels = Box::new([[[block; 16]; 256]; 16]);
+        for (z, plane) in voxels.iter_mut().enumerate() {
+            for (y, row) in plane.iter_mut().take(SECTION_SIZE).enumerate() {
+                for (x, voxel) in row.iter_mut().enumerate() {
+                    if carve(x, y, z) {
+                        *voxel = BlockType::Air;
+                    }
+                }
+            }
+        }
+        VoxelData::new(voxels)
+    }
+
+    fn connected_pairs(visibility: SectionVisibility) -> Vec<(Direction, Direction)> {
+        Direction::ALL
+            .into_iter()
+            .flat_map(|from| Direction::ALL.map(|to| (from, to)))
+            .filter(|&(from, to)| visibility.connects(from, to))
+            .collect()
+    }
+
+    #[test]
+    fn solid_section_connects_no_faces() {
+        let voxels = chunk(BlockType::Solid, |_, _, _| false);
+        assert_eq!(
+            compute_section_visibility(&voxels, 0),
+            SectionVisibility::default()
+        );
+    }
+
+    #[test]
+    fn air_section_connects_every_face() {
+        let voxels = chunk(BlockType::Air, |_, _, _| false);
+        let visibility = compute_section_visibility(&voxels, 0);
+        assert_eq!(connected_pairs(visibility).len(), 36);
+    }
+
+    #[test]
+    fn sealed_cave_connects_no_faces() {
+        let inside = |i: usize| (4..12).contains(&i);
+        let voxels = chunk(BlockType::Solid, |x, y, z| {
+            inside(x) && inside(y) && inside(z)
+        });
+        assert_eq!(
+            compute_section_visibility(&voxels, 0),
+            SectionVisibility::default()
+        );
+    }
+
+    #[test]
+    fn tunnel_connects_only_its_ends() {
+        let voxels = chunk(BlockType::Solid, |_, y, z| y == 8 && z == 8);
+        let visibility = compute_section_visibility(&voxels, 0);
+        assert_eq!(
+            connected_pairs(visibility),
+            [
+                (Direction::PosX, Direction::PosX),
+                (Direction::PosX, Direction::NegX),
+                (Direction::NegX, Direction::PosX),
+                (Direction::NegX, Direction::NegX),
+            ]
+        );
+    }
+
+    #[test]
+    fn separate_pockets_do_not_connect() {
+        // Air along the bottom touching -x, and along the top touching +x
+        let voxels = chunk(BlockType::Solid, |x, y, z| {
+            z == 8 && ((y == 2 && x < 8) || (y == 13 && x >= 8))
+        });
+        let visibility = compute_section_visibility(&voxels, 0);
+        assert!(visibility.connects(Direction::NegX, Direction::NegX));
+        assert!(visibility.connects(Direction::PosX, Direction::PosX));
+        assert!(!visibility.connects(Direction::NegX, Direction::PosX));
+    }
+
+    #[test]
+    fn sections_are_computed_independently() {
+        // Only the first section has any air
+        let voxels = chunk(BlockType::Solid, |_, _, _| true);
+        let visibility = compute_chunk_visibility(&voxels);
+        assert_eq!(connected_pairs(visibility[0]).len(), 36);
+        assert!(
+            visibility[1..]
+                .iter()
+                .all(|&section| section == SectionVisibility::default())
+        );
+    }
+
+    /// Loaded chunks from -3 to 3 on both axes, sections in chunks with `x == wall_x` are solid
+    fn world(wall_x: Option<i32>) -> HashMap<SectionPos, SectionVisibility> {
+        let air = compute_section_visibility(&chunk(BlockType::Air, |_, _, _| false), 0);
+        let solid = compute_section_visibility(&chunk(BlockType::Solid, |_, _, _| false), 0);
+
+        let mut sections = HashMap::new();
+        for x in -3..=3 {
+            for z in -3..=3 {
+                for y in 0..SECTION_COUNT as i32 {
+                    let visibility = if Some(x) == wall_x { solid } else { air };
+                    sections.insert((x, y, z), visibility);
+                }
+            }
+        }
+        sections
+    }
+
+    #[test]
+    fn open_world_reaches_every_section() {
+        let sections = world(None);
+        let visible = find_visible_sections((0, 4, 0), |pos| sections.get(&pos).copied(), |_| true);
+
+        assert_eq!(visible.len(), sections.len());
+        assert_eq!(visible.iter().collect::<HashSet<_>>().len(), visible.len());
+    }
+
+    #[test]
+    fn sections_behind_a_wall_are_not_reached() {
+        let sections = world(Some(2));
+        let visible = find_visible_sections((0, 4, 0), |pos| sections.get(&pos).copied(), |_| true);
+
+        // The wall itself is seen, but nothing past it
+        assert!(visible.contains(&(2, 4, 0)));
+        assert!(visible.iter().all(|&(x, _, _)| x <= 2));
+        assert!(visible.contains(&(-3, 4, -3)));
+    }
+
+    #[test]
+    fn sections_out_of_view_are_not_reached() {
+        let sections = world(None);
+        let visible = find_visible_sections(
+            (0, 4, 0),
+            |pos| sections.get(&pos).copied(),
+            |(x, _, _)| x >= 0,
+        );
+
+        assert!(!visible.is_empty());
+        assert!(visible.iter().all(|&(x, _, _)| x >= 0));
+    }
+
+    #[test]
+    fn unloaded_start_finds_nothing() {
+        let sections = world(None);
+        let visible =
+            find_visible_sections((10, 4, 10), |pos| sections.get(&pos).copied(), |_| true);
+        assert!(visible.is_empty());
+    }
+}
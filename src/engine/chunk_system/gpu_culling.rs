@@ -0,0 +1,288 @@
+use crate::engine::chunk_system::chunk_loader::ChunkMesh;
+use crate::engine::gpu::{GpuCtx, ShaderFile, shader_file};
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use crate::engine::render_system::FrameContext;
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt, DrawIndexedIndirectArgs};
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, PipelineCompilationOptions, PipelineLayoutDescriptor, RenderPass,
+    SamplerBindingType, ShaderModule, ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+const CULL_SHADER: ShaderFile = shader_file!("src/engine/chunk_system/chunk_cull.wgsl");
+
+const WORKGROUP_SIZE: u32 = 64;
+const DRAW_ARGS_SIZE: u64 = size_of::<DrawIndexedIndirectArgs>() as u64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct CullParams {
+    view_proj: [[f32; 4]; 4],
+    planes: [[f32; 4]; 6],
+    viewport: [f32; 2],
+    chunk_count: u32,
+    hi_z_mip_count: u32,
+    reverse_z: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ChunkBounds {
+    min: [f32; 3],
+    index_count: u32,
+    max: [f32; 3],
+    first_index: u32,
+    base_vertex: i32,
+    _padding: [u32; 3],
+}
+
+/// Culls chunks in a compute pass that writes one indirect draw per chunk,
+/// the CPU only uploads chunk bounds when the set of meshes changes
+pub struct GpuChunkCuller {
+    pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    params_buffer: Buffer,
+    chunk_buffer: Buffer,
+    draw_buffer: Buffer,
+    capacity: u64,
+    chunk_count: u32,
+    multi_draw: bool,
+}
+
+impl GpuChunkCuller {
+    pub fn new(gpu_ctx: &GpuCtx, multi_draw: bool) -> Self {
+        let shader = CULL_SHADER.create_module(gpu_ctx);
+        let bind_group_layout = create_bind_group_layout(gpu_ctx);
+        let pipeline = create_cull_pipeline(gpu_ctx, &bind_group_layout, &shader);
+
+        let params_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[CullParams::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let capacity = 1024;
+        let (chunk_buffer, draw_buffer) = create_chunk_buffers(gpu_ctx, capacity);
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            params_buffer,
+            chunk_buffer,
+            draw_buffer,
+            capacity,
+            chunk_count: 0,
+            multi_draw,
+        }
+    }
+
+    /// Builds the pipeline from the shader on disk without touching the current one,
+    /// the returned function swaps it in
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &self,
+        gpu_ctx: &GpuCtx,
+    ) -> Result<impl FnOnce(&mut Self) + use<>, ShaderError> {
+        let pipeline = validate(gpu_ctx, || {
+            let shader = CULL_SHADER.reload_module(gpu_ctx)?;
+            Ok(create_cull_pipeline(
+                gpu_ctx,
+                &self.bind_group_layout,
+                &shader,
+            ))
+        })?;
+        Ok(move |culler: &mut Self| culler.pipeline = pipeline)
+    }
+
+    pub fn update_chunks(&mut self, gpu_ctx: &GpuCtx, meshes: &[&ChunkMesh]) {
+        let chunks: Vec<_> = meshes
+            .iter()
+            .map(|chunk_mesh| {
+                let indices = chunk_mesh.mesh.get_index_range();
+                ChunkBounds {
+                    min: chunk_mesh.bounds.min.into(),
+                    index_count: indices.end - indices.start,
+                    max: chunk_mesh.bounds.max.into(),
+                    first_index: indices.start,
+                    base_vertex: chunk_mesh.mesh.get_base_vertex() as i32,
+                    _padding: [0; 3],
+                }
+            })
+            .collect();
+
+        if chunks.len() as u64 > self.capacity {
+            self.capacity = (chunks.len() as u64).next_power_of_two();
+            (self.chunk_buffer, self.draw_buffer) = create_chunk_buffers(gpu_ctx, self.capacity);
+        }
+
+        if !chunks.is_empty() {
+            gpu_ctx
+                .queue
+                .write_buffer(&self.chunk_buffer, 0, bytemuck::cast_slice(&chunks));
+        }
+        self.chunk_count = chunks.len() as u32;
+    }
+
+    pub fn dispatch(&self, encoder: &mut CommandEncoder, frame: &FrameContext, occlusion: bool) {
+        if self.chunk_count == 0 {
+            return;
+        }
+
+        let params = CullParams {
+            view_proj: frame.view_proj.into(),
+            planes: frame.frustum.planes().map(Into::into),
+            viewport: [frame.viewport.0 as f32, frame.viewport.1 as f32],
+            chunk_count: self.chunk_count,
+            hi_z_mip_count: if occlusion {
+                frame.hi_z.mip_level_count()
+            } else {
+                0
+            },
+            reverse_z: frame.depth_mode.is_reversed().into(),
+            _padding: [0; 3],
+        };
+        frame
+            .gpu_ctx
+            .queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        // The Hi-Z view is recreated on resize so the bind group is rebuilt every frame
+        let bind_group = frame
+            .gpu_ctx
+            .device
+            .create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.params_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: self.chunk_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: self.draw_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(frame.hi_z.view()),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::Sampler(frame.hi_z.sampler()),
+                    },
+                ],
+            });
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(self.chunk_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Expects the chunk pipeline and arena buffers to already be bound
+    pub fn draw(&self, pass: &mut RenderPass) {
+        if self.chunk_count == 0 {
+            return;
+        }
+
+        if self.multi_draw {
+            pass.multi_draw_indexed_indirect(&self.draw_buffer, 0, self.chunk_count);
+        } else {
+            for i in 0..self.chunk_count as u64 {
+                pass.draw_indexed_indirect(&self.draw_buffer, i * DRAW_ARGS_SIZE);
+            }
+        }
+    }
+}
+
+fn create_cull_pipeline(
+    gpu_ctx: &GpuCtx,
+    bind_group_layout: &BindGroupLayout,
+    shader: &ShaderModule,
+) -> ComputePipeline {
+    let layout = gpu_ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+    gpu_ctx
+        .device
+        .create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            module: shader,
+            entry_point: Some("cull"),
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        })
+}
+
+fn create_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    let buffer = |binding, ty| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                buffer(0, BufferBindingType::Uniform),
+                buffer(1, BufferBindingType::Storage { read_only: true }),
+                buffer(2, BufferBindingType::Storage { read_only: false }),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        })
+}
+
+fn create_chunk_buffers(gpu_ctx: &GpuCtx, capacity: u64) -> (Buffer, Buffer) {
+    let chunk_buffer = gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: capacity * size_of::<ChunkBounds>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let draw_buffer = gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: capacity * DRAW_ARGS_SIZE,
+        usage: BufferUsages::STORAGE | BufferUsages::INDIRECT,
+        mapped_at_creation: false,
+    });
+    (chunk_buffer, draw_buffer)
+}
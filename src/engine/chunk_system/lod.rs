@@ -0,0 +1,29 @@
+/// Chunks further than these many chunks from the loading center are meshed at the next level of detail
+const LOD_DISTANCES: [i32; 3] = [16, 32, 64];
+
+/// Vertices of a chunk of generated terrain at each level, measured on the default world
+const TYPICAL_VERTEX_COUNTS: [u64; LOD_DISTANCES.len() + 1] = [2453, 1623, 400, 97];
+
+/// How many times a chunk's voxels are halved in resolution before meshing,
+/// level 0 is full resolution and every level after merges 2x2x2 cubes into one
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Lod(u8);
+
+impl Lod {
+    pub fn for_distance_squared(distance_squared: i32) -> Self {
+        let level = LOD_DISTANCES
+            .iter()
+            .take_while(|distance| distance_squared > *distance * *distance)
+            .count();
+        Self(level as u8)
+    }
+
+    /// Width of a downsampled cube in blocks
+    pub fn scale(self) -> usize {
+        1 << self.0
+    }
+
+    pub fn typical_vertex_count(self) -> u64 {
+        TYPICAL_VERTEX_COUNTS[self.0 as usize]
+    }
+}
@@ -1,147 +1,717 @@
 use std::collections::HashSet;
+use crate::engine::chunk_system::block_textures::{BlockTextures, create_block_texture_bind_group_layout};
 use crate::engine::chunk_system::chunk_vertex::ChunkVertex;
-use crate::engine::gpu::{GpuCtx, GpuMesh, Vertex};
-use crate::engine::render_system::Renderable;
+use crate::engine::chunk_system::connectivity::{SECTION_COUNT, SECTION_SIZE, SectionPos, find_visible_sections};
+use crate::engine::gpu::{Aabb, DepthMode, Frustum, GpuCtx, GpuMesh, IndirectDraws, MeshArenaStats, ShaderFile, Vertex, shader_file};
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use crate::engine::chunk_system::gpu_culling::GpuChunkCuller;
+use crate::engine::chunk_system::lod::Lod;
+use crate::engine::render_system::{FrameContext, HDR_FORMAT, PassName, Renderable, create_cascade_bind_group_layout, create_environment_bind_group_layout, create_shadow_bind_group_layout};
+use cgmath::{Point3, Vector3};
 use std::sync::Arc;
-use wgpu::{AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState, BufferBindingType, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Extent3d, Face, FilterMode, FragmentState, FrontFace, IndexFormat, MultisampleState, Origin3d, PipelineCompilationOptions, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPass, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, StencilState, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexState};
-pub use chunk_loader::ChunkLoader;
+use wgpu::{BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState, BufferBindingType, CompareFunction, ColorTargetState, CommandEncoder, ColorWrites, DepthBiasState, DepthStencilState, Face, Features, FragmentState, FrontFace, IndexFormat, MultisampleState, PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderStages, StencilState, TextureFormat, VertexState};
+pub use chunk_loader::{ChunkLoadStats, ChunkLoader};
+pub use resource_packs::{BlockFaces, BlockResources, built_in_resources, load_resource_packs};
+#[cfg(feature = "hot-reload")]
+pub use resource_packs::DEFAULT_PACK_DIR;
 pub use threaded_chunk_loader::ThreadedChunkLoader;
+pub use voxel_data::BlockType;
 
+mod block_textures;
 mod chunk_loader;
 mod chunk_vertex;
+mod connectivity;
+mod gpu_culling;
+mod lod;
+mod resource_packs;
 mod threaded_chunk_loader;
 mod voxel_data;
 
-const TEXTURE_ATLAS_BYTES: &[u8] = include_bytes!("texture_atlas.png");
+/// How many chunks past the load radius a chunk has to be before it is unloaded,
+/// this stops chunks on the border from being regenerated when walking back and forth
+const UNLOAD_HYSTERESIS: i32 = 2;
+const MIN_RENDER_DISTANCE: i32 = 2;
+const DEFAULT_RENDER_DISTANCE: i32 = 16;
+/// Distant chunks keep a few hundred bytes of voxels, but still around 4 KiB of geometry each.
+/// At this radius that's over a gigabyte of mesh arena, devices with smaller buffers get less,
+/// see `max_render_distance`
+const MAX_RENDER_DISTANCE: i32 = 256;
+/// Room on top of the typical geometry for rougher terrain, fragmentation and new meshes being
+/// uploaded before the ones they replace are released
+const ARENA_HEADROOM: f64 = 1.5;
+const DEFAULT_ANISOTROPY: u16 = 16;
+
+const CHUNK_SHADER: ShaderFile = shader_file!("src/engine/chunk_system/chunk_shader.wgsl");
+const CHUNK_SHADOW_SHADER: ShaderFile = shader_file!("src/engine/chunk_system/chunk_shadow.wgsl");
+const CHUNK_DEBUG_SHADER: ShaderFile = shader_file!("src/engine/chunk_system/chunk_debug.wgsl");
+
+/// Two per line of the grid, see chunk_debug.wgsl
+const CHUNK_BORDER_VERTICES: u32 = 2 * (6 * 6 + 17 * 2 * 6);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CullMode {
+    /// Frustum culled on the CPU every frame
+    Cpu,
+    /// Frustum culled on the CPU, skipping sections the camera can't see into through caves
+    CpuConnectivity,
+    /// Frustum culled in a compute pass
+    Gpu,
+    /// Frustum and Hi-Z occlusion culled in a compute pass
+    GpuOcclusion,
+}
+
+impl CullMode {
+    /// Without multi-draw the GPU culled draws are issued one per chunk, hidden chunks included
+    fn default_for(multi_draw: bool) -> Self {
+        if multi_draw {
+            CullMode::Gpu
+        } else {
+            CullMode::Cpu
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            CullMode::Cpu => CullMode::CpuConnectivity,
+            CullMode::CpuConnectivity => CullMode::Gpu,
+            CullMode::Gpu => CullMode::GpuOcclusion,
+            CullMode::GpuOcclusion => CullMode::Cpu,
+        }
+    }
+
+    fn is_cpu(self) -> bool {
+        matches!(self, CullMode::Cpu | CullMode::CpuConnectivity)
+    }
+}
+
+/// Alternate ways of drawing the chunks, for looking at what the renderer is doing
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DebugRenderMode {
+    Off,
+    /// Triangle edges only, faces behind others show through
+    Wireframe,
+    /// World space normals as colors
+    Normals,
+    /// Normal shading multiplied by a random color for each chunk
+    ChunkTint,
+    /// Every face drawn without depth testing, brighter where more of them cover a pixel
+    Overdraw,
+    /// Normal shading with the chunk grid around the camera drawn over it
+    ChunkBorders,
+}
+
+impl DebugRenderMode {
+    #[cfg(feature = "hot-reload")]
+    const ALL: [Self; 6] = [
+        DebugRenderMode::Off,
+        DebugRenderMode::Wireframe,
+        DebugRenderMode::Normals,
+        DebugRenderMode::ChunkTint,
+        DebugRenderMode::Overdraw,
+        DebugRenderMode::ChunkBorders,
+    ];
+
+    pub fn next(self) -> Self {
+        match self {
+            DebugRenderMode::Off => DebugRenderMode::Wireframe,
+            DebugRenderMode::Wireframe => DebugRenderMode::Normals,
+            DebugRenderMode::Normals => DebugRenderMode::ChunkTint,
+            DebugRenderMode::ChunkTint => DebugRenderMode::Overdraw,
+            DebugRenderMode::Overdraw => DebugRenderMode::ChunkBorders,
+            DebugRenderMode::ChunkBorders => DebugRenderMode::Off,
+        }
+    }
+}
+
+/// Counted in chunks, or in sections when culling by connectivity
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
 
 pub struct ChunkSystem<L: ChunkLoader> {
     chunk_loading_center: (i32, i32),
     chunk_loading_radius: i32,
+    chunk_unloading_radius: i32,
+    /// The most chunks the mesh arena is expected to hold
+    max_render_distance: i32,
+    loaded_chunks: HashSet<(i32, i32)>,
     loader: L,
+    gpu_ctx: Arc<GpuCtx>,
+    /// Only present when the device supports `multi_draw_indexed_indirect`
+    indirect_draws: Option<IndirectDraws>,
+    visible_meshes: Vec<GpuMesh>,
+    cull_stats: CullStats,
+    cull_mode: CullMode,
+    gpu_culler: GpuChunkCuller,
+    /// Mesh revision the GPU culler's chunk list was last built from
+    gpu_culler_revision: Option<u64>,
 
+    depth_mode: DepthMode,
+    sample_count: u32,
+    debug_render_mode: DebugRenderMode,
+    /// Kept so the render pipelines can be rebuilt when the depth mode, sample count or debug
+    /// render mode changes
+    chunk_shader: ShaderModule,
+    chunk_debug_shader: ShaderModule,
     chunk_render_pipeline: RenderPipeline,
-    texture_atlas: Texture,
-    texture_atlas_view: TextureView,
-    texture_atlas_sampler: Sampler,
-    texture_atlas_bind_group: BindGroup
+    chunk_shadow_pipeline: RenderPipeline,
+    chunk_border_pipeline: RenderPipeline,
+    overdraw_background_pipeline: RenderPipeline,
+    block_textures: BlockTextures
 }
 
 impl<L: ChunkLoader> ChunkSystem<L> {
-    pub fn new(gpu_ctx: Arc<GpuCtx>, loader: L) -> Self {
-        let chunk_render_pipeline = create_chunk_render_pipeline(&gpu_ctx);
-        let (texture_atlas, texture_atlas_view, texture_atlas_sampler) = create_texture_atlas(&gpu_ctx);
-        let texture_atlas_bind_group = gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
-            label: None,
-            layout: &gpu_ctx.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float {
-                                filterable: true
-                            },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false
-                        },
-                        count: None
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None
-                    }
-                ]
-            }),
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&texture_atlas_view),
-            }, BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::Sampler(&texture_atlas_sampler),
-            }
-            ]
-        });
+    pub fn new(
+        gpu_ctx: Arc<GpuCtx>,
+        loader: L,
+        depth_mode: DepthMode,
+        sample_count: u32,
+        block_resources: &BlockResources,
+    ) -> Self {
+        let chunk_shader = CHUNK_SHADER.create_module(&gpu_ctx);
+        let chunk_debug_shader = CHUNK_DEBUG_SHADER.create_module(&gpu_ctx);
+        let debug_render_mode = DebugRenderMode::Off;
+        let chunk_render_pipeline = create_chunk_render_pipeline(
+            &gpu_ctx,
+            depth_mode,
+            sample_count,
+            debug_render_mode,
+            &chunk_shader,
+        );
+        let chunk_border_pipeline =
+            create_chunk_border_pipeline(&gpu_ctx, depth_mode, sample_count, &chunk_debug_shader);
+        let overdraw_background_pipeline =
+            create_overdraw_background_pipeline(&gpu_ctx, sample_count, &chunk_debug_shader);
+        let chunk_shadow_pipeline =
+            create_chunk_shadow_pipeline(&gpu_ctx, &CHUNK_SHADOW_SHADER.create_module(&gpu_ctx));
+        let block_textures = BlockTextures::new(
+            &gpu_ctx,
+            &block_resources.textures,
+            &block_resources.emissive,
+            DEFAULT_ANISOTROPY,
+        );
+
+        let multi_draw = gpu_ctx
+            .device
+            .features()
+            .contains(Features::MULTI_DRAW_INDIRECT);
+        let indirect_draws = multi_draw.then(|| IndirectDraws::new(&gpu_ctx, 1024));
+        let gpu_culler = GpuChunkCuller::new(&gpu_ctx, multi_draw);
+
+        let max_render_distance = max_render_distance(gpu_ctx.device.limits().max_buffer_size);
+        let (chunk_loading_radius, chunk_unloading_radius) =
+            loading_radii(DEFAULT_RENDER_DISTANCE, max_render_distance);
 
         let mut system = Self {
             chunk_loading_center: (0, 0),
-            chunk_loading_radius: 16,
+            chunk_loading_radius,
+            chunk_unloading_radius,
+            max_render_distance,
+            loaded_chunks: HashSet::new(),
 
             loader,
+            gpu_ctx,
+            indirect_draws,
+            visible_meshes: Vec::new(),
+            cull_stats: CullStats::default(),
+            cull_mode: CullMode::default_for(multi_draw),
+            gpu_culler,
+            gpu_culler_revision: None,
+            depth_mode,
+            sample_count,
+            debug_render_mode,
+            chunk_shader,
+            chunk_debug_shader,
             chunk_render_pipeline,
-            texture_atlas,
-            texture_atlas_view,
-            texture_atlas_sampler,
-            texture_atlas_bind_group
+            chunk_shadow_pipeline,
+            chunk_border_pipeline,
+            overdraw_background_pipeline,
+            block_textures
         };
 
-        system.load_chunks(system.get_chunks_to_load(system.chunk_loading_center));
+        system.update_loaded_chunks((0, 0));
         system
     }
 
     pub fn player_moved(&mut self, p_x: i32, p_z: i32) {
-        let new_c_x = p_x / 16;
-        let new_c_z = p_z / 16;
+        let new_c_x = p_x.div_euclid(16);
+        let new_c_z = p_z.div_euclid(16);
         let new_chunk_loading_center = (new_c_x, new_c_z);
 
         if new_chunk_loading_center != self.chunk_loading_center {
-            let old_chunks = self.get_chunks_to_load(self.chunk_loading_center);
+            let previous_center = self.chunk_loading_center;
             self.chunk_loading_center = new_chunk_loading_center;
-            let new_chunks = self.get_chunks_to_load(self.chunk_loading_center);
-            let chunks_to_remove = old_chunks.difference(&new_chunks).copied();
-            let chunks_to_load = new_chunks.difference(&old_chunks).copied();
-            self.unload_chunks(chunks_to_remove);
-            self.load_chunks(chunks_to_load);
+            self.update_loaded_chunks(previous_center);
+        }
+    }
+
+    pub fn get_render_distance(&self) -> i32 {
+        self.chunk_loading_radius
+    }
+
+    pub fn set_render_distance(&mut self, radius: i32) {
+        let (loading_radius, unloading_radius) = loading_radii(radius, self.max_render_distance);
+        if loading_radius != self.chunk_loading_radius {
+            self.chunk_loading_radius = loading_radius;
+            self.chunk_unloading_radius = unloading_radius;
+            self.update_loaded_chunks(self.chunk_loading_center);
         }
     }
 
     pub fn unload_chunks(&mut self, chunks_to_remove: impl IntoIterator<Item = (i32, i32)>) {
         for pos in chunks_to_remove {
-            self.loader.queue_unload_chunk(pos);
+            if self.loaded_chunks.remove(&pos) {
+                self.loader.queue_unload_chunk(pos);
+            }
         }
     }
 
     pub fn load_chunks(&mut self, chunks_to_load: impl IntoIterator<Item = (i32, i32)>) {
         for pos in chunks_to_load {
-            self.loader.queue_load_chunk(pos);
+            if self.loaded_chunks.insert(pos) {
+                let lod = Lod::for_distance_squared(distance_squared(self.chunk_loading_center, pos));
+                self.loader.queue_load_chunk(pos, lod);
+            }
         }
     }
 
-    pub fn get_chunk_meshes(&self) -> Vec<&GpuMesh> {
-        self.loader.get_meshes()
+    /// Furthest a loaded block can be from a camera inside the loading center chunk,
+    /// chunks waiting to be unloaded are included since they keep being drawn
+    pub fn get_view_distance(&self) -> f32 {
+        let horizontal = ((self.chunk_unloading_radius + 2) * 16) as f32;
+        horizontal.hypot(256.0)
+    }
+
+    /// Horizontal distance to the edge of the loaded area, terrain should be fully fogged by here
+    pub fn get_fog_distance(&self) -> f32 {
+        (self.chunk_loading_radius * 16) as f32
+    }
+
+    /// Every chunk in render distance has its mesh uploaded
+    pub fn is_idle(&self) -> bool {
+        self.loader.is_idle()
+    }
+
+    pub fn get_load_stats(&self) -> ChunkLoadStats {
+        self.loader.get_load_stats()
+    }
+
+    pub fn get_geometry_stats(&self) -> MeshArenaStats {
+        self.loader.get_mesh_arena().stats()
+    }
+
+    /// Only known when culling on the CPU, the GPU culler never reads its results back
+    pub fn get_cull_stats(&self) -> Option<CullStats> {
+        self.cull_mode.is_cpu().then_some(self.cull_stats)
+    }
+
+    pub fn get_cull_mode(&self) -> CullMode {
+        self.cull_mode
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: CullMode) {
+        self.cull_mode = cull_mode;
+    }
+
+    pub fn get_anisotropy(&self) -> u16 {
+        self.block_textures.get_anisotropy()
+    }
+
+    pub fn set_anisotropy(&mut self, anisotropy: u16) {
+        self.block_textures.set_anisotropy(&self.gpu_ctx, anisotropy);
+    }
+
+    /// Must match the depth mode the render system draws with
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+        self.rebuild_render_pipelines();
+    }
+
+    /// Must match the render system's MSAA sample count
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.rebuild_render_pipelines();
+    }
+
+    pub fn get_debug_render_mode(&self) -> DebugRenderMode {
+        self.debug_render_mode
+    }
+
+    pub fn set_debug_render_mode(&mut self, debug_render_mode: DebugRenderMode) {
+        self.debug_render_mode = debug_render_mode;
+        self.chunk_render_pipeline = create_chunk_render_pipeline(
+            &self.gpu_ctx,
+            self.depth_mode,
+            self.sample_count,
+            debug_render_mode,
+            &self.chunk_shader,
+        );
+    }
+
+    fn rebuild_render_pipelines(&mut self) {
+        let gpu_ctx = &self.gpu_ctx;
+        self.chunk_render_pipeline = create_chunk_render_pipeline(
+            gpu_ctx,
+            self.depth_mode,
+            self.sample_count,
+            self.debug_render_mode,
+            &self.chunk_shader,
+        );
+        self.chunk_border_pipeline = create_chunk_border_pipeline(
+            gpu_ctx,
+            self.depth_mode,
+            self.sample_count,
+            &self.chunk_debug_shader,
+        );
+        self.overdraw_background_pipeline = create_overdraw_background_pipeline(
+            gpu_ctx,
+            self.sample_count,
+            &self.chunk_debug_shader,
+        );
+    }
+
+    /// Builds every pipeline from the shaders on disk without touching the current ones, the
+    /// returned function swaps them in. On any error nothing is swapped
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&self) -> Result<impl FnOnce(&mut Self) + use<L>, ShaderError> {
+        let gpu_ctx = &self.gpu_ctx;
+        let (chunk_shader, chunk_debug_shader, chunk_shadow_pipeline) = validate(gpu_ctx, || {
+            let chunk_shader = CHUNK_SHADER.reload_module(gpu_ctx)?;
+            let chunk_debug_shader = CHUNK_DEBUG_SHADER.reload_module(gpu_ctx)?;
+            let chunk_shadow_shader = CHUNK_SHADOW_SHADER.reload_module(gpu_ctx)?;
+            // Every debug render mode is built so a broken entry point shows up before switching to it
+            for debug_render_mode in DebugRenderMode::ALL {
+                create_chunk_render_pipeline(
+                    gpu_ctx,
+                    self.depth_mode,
+                    self.sample_count,
+                    debug_render_mode,
+                    &chunk_shader,
+                );
+            }
+            create_chunk_border_pipeline(
+                gpu_ctx,
+                self.depth_mode,
+                self.sample_count,
+                &chunk_debug_shader,
+            );
+            create_overdraw_background_pipeline(gpu_ctx, self.sample_count, &chunk_debug_shader);
+            let chunk_shadow_pipeline = create_chunk_shadow_pipeline(gpu_ctx, &chunk_shadow_shader);
+            Ok((chunk_shader, chunk_debug_shader, chunk_shadow_pipeline))
+        })?;
+        let apply_culler = self.gpu_culler.reload_shader(gpu_ctx)?;
+
+        Ok(move |system: &mut Self| {
+            system.chunk_shader = chunk_shader;
+            system.chunk_debug_shader = chunk_debug_shader;
+            system.chunk_shadow_pipeline = chunk_shadow_pipeline;
+            apply_culler(&mut system.gpu_culler);
+            system.rebuild_render_pipelines();
+        })
+    }
+
+    /// Uploads the new block textures and remeshes every chunk, since texture layers may have moved
+    #[cfg(feature = "hot-reload")]
+    pub fn set_block_resources(&mut self, block_resources: &BlockResources) {
+        let anisotropy = self.block_textures.get_anisotropy();
+        self.block_textures = BlockTextures::new(
+            &self.gpu_ctx,
+            &block_resources.textures,
+            &block_resources.emissive,
+            anisotropy,
+        );
+        self.loader.set_block_faces(block_resources.faces.clone());
     }
 
     pub fn handle_chunk_jobs(&mut self) {
         self.loader.process_chunks();
+
+        let revision = self.loader.get_mesh_revision();
+        if self.gpu_culler_revision != Some(revision) {
+            self.gpu_culler
+                .update_chunks(&self.gpu_ctx, &self.loader.get_meshes());
+            self.gpu_culler_revision = Some(revision);
+        }
+    }
+
+    /// Picks the chunks inside the camera's frustum, only those get drawn on the next render.
+    /// Does nothing when culling on the GPU
+    pub fn cull_chunks(&mut self, frustum: &Frustum, camera_pos: (f32, f32, f32)) {
+        match self.cull_mode {
+            CullMode::Cpu => self.cull_chunks_by_frustum(frustum),
+            CullMode::CpuConnectivity => self.cull_chunks_by_connectivity(frustum, camera_pos),
+            CullMode::Gpu | CullMode::GpuOcclusion => return,
+        }
+
+        if let Some(indirect_draws) = self.indirect_draws.as_mut() {
+            indirect_draws.write(&self.gpu_ctx, &self.visible_meshes);
+        }
     }
 
-    fn get_chunks_to_load(&self, (center_x, center_z): (i32, i32)) -> HashSet<(i32, i32)> {
-        (-self.chunk_loading_radius..self.chunk_loading_radius)
-            .flat_map(|y| {
-                (-self.chunk_loading_radius..self.chunk_loading_radius)
-                    .map(move |x| (x + center_x, y + center_z))
-            }).collect()
+    fn cull_chunks_by_frustum(&mut self, frustum: &Frustum) {
+        let meshes = self.loader.get_meshes();
+        let total = meshes.len();
+
+        self.visible_meshes.clear();
+        self.visible_meshes.extend(
+            meshes
+                .into_iter()
+                .filter(|chunk_mesh| frustum.intersects_aabb(&chunk_mesh.bounds))
+                .map(|chunk_mesh| chunk_mesh.mesh),
+        );
+
+        self.cull_stats = CullStats {
+            drawn: self.visible_meshes.len(),
+            culled: total - self.visible_meshes.len(),
+        };
+    }
+
+    /// Walks the section connectivity graph out from the camera, so caves sealed off from it are never drawn
+    fn cull_chunks_by_connectivity(&mut self, frustum: &Frustum, (cam_x, cam_y, cam_z): (f32, f32, f32)) {
+        let start = (
+            (cam_x.floor() as i32).div_euclid(16),
+            (cam_y.floor() as i32).div_euclid(SECTION_SIZE as i32).clamp(0, SECTION_COUNT as i32 - 1),
+            (cam_z.floor() as i32).div_euclid(16),
+        );
+
+        // Nothing to walk from until the camera's own chunk has been meshed
+        if self.loader.get_visibility((start.0, start.2)).is_none() {
+            self.cull_chunks_by_frustum(frustum);
+            return;
+        }
+
+        let loader = &self.loader;
+        let visible_sections = find_visible_sections(
+            start,
+            |(x, y, z)| loader.get_visibility((x, z)).map(|sections| sections[y as usize]),
+            |pos| frustum.intersects_aabb(&section_bounds(pos)),
+        );
+
+        self.visible_meshes.clear();
+        for (x, y, z) in visible_sections {
+            if let Some(chunk_mesh) = loader.get_mesh((x, z)) {
+                let indices = chunk_mesh.sections[y as usize].clone();
+                if !indices.is_empty() {
+                    self.visible_meshes.push(chunk_mesh.mesh.sub_mesh(indices));
+                }
+            }
+        }
+
+        let total: usize = loader
+            .get_meshes()
+            .iter()
+            .map(|chunk_mesh| chunk_mesh.sections.iter().filter(|indices| !indices.is_empty()).count())
+            .sum();
+        self.cull_stats = CullStats {
+            drawn: self.visible_meshes.len(),
+            culled: total - self.visible_meshes.len(),
+        };
+    }
+
+    /// Only touches the chunks that crossed the load or unload radius or a level of detail ring
+    /// since the center was at `previous_center`
+    fn update_loaded_chunks(&mut self, previous_center: (i32, i32)) {
+        let center = self.chunk_loading_center;
+        let chunks_to_remove =
+            chunks_to_unload(&self.loaded_chunks, center, self.chunk_unloading_radius);
+        self.unload_chunks(chunks_to_remove);
+
+        for (pos, lod) in chunks_changing_lod(&self.loaded_chunks, previous_center, center) {
+            self.loader.set_chunk_lod(pos, lod);
+        }
+
+        let chunks_to_load = chunks_to_load(&self.loaded_chunks, center, self.chunk_loading_radius);
+        self.load_chunks(chunks_to_load);
+    }
+}
+
+/// Loading and unloading radius of a render distance, which is kept to what the mesh arena holds
+fn loading_radii(render_distance: i32, max_render_distance: i32) -> (i32, i32) {
+    let radius = render_distance.clamp(MIN_RENDER_DISTANCE, max_render_distance);
+    (radius, radius + UNLOAD_HYSTERESIS)
+}
+
+fn chunks_to_unload(
+    loaded_chunks: &HashSet<(i32, i32)>,
+    center: (i32, i32),
+    unloading_radius: i32,
+) -> Vec<(i32, i32)> {
+    loaded_chunks
+        .iter()
+        .copied()
+        .filter(|pos| !is_within_radius(center, *pos, unloading_radius))
+        .collect()
+}
+
+/// Closest chunks come first so the world fills in from the player outwards
+fn chunks_to_load(
+    loaded_chunks: &HashSet<(i32, i32)>,
+    (center_x, center_z): (i32, i32),
+    loading_radius: i32,
+) -> Vec<(i32, i32)> {
+    let radius = loading_radius;
+    let mut chunks: Vec<_> = (-radius..=radius)
+        .flat_map(|z| (-radius..=radius).map(move |x| (x, z)))
+        .filter(|(x, z)| x * x + z * z <= radius * radius)
+        .map(|(x, z)| (x + center_x, z + center_z))
+        .filter(|pos| !loaded_chunks.contains(pos))
+        .collect();
+    chunks.sort_by_key(|pos| distance_squared((center_x, center_z), *pos));
+    chunks
+}
+
+/// Loaded chunks that are in another level of detail ring around `center` than around
+/// `previous_center`, with their new level
+fn chunks_changing_lod(
+    loaded_chunks: &HashSet<(i32, i32)>,
+    previous_center: (i32, i32),
+    center: (i32, i32),
+) -> Vec<((i32, i32), Lod)> {
+    loaded_chunks
+        .iter()
+        .filter_map(|&pos| {
+            let lod = Lod::for_distance_squared(distance_squared(center, pos));
+            let previous_lod = Lod::for_distance_squared(distance_squared(previous_center, pos));
+            (lod != previous_lod).then_some((pos, lod))
+        })
+        .collect()
+}
+
+/// Largest render distance whose geometry should fit in vertex buffers of `max_buffer_size` bytes.
+/// The vertex buffer is always the larger of the arena's two
+fn max_render_distance(max_buffer_size: u64) -> i32 {
+    // More chunks never take less space, so the fitting radii are all below the ones that don't
+    let (mut low, mut high) = (MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE);
+    while low < high {
+        let radius = (low + high + 1) / 2;
+        if estimated_vertex_bytes(radius) <= max_buffer_size {
+            low = radius;
+        } else {
+            high = radius - 1;
+        }
     }
+    low
+}
+
+/// Vertex buffer space every chunk up to the unload radius takes at its level of detail
+fn estimated_vertex_bytes(radius: i32) -> u64 {
+    let unload_radius = radius + UNLOAD_HYSTERESIS;
+    let vertices: u64 = (-unload_radius..=unload_radius)
+        .flat_map(|z| (-unload_radius..=unload_radius).map(move |x| (x, z)))
+        .map(|pos| distance_squared((0, 0), pos))
+        .filter(|&distance_squared| distance_squared <= unload_radius * unload_radius)
+        .map(|distance_squared| Lod::for_distance_squared(distance_squared).typical_vertex_count())
+        .sum();
+    (vertices as f64 * size_of::<ChunkVertex>() as f64 * ARENA_HEADROOM) as u64
+}
+
+fn distance_squared((a_x, a_z): (i32, i32), (b_x, b_z): (i32, i32)) -> i32 {
+    let d_x = a_x - b_x;
+    let d_z = a_z - b_z;
+    d_x * d_x + d_z * d_z
+}
+
+fn is_within_radius(center: (i32, i32), pos: (i32, i32), radius: i32) -> bool {
+    distance_squared(center, pos) <= radius * radius
+}
+
+fn section_bounds((x, y, z): SectionPos) -> Aabb {
+    let size = SECTION_SIZE as f32;
+    let min = Point3::new(x as f32 * size, y as f32 * size, z as f32 * size);
+    Aabb::new(min, min + Vector3::new(size, size, size))
 }
 
 impl<L: ChunkLoader> Renderable for ChunkSystem<L> {
-    fn render(&self, pass: &mut RenderPass) {
+    fn prepare(&self, encoder: &mut CommandEncoder, frame: &FrameContext) {
+        match self.cull_mode {
+            CullMode::Cpu | CullMode::CpuConnectivity => (),
+            CullMode::Gpu => self.gpu_culler.dispatch(encoder, frame, false),
+            CullMode::GpuOcclusion => self.gpu_culler.dispatch(encoder, frame, true),
+        }
+    }
+
+    fn passes(&self) -> &[PassName] {
+        &[PassName::Shadow, PassName::Opaque]
+    }
+
+    fn render(&self, pass_name: PassName, pass: &mut RenderPass, frustum: &Frustum) {
+        match pass_name {
+            PassName::Shadow => self.render_shadow(pass, frustum),
+            PassName::Opaque => self.render_opaque(pass),
+            _ => (),
+        }
+    }
+}
+
+impl<L: ChunkLoader> ChunkSystem<L> {
+    /// Draws what culling left visible, the frustum was already applied there
+    fn render_opaque(&self, pass: &mut RenderPass) {
+        // Every pipeline drawn here shares the chunk pipeline's layout
+        pass.set_bind_group(1, self.block_textures.bind_group(), &[]);
+
+        if self.debug_render_mode == DebugRenderMode::Overdraw {
+            pass.set_pipeline(&self.overdraw_background_pipeline);
+            pass.draw(0..3, 0..1);
+        }
+
+        self.draw_chunks(pass);
+
+        if self.debug_render_mode == DebugRenderMode::ChunkBorders {
+            pass.set_pipeline(&self.chunk_border_pipeline);
+            pass.draw(0..CHUNK_BORDER_VERTICES, 0..1);
+        }
+    }
+
+    fn draw_chunks(&self, pass: &mut RenderPass) {
         pass.set_pipeline(&self.chunk_render_pipeline);
-        pass.set_bind_group(1, &self.texture_atlas_bind_group, &[]);
-        for mesh in self.get_chunk_meshes() {
-            let vertex = mesh.get_vertices();
-            let index = mesh.get_indices();
-
-            pass.set_vertex_buffer(0, vertex.slice(..));
-            pass.set_index_buffer(index.slice(..), IndexFormat::Uint32);
-            pass.draw_indexed(0..mesh.get_index_count(), 0, 0..1);
+
+        // Every chunk lives in the same arena so the buffers only need binding once
+        let arena = self.loader.get_mesh_arena();
+        pass.set_vertex_buffer(0, arena.vertex_buffer().slice(..));
+        pass.set_index_buffer(arena.index_buffer().slice(..), IndexFormat::Uint32);
+
+        if !self.cull_mode.is_cpu() {
+            self.gpu_culler.draw(pass);
+            return;
+        }
+
+        match self.indirect_draws.as_ref() {
+            Some(indirect_draws) => {
+                if indirect_draws.count() > 0 {
+                    pass.multi_draw_indexed_indirect(indirect_draws.buffer(), 0, indirect_draws.count());
+                }
+            }
+            None => {
+                for mesh in &self.visible_meshes {
+                    pass.draw_indexed(mesh.get_index_range(), mesh.get_base_vertex() as i32, 0..1);
+                }
+            }
+        }
+    }
+
+    /// Every loaded chunk the cascade can see casts shadows, regardless of the camera's culling
+    fn render_shadow(&self, pass: &mut RenderPass, frustum: &Frustum) {
+        pass.set_pipeline(&self.chunk_shadow_pipeline);
+
+        let arena = self.loader.get_mesh_arena();
+        pass.set_vertex_buffer(0, arena.vertex_buffer().slice(..));
+        pass.set_index_buffer(arena.index_buffer().slice(..), IndexFormat::Uint32);
+
+        for chunk_mesh in self.loader.get_meshes() {
+            if frustum.intersects_aabb(&chunk_mesh.bounds) {
+                let mesh = &chunk_mesh.mesh;
+                pass.draw_indexed(mesh.get_index_range(), mesh.get_base_vertex() as i32, 0..1);
+            }
         }
     }
 }
 
-fn create_chunk_render_pipeline(gpu_ctx: &GpuCtx) -> RenderPipeline {
+/// Camera, block textures, environment and shadows, the debug pipelines share it so nothing
+/// has to be bound again when switching to them
+fn create_chunk_pipeline_layout(gpu_ctx: &GpuCtx) -> PipelineLayout {
     let camera_bind_group_layout =
         gpu_ctx
             .device
@@ -159,41 +729,64 @@ fn create_chunk_render_pipeline(gpu_ctx: &GpuCtx) -> RenderPipeline {
                 }],
             });
 
-    let texture_atlas_bind_group_layout = gpu_ctx.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: None,
-        entries: &[
-            BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Texture {
-                    sample_type: TextureSampleType::Float {
-                        filterable: true
-                    },
-                    view_dimension: TextureViewDimension::D2,
-                    multisampled: false
-                },
-                count: None
-            },
-            BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                count: None
-            }
-        ]
-    });
+    let block_texture_bind_group_layout = create_block_texture_bind_group_layout(gpu_ctx);
+    let environment_bind_group_layout = create_environment_bind_group_layout(gpu_ctx);
+    let shadow_bind_group_layout = create_shadow_bind_group_layout(gpu_ctx);
 
-    let layout = gpu_ctx
+    gpu_ctx
         .device
         .create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&camera_bind_group_layout, &texture_atlas_bind_group_layout],
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &block_texture_bind_group_layout,
+                &environment_bind_group_layout,
+                &shadow_bind_group_layout,
+            ],
             push_constant_ranges: &[],
-        });
+        })
+}
 
-    let shader = gpu_ctx
-        .device
-        .create_shader_module(wgpu::include_wgsl!("chunk_shader.wgsl"));
+/// Debug render modes swap the fragment shader, and for wireframes and overdraw how faces are rasterized and blended
+fn create_chunk_render_pipeline(
+    gpu_ctx: &GpuCtx,
+    depth_mode: DepthMode,
+    sample_count: u32,
+    debug_render_mode: DebugRenderMode,
+    shader: &ShaderModule,
+) -> RenderPipeline {
+    let layout = create_chunk_pipeline_layout(gpu_ctx);
+
+    let fragment_entry_point = match debug_render_mode {
+        DebugRenderMode::Off | DebugRenderMode::ChunkBorders => "f_main",
+        DebugRenderMode::Wireframe => "f_wireframe",
+        DebugRenderMode::Normals => "f_normals",
+        DebugRenderMode::ChunkTint => "f_chunk_tint",
+        DebugRenderMode::Overdraw => "f_overdraw",
+    };
+    let overdraw = debug_render_mode == DebugRenderMode::Overdraw;
+    let blend = if overdraw {
+        BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent::REPLACE,
+        }
+    } else {
+        BlendState::REPLACE
+    };
+    let polygon_mode = if debug_render_mode == DebugRenderMode::Wireframe {
+        PolygonMode::Line
+    } else {
+        PolygonMode::Fill
+    };
+    let depth_compare = if overdraw {
+        CompareFunction::Always
+    } else {
+        depth_mode.compare_function()
+    };
 
     gpu_ctx
         .device
@@ -201,17 +794,17 @@ fn create_chunk_render_pipeline(gpu_ctx: &GpuCtx) -> RenderPipeline {
             label: None,
             layout: Some(&layout),
             vertex: VertexState {
-                module: &shader,
-                entry_point: None,
+                module: shader,
+                entry_point: Some("v_main"),
                 buffers: &[ChunkVertex::layout()],
                 compilation_options: PipelineCompilationOptions::default(),
             },
             fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: None,
+                module: shader,
+                entry_point: Some(fragment_entry_point),
                 targets: &[Some(ColorTargetState {
-                    format: gpu_ctx.surface_format,
-                    blend: Some(BlendState::REPLACE),
+                    format: HDR_FORMAT,
+                    blend: Some(blend),
                     write_mask: ColorWrites::ALL,
                 })],
                 compilation_options: PipelineCompilationOptions::default(),
@@ -222,18 +815,18 @@ fn create_chunk_render_pipeline(gpu_ctx: &GpuCtx) -> RenderPipeline {
                 front_face: FrontFace::Ccw,
                 cull_mode: Some(Face::Back),
                 unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
+                polygon_mode,
                 conservative: false,
             },
             multisample: MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             depth_stencil: Some(DepthStencilState {
                 format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::LessEqual,
+                depth_write_enabled: !overdraw,
+                depth_compare,
                 stencil: StencilState::default(),
                 bias: DepthBiasState::default(),
             }),
@@ -242,49 +835,250 @@ fn create_chunk_render_pipeline(gpu_ctx: &GpuCtx) -> RenderPipeline {
         })
 }
 
-fn create_texture_atlas(gpu_ctx: &GpuCtx) -> (Texture, TextureView, Sampler) {
-    let size = Extent3d {
-        width: 512,
-        height: 512,
-        depth_or_array_layers: 1
-    };
+/// Lines of the chunk grid, depth tested against the terrain
+fn create_chunk_border_pipeline(
+    gpu_ctx: &GpuCtx,
+    depth_mode: DepthMode,
+    sample_count: u32,
+    shader: &ShaderModule,
+) -> RenderPipeline {
+    let layout = create_chunk_pipeline_layout(gpu_ctx);
+
+    gpu_ctx
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some("v_border"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some("f_border"),
+                targets: &[Some(ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                ..PrimitiveState::default()
+            },
+            multisample: MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: depth_mode.compare_function(),
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
+
+/// Paints over the sky so the overdraw heatmap starts from black, leaves depth alone
+fn create_overdraw_background_pipeline(
+    gpu_ctx: &GpuCtx,
+    sample_count: u32,
+    shader: &ShaderModule,
+) -> RenderPipeline {
+    let layout = create_chunk_pipeline_layout(gpu_ctx);
+
+    gpu_ctx
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some("v_fullscreen"),
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some("f_black"),
+                targets: &[Some(ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
+
+fn create_chunk_shadow_pipeline(gpu_ctx: &GpuCtx, shader: &ShaderModule) -> RenderPipeline {
+    let layout = gpu_ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&create_cascade_bind_group_layout(gpu_ctx)],
+            push_constant_ranges: &[],
+        });
 
-    let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
-        label: None,
-        size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: TextureDimension::D2,
-        format: TextureFormat::Rgba8UnormSrgb,
-        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-        view_formats: &[]
-    });
-
-    let diffuse_image = image::load_from_memory(TEXTURE_ATLAS_BYTES).unwrap();
-    let diffuse_rgba = diffuse_image.to_rgba8();
-
-    gpu_ctx.queue.write_texture(TexelCopyTextureInfo {
-        texture: &texture,
-        mip_level: 0,
-        origin: Origin3d::ZERO,
-        aspect: TextureAspect::All
-    }, &diffuse_rgba, TexelCopyBufferLayout {
-        offset: 0,
-        bytes_per_row: Some(4 * size.width),
-        rows_per_image: Some(size.height)
-    }, size);
-
-    let view = texture.create_view(&TextureViewDescriptor::default());
-    let sampler = gpu_ctx.device.create_sampler(&SamplerDescriptor {
-        label: None,
-        address_mode_u: AddressMode::ClampToEdge,
-        address_mode_v: AddressMode::ClampToEdge,
-        address_mode_w: AddressMode::ClampToEdge,
-        mag_filter: FilterMode::Nearest,
-        min_filter: FilterMode::Nearest,
-        mipmap_filter: FilterMode::Nearest,
-        ..Default::default()
-    });
-
-    (texture, view, sampler)
-}
\ No newline at end of file
+    gpu_ctx
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: None,
+                buffers: &[ChunkVertex::layout()],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                // Skirts only have one side, drawing both keeps light from leaking past them
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            multisample: MultisampleState::default(),
+            // Shadow maps always use standard depth
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: DepthMode::Standard.compare_function(),
+                stencil: StencilState::default(),
+                bias: DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::Limits;
+
+    #[test]
+    fn render_distance_fits_in_the_default_buffer_size() {
+        let max_buffer_size = Limits::default().max_buffer_size;
+        let radius = max_render_distance(max_buffer_size);
+
+        assert!(radius < MAX_RENDER_DISTANCE);
+        assert!(estimated_vertex_bytes(radius) <= max_buffer_size);
+        assert!(estimated_vertex_bytes(radius + 1) > max_buffer_size);
+    }
+
+    #[test]
+    fn large_buffers_reach_the_max_render_distance() {
+        assert!(estimated_vertex_bytes(MAX_RENDER_DISTANCE) > 1 << 30);
+        assert_eq!(max_render_distance(u64::MAX), MAX_RENDER_DISTANCE);
+        assert_eq!(max_render_distance(0), MIN_RENDER_DISTANCE);
+    }
+
+    #[test]
+    fn gpu_culling_is_the_default_only_with_multi_draw() {
+        assert_eq!(CullMode::default_for(true), CullMode::Gpu);
+        assert_eq!(CullMode::default_for(false), CullMode::Cpu);
+    }
+
+    fn loaded_around(center: (i32, i32), radius: i32) -> HashSet<(i32, i32)> {
+        chunks_to_load(&HashSet::new(), center, radius)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn chunks_load_in_a_circle_closest_first() {
+        let chunks = chunks_to_load(&HashSet::new(), (10, -3), 4);
+
+        assert!(chunks.contains(&(14, -3)));
+        assert!(chunks.contains(&(10, 1)));
+        assert!(
+            !chunks.contains(&(13, 0)),
+            "a corner of the square was loaded"
+        );
+        assert_eq!(chunks[0], (10, -3));
+        assert!(chunks.is_sorted_by_key(|pos| distance_squared((10, -3), *pos)));
+
+        let loaded = loaded_around((10, -3), 4);
+        assert_eq!(loaded.len(), chunks.len());
+        assert!(chunks_to_load(&loaded, (10, -3), 4).is_empty());
+    }
+
+    #[test]
+    fn chunks_unload_past_the_hysteresis() {
+        let loaded = loaded_around((0, 0), 8);
+        for step in 0..=UNLOAD_HYSTERESIS {
+            assert!(chunks_to_unload(&loaded, (step, 0), 8 + UNLOAD_HYSTERESIS).is_empty());
+        }
+
+        let center = (UNLOAD_HYSTERESIS + 1, 0);
+        let unloaded = chunks_to_unload(&loaded, center, 8 + UNLOAD_HYSTERESIS);
+        assert!(unloaded.contains(&(-8, 0)));
+        for pos in unloaded {
+            assert!(distance_squared(center, pos) > (8 + UNLOAD_HYSTERESIS).pow(2));
+        }
+    }
+
+    #[test]
+    fn only_chunks_crossing_a_lod_ring_change_lod() {
+        let loaded = loaded_around((0, 0), 40);
+        assert!(chunks_changing_lod(&loaded, (0, 0), (0, 0)).is_empty());
+
+        let changes = chunks_changing_lod(&loaded, (0, 0), (1, 0));
+        assert!(!changes.is_empty());
+        assert!(changes.len() < loaded.len() / 10);
+        for (pos, lod) in changes {
+            assert_eq!(
+                lod,
+                Lod::for_distance_squared(distance_squared((1, 0), pos))
+            );
+            assert_ne!(
+                lod,
+                Lod::for_distance_squared(distance_squared((0, 0), pos))
+            );
+        }
+    }
+
+    #[test]
+    fn render_distance_is_clamped() {
+        let max = max_render_distance(Limits::default().max_buffer_size);
+        assert_eq!(loading_radii(8, max), (8, 8 + UNLOAD_HYSTERESIS));
+        assert_eq!(loading_radii(0, max).0, MIN_RENDER_DISTANCE);
+        assert_eq!(loading_radii(-5, max).0, MIN_RENDER_DISTANCE);
+        assert_eq!(loading_radii(MAX_RENDER_DISTANCE, max).0, max);
+        assert_eq!(
+            loading_radii(DEFAULT_RENDER_DISTANCE, max).0,
+            DEFAULT_RENDER_DISTANCE
+        );
+    }
+}
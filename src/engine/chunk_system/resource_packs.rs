@@ -0,0 +1,722 @@
+use crate::engine::chunk_system::block_textures::BLOCK_TEXTURE_SIZE;
+use crate::engine::chunk_system::voxel_data::BlockType;
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+const MANIFEST_FILE: &str = "pack.txt";
+
+/// Where the built-in pack lives in the source tree
+#[cfg(feature = "hot-reload")]
+pub const DEFAULT_PACK_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/engine/chunk_system/resource_packs/default"
+);
+
+const DEFAULT_MANIFEST: &[u8] = include_bytes!("resource_packs/default/pack.txt");
+const DEFAULT_TEXTURES: [(&str, &[u8]); 3] = [
+    (
+        "dirt",
+        include_bytes!("resource_packs/default/textures/dirt.png"),
+    ),
+    (
+        "grass_side",
+        include_bytes!("resource_packs/default/textures/grass_side.png"),
+    ),
+    (
+        "grass_top",
+        include_bytes!("resource_packs/default/textures/grass_top.png"),
+    ),
+];
+
+/// Blocks a manifest can define, by the name it uses for them
+const BLOCKS: [(&str, BlockType); 1] = [("solid", BlockType::Solid)];
+
+/// Block texture array layer of each face
+#[derive(Clone)]
+pub struct BlockFaces {
+    pub front: u32,
+    pub back: u32,
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// Everything the enabled resource packs resolved to after stacking
+pub struct BlockResources {
+    /// One image per block texture array layer, all `BLOCK_TEXTURE_SIZE` squared
+    pub textures: Vec<RgbaImage>,
+    /// Light each layer gives off on top of the lit color, 0 for layers that don't glow
+    pub emissive: Vec<f32>,
+    pub faces: HashMap<BlockType, BlockFaces>,
+}
+
+#[derive(Debug)]
+pub enum ResourcePackError {
+    Io {
+        pack: String,
+        err: io::Error,
+    },
+    MissingManifest {
+        pack: String,
+    },
+    Manifest {
+        pack: String,
+        line: usize,
+        message: String,
+    },
+    InvalidTexture {
+        pack: String,
+        texture: String,
+        err: image::ImageError,
+    },
+    TextureSize {
+        pack: String,
+        texture: String,
+        width: u32,
+        height: u32,
+    },
+    /// No pack, not even the default one, has the texture
+    MissingTexture {
+        texture: String,
+    },
+}
+
+impl fmt::Display for ResourcePackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { pack, err } => write!(f, "Resource pack {pack:?} couldn't be read: {err}"),
+            Self::MissingManifest { pack } => {
+                write!(f, "Resource pack {pack:?} has no {MANIFEST_FILE}")
+            }
+            Self::Manifest {
+                pack,
+                line,
+                message,
+            } => {
+                write!(
+                    f,
+                    "Resource pack {pack:?}, {MANIFEST_FILE} line {line}: {message}"
+                )
+            }
+            Self::InvalidTexture { pack, texture, err } => {
+                write!(
+                    f,
+                    "Resource pack {pack:?}, {}: {err}",
+                    texture_path(texture)
+                )
+            }
+            Self::TextureSize {
+                pack,
+                texture,
+                width,
+                height,
+            } => write!(
+                f,
+                "Resource pack {pack:?}, {} is {width}x{height}, block textures must be {BLOCK_TEXTURE_SIZE}x{BLOCK_TEXTURE_SIZE}",
+                texture_path(texture)
+            ),
+            Self::MissingTexture { texture } => {
+                write!(f, "No resource pack has {}", texture_path(texture))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResourcePackError {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Face {
+    Front,
+    Back,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Face {
+    const SIDES: [Face; 4] = [Face::Front, Face::Back, Face::Left, Face::Right];
+}
+
+struct Manifest {
+    name: String,
+    priority: i32,
+    /// Only the faces this pack overrides, the rest come from packs below it
+    faces: HashMap<(BlockType, Face), String>,
+    emissive: HashMap<BlockType, f32>,
+}
+
+enum PackSource {
+    BuiltIn,
+    Dir(PathBuf),
+    Zip(ZipArchive<File>),
+}
+
+impl PackSource {
+    /// `Ok(None)` if the pack doesn't contain the file
+    fn read(&mut self, path: &str) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Self::BuiltIn => {
+                if path == MANIFEST_FILE {
+                    return Ok(Some(DEFAULT_MANIFEST.to_vec()));
+                }
+                let texture = DEFAULT_TEXTURES
+                    .iter()
+                    .find(|(texture, _)| texture_path(texture) == path);
+                Ok(texture.map(|(_, bytes)| bytes.to_vec()))
+            }
+            Self::Dir(dir) => match fs::read(dir.join(path)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            },
+            Self::Zip(archive) => {
+                let mut file = match archive.by_name(path) {
+                    Ok(file) => file,
+                    Err(ZipError::FileNotFound) => return Ok(None),
+                    Err(err) => return Err(io::Error::other(err)),
+                };
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes)?;
+                Ok(Some(bytes))
+            }
+        }
+    }
+}
+
+struct ResourcePack {
+    /// File or directory name, the manifest's name can't be trusted before it parsed
+    label: String,
+    manifest: Manifest,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    fn open(label: String, mut source: PackSource) -> Result<Self, ResourcePackError> {
+        let manifest = match source.read(MANIFEST_FILE) {
+            Ok(Some(bytes)) => parse_manifest(&label, &String::from_utf8_lossy(&bytes))?,
+            Ok(None) => return Err(ResourcePackError::MissingManifest { pack: label }),
+            Err(err) => return Err(ResourcePackError::Io { pack: label, err }),
+        };
+
+        Ok(Self {
+            label,
+            manifest,
+            source,
+        })
+    }
+
+    fn built_in() -> Self {
+        // Read from the source tree while hot reloading, so the default textures can be edited too
+        #[cfg(feature = "hot-reload")]
+        if let Ok(pack) = Self::open(
+            "built-in".to_string(),
+            PackSource::Dir(PathBuf::from(DEFAULT_PACK_DIR)),
+        ) {
+            return pack;
+        }
+
+        Self::open("built-in".to_string(), PackSource::BuiltIn)
+            .expect("The built-in resource pack is invalid!")
+    }
+}
+
+/// Stacks every pack in `dir` on top of the built-in one, the pack with the highest priority wins.
+/// Broken packs and textures are skipped and reported, so this always returns something drawable
+pub fn load_resource_packs(dir: &Path) -> (BlockResources, Vec<ResourcePackError>) {
+    let mut errors = Vec::new();
+
+    let mut packs = open_resource_packs(dir, &mut errors);
+    packs.sort_by(|a, b| {
+        b.manifest
+            .priority
+            .cmp(&a.manifest.priority)
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    for pack in &packs {
+        println!(
+            "Loaded resource pack {:?} ({}) with priority {}",
+            pack.manifest.name, pack.label, pack.manifest.priority
+        );
+    }
+
+    let block_resources = stack_resource_packs(packs, &mut errors);
+    (block_resources, errors)
+}
+
+/// Only what's built into the game, for when the output mustn't depend on what's installed
+pub fn built_in_resources() -> BlockResources {
+    let mut errors = Vec::new();
+    let block_resources = stack_resource_packs(Vec::new(), &mut errors);
+    if let Some(err) = errors.first() {
+        panic!("The built-in resource pack is invalid: {err}");
+    }
+    block_resources
+}
+
+/// `packs` go from highest to lowest priority, the built-in pack is put below all of them
+fn stack_resource_packs(
+    mut packs: Vec<ResourcePack>,
+    errors: &mut Vec<ResourcePackError>,
+) -> BlockResources {
+    packs.push(ResourcePack::built_in());
+
+    // A texture used by blocks that glow differently gets a layer for each
+    let mut layers: Vec<(String, f32)> = Vec::new();
+    let mut faces = HashMap::new();
+    for (_, block) in BLOCKS {
+        let emissive = packs
+            .iter()
+            .find_map(|pack| pack.manifest.emissive.get(&block))
+            .copied()
+            .unwrap_or(0.0);
+        let mut layer_of = |face: Face| {
+            let texture = packs
+                .iter()
+                .find_map(|pack| pack.manifest.faces.get(&(block, face)))
+                .expect("The built-in resource pack doesn't define every block face!");
+            let layer = (texture.clone(), emissive);
+            match layers.iter().position(|other| *other == layer) {
+                Some(layer) => layer as u32,
+                None => {
+                    layers.push(layer);
+                    layers.len() as u32 - 1
+                }
+            }
+        };
+
+        let block_faces = BlockFaces {
+            front: layer_of(Face::Front),
+            back: layer_of(Face::Back),
+            top: layer_of(Face::Top),
+            bottom: layer_of(Face::Bottom),
+            left: layer_of(Face::Left),
+            right: layer_of(Face::Right),
+        };
+        faces.insert(block, block_faces);
+    }
+
+    let textures = layers
+        .iter()
+        .map(|(texture, _)| load_texture(&mut packs, texture, errors))
+        .collect();
+    let emissive = layers.iter().map(|&(_, emissive)| emissive).collect();
+
+    BlockResources {
+        textures,
+        emissive,
+        faces,
+    }
+}
+
+/// Every directory and `.zip` file in `dir`, a missing `dir` just means there are no packs
+fn open_resource_packs(dir: &Path, errors: &mut Vec<ResourcePackError>) -> Vec<ResourcePack> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                errors.push(ResourcePackError::Io {
+                    pack: dir.display().to_string(),
+                    err,
+                });
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut packs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let label = entry.file_name().to_string_lossy().into_owned();
+
+        let source = if path.is_dir() {
+            PackSource::Dir(path)
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+        {
+            let archive =
+                File::open(&path).and_then(|file| ZipArchive::new(file).map_err(io::Error::other));
+            match archive {
+                Ok(archive) => PackSource::Zip(archive),
+                Err(err) => {
+                    errors.push(ResourcePackError::Io { pack: label, err });
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+
+        match ResourcePack::open(label, source) {
+            Ok(pack) => packs.push(pack),
+            Err(err) => errors.push(err),
+        }
+    }
+    packs
+}
+
+/// Takes the texture from the highest priority pack that has a usable one
+fn load_texture(
+    packs: &mut [ResourcePack],
+    texture: &str,
+    errors: &mut Vec<ResourcePackError>,
+) -> RgbaImage {
+    for pack in packs {
+        let bytes = match pack.source.read(&texture_path(texture)) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            Err(err) => {
+                errors.push(ResourcePackError::Io {
+                    pack: pack.label.clone(),
+                    err,
+                });
+                continue;
+            }
+        };
+
+        let image = match image::load_from_memory(&bytes) {
+            Ok(image) => image.to_rgba8(),
+            Err(err) => {
+                errors.push(ResourcePackError::InvalidTexture {
+                    pack: pack.label.clone(),
+                    texture: texture.to_string(),
+                    err,
+                });
+                continue;
+            }
+        };
+
+        if image.dimensions() != (BLOCK_TEXTURE_SIZE, BLOCK_TEXTURE_SIZE) {
+            errors.push(ResourcePackError::TextureSize {
+                pack: pack.label.clone(),
+                texture: texture.to_string(),
+                width: image.width(),
+                height: image.height(),
+            });
+            continue;
+        }
+
+        return image;
+    }
+
+    errors.push(ResourcePackError::MissingTexture {
+        texture: texture.to_string(),
+    });
+    missing_texture()
+}
+
+/// `key = value` lines, `#` starts a comment line. Block faces are set with
+/// `<block>.<face> = <texture>`, where `<face>` is one of `top`, `bottom`, `sides`, `front`, `back`,
+/// `left` or `right` and `<texture>` names `textures/<texture>.png`. `<block>.emissive = <strength>`
+/// makes a block glow, 1 is about as bright as full sunlight
+fn parse_manifest(pack: &str, contents: &str) -> Result<Manifest, ResourcePackError> {
+    let mut manifest = Manifest {
+        name: pack.to_string(),
+        priority: 0,
+        faces: HashMap::new(),
+        emissive: HashMap::new(),
+    };
+
+    for (index, line) in contents.lines().enumerate() {
+        let error = |message: String| ResourcePackError::Manifest {
+            pack: pack.to_string(),
+            line: index + 1,
+            message,
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error(format!("expected `key = value`, found {line:?}")));
+        };
+        let (key, value) = (key.trim(), value.trim());
+
+        match key {
+            "name" => manifest.name = value.to_string(),
+            "priority" => {
+                manifest.priority = value
+                    .parse()
+                    .map_err(|_| error(format!("priority {value:?} isn't a whole number")))?;
+            }
+            _ => {
+                let Some((block_name, face_name)) = key.split_once('.') else {
+                    return Err(error(format!("unknown key {key:?}")));
+                };
+                let Some(&(_, block)) = BLOCKS.iter().find(|(name, _)| *name == block_name) else {
+                    return Err(error(format!("unknown block {block_name:?}")));
+                };
+                if face_name == "emissive" {
+                    let strength = value.parse::<f32>().ok();
+                    let Some(strength) = strength.filter(|s| s.is_finite() && *s >= 0.0) else {
+                        return Err(error(format!(
+                            "emissive strength {value:?} isn't a positive number"
+                        )));
+                    };
+                    manifest.emissive.insert(block, strength);
+                    continue;
+                }
+                let faces: &[Face] = match face_name {
+                    "top" => &[Face::Top],
+                    "bottom" => &[Face::Bottom],
+                    "sides" => &Face::SIDES,
+                    "front" => &[Face::Front],
+                    "back" => &[Face::Back],
+                    "left" => &[Face::Left],
+                    "right" => &[Face::Right],
+                    _ => return Err(error(format!("unknown face {face_name:?}"))),
+                };
+
+                // Names become paths inside the pack, so they can't point outside of it
+                let valid_name = !value.is_empty()
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                if !valid_name {
+                    return Err(error(format!(
+                        "texture name {value:?} may only contain letters, digits, `_` and `-`"
+                    )));
+                }
+
+                for &face in faces {
+                    manifest.faces.insert((block, face), value.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(manifest)
+}
+
+fn texture_path(texture: &str) -> String {
+    format!("textures/{texture}.png")
+}
+
+/// Magenta and black checkerboard that's hard to miss in the world
+fn missing_texture() -> RgbaImage {
+    let half = BLOCK_TEXTURE_SIZE / 2;
+    RgbaImage::from_fn(BLOCK_TEXTURE_SIZE, BLOCK_TEXTURE_SIZE, |x, y| {
+        if (x < half) == (y < half) {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    fn pack(manifest: &str) -> ResourcePack {
+        ResourcePack {
+            label: "test".to_string(),
+            manifest: parse_manifest("test", manifest).unwrap_or_else(|err| panic!("{err}")),
+            source: PackSource::Dir(PathBuf::from("does-not-exist")),
+        }
+    }
+
+    /// An empty directory of resource packs of its own
+    fn packs_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("resource_packs_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn solid(color: [u8; 3], size: u32) -> Vec<u8> {
+        let [r, g, b] = color;
+        let image = RgbaImage::from_pixel(size, size, Rgba([r, g, b, 255]));
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn write_dir_pack(dir: &Path, manifest: Option<&str>, textures: &[(&str, Vec<u8>)]) {
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        if let Some(manifest) = manifest {
+            fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+        }
+        for (texture, bytes) in textures {
+            fs::write(dir.join(texture_path(texture)), bytes).unwrap();
+        }
+    }
+
+    fn write_zip_pack(path: &Path, manifest: &str, textures: &[(&str, Vec<u8>)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        zip.start_file(MANIFEST_FILE, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(manifest.as_bytes()).unwrap();
+        for (texture, bytes) in textures {
+            zip.start_file(texture_path(texture), SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn top_texture(resources: &BlockResources) -> &RgbaImage {
+        &resources.textures[resources.faces[&BlockType::Solid].top as usize]
+    }
+
+    fn side_texture(resources: &BlockResources) -> &RgbaImage {
+        &resources.textures[resources.faces[&BlockType::Solid].front as usize]
+    }
+
+    #[test]
+    fn packs_stack_by_priority() {
+        let dir = packs_dir("stack");
+        let red = solid([255, 0, 0], BLOCK_TEXTURE_SIZE);
+        let blue = solid([0, 0, 255], BLOCK_TEXTURE_SIZE);
+        write_dir_pack(
+            &dir.join("a_low"),
+            Some("priority = 1\nsolid.top = red\nsolid.sides = red"),
+            &[("red", red)],
+        );
+        write_zip_pack(
+            &dir.join("high.zip"),
+            "priority = 2\nsolid.top = blue",
+            &[("blue", blue)],
+        );
+
+        let (resources, errors) = load_resource_packs(&dir);
+        let built_in = built_in_resources();
+        let faces = &resources.faces[&BlockType::Solid];
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            top_texture(&resources).get_pixel(0, 0),
+            &Rgba([0, 0, 255, 255])
+        );
+        assert_eq!(
+            side_texture(&resources).get_pixel(0, 0),
+            &Rgba([255, 0, 0, 255])
+        );
+        assert_eq!(
+            resources.textures[faces.bottom as usize],
+            built_in.textures[built_in.faces[&BlockType::Solid].bottom as usize]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn packs_without_a_manifest_are_skipped() {
+        let dir = packs_dir("no_manifest");
+        write_dir_pack(&dir.join("empty"), None, &[]);
+
+        let (resources, errors) = load_resource_packs(&dir);
+
+        assert!(matches!(
+            errors[..],
+            [ResourcePackError::MissingManifest { ref pack }] if pack == "empty"
+        ));
+        assert_eq!(resources.textures, built_in_resources().textures);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn packs_with_a_broken_manifest_are_skipped() {
+        let dir = packs_dir("broken_manifest");
+        write_dir_pack(&dir.join("broken"), Some("priority = 1\nsolid.top"), &[]);
+
+        let (resources, errors) = load_resource_packs(&dir);
+
+        assert!(matches!(
+            errors[..],
+            [ResourcePackError::Manifest { line: 2, .. }]
+        ));
+        assert_eq!(resources.textures, built_in_resources().textures);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn textures_of_the_wrong_size_fall_back_to_lower_packs() {
+        let dir = packs_dir("texture_size");
+        let big = solid([255, 0, 0], BLOCK_TEXTURE_SIZE * 2);
+        write_dir_pack(
+            &dir.join("big"),
+            Some("priority = 1"),
+            &[("grass_top", big)],
+        );
+
+        let (resources, errors) = load_resource_packs(&dir);
+
+        assert!(matches!(
+            errors[..],
+            [ResourcePackError::TextureSize {
+                width: 32,
+                height: 32,
+                ..
+            }]
+        ));
+        assert_eq!(top_texture(&resources), top_texture(&built_in_resources()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_textures_are_a_checkerboard() {
+        let dir = packs_dir("missing_texture");
+        write_dir_pack(&dir.join("missing"), Some("solid.top = nowhere"), &[]);
+
+        let (resources, errors) = load_resource_packs(&dir);
+
+        assert!(matches!(
+            errors[..],
+            [ResourcePackError::MissingTexture { ref texture }] if texture == "nowhere"
+        ));
+        assert_eq!(top_texture(&resources), &missing_texture());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn built_in_blocks_do_not_glow() {
+        let resources = built_in_resources();
+        assert_eq!(resources.emissive, [0.0; 3]);
+        assert_eq!(resources.textures.len(), 3);
+    }
+
+    #[test]
+    fn emissive_blocks_glow_with_the_textures_below_them() {
+        let mut errors = Vec::new();
+        let resources = stack_resource_packs(vec![pack("solid.emissive = 2.5")], &mut errors);
+
+        assert!(errors.is_empty());
+        assert_eq!(resources.emissive, [2.5; 3]);
+        assert_eq!(resources.textures, built_in_resources().textures);
+    }
+
+    #[test]
+    fn higher_priority_pack_sets_emissive() {
+        let mut errors = Vec::new();
+        let packs = vec![pack("solid.emissive = 0.5"), pack("solid.emissive = 4")];
+        let resources = stack_resource_packs(packs, &mut errors);
+        assert_eq!(resources.emissive, [0.5; 3]);
+    }
+
+    #[test]
+    fn emissive_must_be_a_positive_number() {
+        for strength in ["-1", "bright", "inf", ""] {
+            let manifest = format!("name = Glow\nsolid.emissive = {strength}");
+            assert!(matches!(
+                parse_manifest("test", &manifest),
+                Err(ResourcePackError::Manifest { line: 2, .. })
+            ));
+        }
+    }
+}
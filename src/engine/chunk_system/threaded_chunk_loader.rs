@@ -1,35 +1,86 @@
-use crate::engine::chunk_system::chunk_loader::ChunkLoader;
+use crate::engine::chunk_system::chunk_loader::{ChunkLoadStats, ChunkLoader, ChunkMesh};
 use crate::engine::chunk_system::chunk_vertex::ChunkVertex;
+use crate::engine::chunk_system::connectivity::{ChunkVisibility, SECTION_COUNT, SECTION_SIZE, compute_chunk_visibility};
+use crate::engine::chunk_system::lod::Lod;
+use crate::engine::chunk_system::resource_packs::BlockResources;
+#[cfg(feature = "hot-reload")]
+use crate::engine::chunk_system::resource_packs::BlockFaces;
 use crate::engine::chunk_system::voxel_data::{BlockType, VoxelData};
-use crate::engine::gpu::{CpuMesh, GpuCtx, GpuMesh};
+use crate::engine::gpu::{Aabb, CpuMesh, GpuCtx, MeshArena};
 use crate::engine::utils::ThreadPool;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::num::NonZero;
+use std::ops::Range;
 use std::sync::Arc;
 use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::{Duration, Instant};
+use crate::engine::chunk_system::threaded_chunk_loader::cell_grid::CellGrid;
+use crate::engine::chunk_system::threaded_chunk_loader::chunk_epoch::ChunkEpochs;
+use crate::engine::chunk_system::threaded_chunk_loader::chunk_state::{ChunkState, ChunkStates};
 use crate::engine::chunk_system::threaded_chunk_loader::texture_atlas::TextureAtlas;
 
+mod cell_grid;
+mod chunk_epoch;
+mod chunk_state;
 mod texture_atlas;
 
+/// Upload limits per frame so a burst of finished meshes doesn't stall the main thread
+const UPLOAD_BYTE_BUDGET: u64 = 8 * 1024 * 1024;
+const UPLOAD_TIME_BUDGET: Duration = Duration::from_millis(2);
+
+/// Starting arena size, enough for a few hundred surface chunks before it has to grow
+const ARENA_VERTEX_CAPACITY: u64 = 1 << 20;
+const ARENA_INDEX_CAPACITY: u64 = 3 << 19;
+
+/// How far in blocks the border faces of a downsampled chunk reach below its surface
+const SKIRT_DEPTH: usize = 16;
+
+/// Chunks are only handed to the pool for generation while fewer jobs than this per thread are
+/// waiting, so the threads stay busy without committing to chunks that get unloaded before their turn
+const QUEUED_JOBS_PER_THREAD: usize = 2;
+
+/// What's kept of a chunk once it's generated, voxels are only kept at the detail the chunk is
+/// meshed at so distant chunks take a fraction of the memory
+struct GeneratedChunk {
+    pos: (i32, i32),
+    cells: CellGrid,
+    visibility: ChunkVisibility,
+}
+
+struct MeshJobOutput {
+    epoch: u64,
+    version: u64,
+    output: MeshGenOutput,
+}
+
+struct PendingUpload {
+    version: u64,
+    output: MeshGenOutput,
+}
+
 pub struct ThreadedChunkLoader {
     thread_pool: Option<ThreadPool>,
-    voxels: HashMap<(i32, i32), VoxelData>,
-    meshes: HashMap<(i32, i32), GpuMesh>,
-    mesh_priorities: HashMap<(i32, i32), u8>,
-    voxels_to_load: HashSet<(i32, i32)>,
-    meshes_to_load: HashSet<(i32, i32)>,
+    /// Shared with the mesh jobs of the chunk and its neighbours
+    voxels: HashMap<(i32, i32), Arc<CellGrid>>,
+    meshes: HashMap<(i32, i32), ChunkMesh>,
+    visibility: HashMap<(i32, i32), ChunkVisibility>,
+    pending_uploads: HashMap<(i32, i32), PendingUpload>,
+    mesh_revision: u64,
+    mesh_arena: MeshArena<ChunkVertex>,
+    states: ChunkStates,
+    epochs: ChunkEpochs,
     texture_atlas: Arc<TextureAtlas>,
 
-    voxel_job_tx: Sender<VoxelData>,
-    voxel_job_recv: Receiver<VoxelData>,
-    mesh_job_tx: Sender<((i32, i32), GpuMesh, u8)>,
-    mesh_job_recv: Receiver<((i32, i32), GpuMesh, u8)>,
+    voxel_job_tx: Sender<(u64, GeneratedChunk)>,
+    voxel_job_recv: Receiver<(u64, GeneratedChunk)>,
+    mesh_job_tx: Sender<MeshJobOutput>,
+    mesh_job_recv: Receiver<MeshJobOutput>,
 
     gpu_ctx: Arc<GpuCtx>,
 }
 
 impl ThreadedChunkLoader {
-    pub fn new(gpu_ctx: Arc<GpuCtx>) -> Self {
+    pub fn new(gpu_ctx: Arc<GpuCtx>, block_resources: &BlockResources) -> Self {
         let thread_pool = Some(ThreadPool::new(
             std::thread::available_parallelism()
                 .unwrap_or(NonZero::new(4).unwrap())
@@ -43,10 +94,13 @@ impl ThreadedChunkLoader {
             thread_pool,
             voxels: HashMap::new(),
             meshes: HashMap::new(),
-            mesh_priorities: HashMap::new(),
-            voxels_to_load: HashSet::new(),
-            meshes_to_load: HashSet::new(),
-            texture_atlas: Arc::new(TextureAtlas::new()),
+            visibility: HashMap::new(),
+            pending_uploads: HashMap::new(),
+            mesh_revision: 0,
+            mesh_arena: MeshArena::new(&gpu_ctx, ARENA_VERTEX_CAPACITY, ARENA_INDEX_CAPACITY),
+            states: ChunkStates::new(),
+            epochs: ChunkEpochs::new(),
+            texture_atlas: Arc::new(TextureAtlas::new(block_resources.faces.clone())),
             voxel_job_tx,
             voxel_job_recv,
             mesh_job_tx,
@@ -54,118 +108,290 @@ impl ThreadedChunkLoader {
             gpu_ctx,
         }
     }
+
+    fn upload_meshes(&mut self) {
+        let start = Instant::now();
+        let mut uploaded_bytes = 0;
+
+        let pending: Vec<_> = self.pending_uploads.keys().copied().collect();
+        for pos in pending {
+            if uploaded_bytes >= UPLOAD_BYTE_BUDGET || start.elapsed() >= UPLOAD_TIME_BUDGET {
+                break;
+            }
+
+            let Some(PendingUpload { version, output }) = self.pending_uploads.remove(&pos) else {
+                continue;
+            };
+            if !self.states.is_newer_mesh(pos, version) {
+                continue;
+            }
+
+            let MeshGenOutput {
+                mesh,
+                bounds,
+                sections,
+                ..
+            } = output;
+
+            uploaded_bytes += mesh.byte_size();
+            let Ok(gpu_mesh) = self.mesh_arena.upload(&self.gpu_ctx, &mesh) else {
+                // The old mesh stays up until a retry fits, failures show up in the arena stats
+                self.states.fail_meshing(pos, version);
+                continue;
+            };
+            self.states.finish_meshing(pos, version);
+            let gpu_mesh = gpu_mesh.zip(bounds).map(|(mesh, bounds)| ChunkMesh {
+                mesh,
+                bounds,
+                sections,
+            });
+
+            // Empty chunks (all air) have no mesh, but still need to drop the one they had before
+            let old_mesh = match gpu_mesh {
+                Some(gpu_mesh) => self.meshes.insert(pos, gpu_mesh),
+                None => self.meshes.remove(&pos),
+            };
+            if let Some(old_mesh) = old_mesh {
+                self.mesh_arena.release(old_mesh.mesh);
+            }
+            self.mesh_revision += 1;
+        }
+    }
+
+    /// Voxels stored coarser than the chunk's level of detail can't be meshed at it,
+    /// the chunk has to be generated again
+    fn regenerate_if_too_coarse(&mut self, pos: (i32, i32)) {
+        if let Some(cells) = self.voxels.get(&pos)
+            && let Some(lod) = self.states.lod(pos)
+            && cells.scale() > lod.scale()
+        {
+            self.states.regenerate(pos);
+        }
+    }
 }
 
 impl ChunkLoader for ThreadedChunkLoader {
-    fn queue_load_chunk(&mut self, pos: (i32, i32)) {
-        if !self.voxels.contains_key(&pos) {
-            self.voxels_to_load.insert(pos);
+    fn queue_load_chunk(&mut self, pos: (i32, i32), lod: Lod) {
+        if !self.epochs.contains(pos) {
+            self.epochs.begin(pos);
+            self.states.queue(pos, lod);
         }
     }
 
+    fn set_chunk_lod(&mut self, pos: (i32, i32), lod: Lod) {
+        self.states.set_lod(pos, lod);
+        self.regenerate_if_too_coarse(pos);
+    }
+
     fn queue_unload_chunk(&mut self, pos: (i32, i32)) {
+        // Any jobs still in flight for this chunk will see their ticket go stale
+        self.epochs.invalidate(pos);
+        self.states.remove(pos);
         self.voxels.remove(&pos);
-        self.meshes.remove(&pos);
-        self.mesh_priorities.remove(&pos);
+        self.pending_uploads.remove(&pos);
+        self.visibility.remove(&pos);
+        if let Some(chunk_mesh) = self.meshes.remove(&pos) {
+            self.mesh_arena.release(chunk_mesh.mesh);
+            self.mesh_revision += 1;
+        }
     }
 
     fn process_chunks(&mut self) {
         let pool = self.thread_pool.as_ref().unwrap();
 
         // Queue voxel generation
-        const QUEUE_SIZE: usize = 4;
-        let mut loaded_voxels = Vec::with_capacity(QUEUE_SIZE);
-
-        for pos in self.voxels_to_load.iter().take(QUEUE_SIZE) {
-            if !self.voxels.contains_key(pos) {
-                // queue voxel gen
-                let pos = *pos;
-                let rx = Sender::clone(&self.voxel_job_tx);
-
-                pool.run(move || {
-                    let voxels = generate_voxels(pos);
-                    let _ = rx.send(voxels);
-                })
-            }
+        while pool.queued_jobs() < pool.thread_count() * QUEUED_JOBS_PER_THREAD {
+            let Some(pos) = self.states.start_generating() else {
+                break;
+            };
+            let Some(ticket) = self.epochs.ticket(pos) else {
+                continue;
+            };
+            let lod = self.states.lod(pos).unwrap_or_default();
+
+            let rx = Sender::clone(&self.voxel_job_tx);
+
+            pool.run(move || {
+                if ticket.is_stale() {
+                    return;
+                }
 
-            loaded_voxels.push(*pos);
+                let voxels = generate_voxels(pos);
+                let _ = rx.send((
+                    ticket.epoch(),
+                    GeneratedChunk {
+                        pos,
+                        cells: CellGrid::new(&voxels, lod.scale()),
+                        visibility: compute_chunk_visibility(&voxels),
+                    },
+                ));
+            })
         }
 
-        for pos in loaded_voxels {
-            self.voxels_to_load.remove(&pos);
-        };
-
         // Receive voxel data
-        while let Ok(voxels) = self.voxel_job_recv.try_recv() {
-            let (c_x, c_z) = voxels.pos();
-            self.voxels.insert(voxels.pos(), voxels);
-
-            // doesnt work due to race condition
-            self.meshes_to_load.insert((c_x, c_z));
-
-            let adj_chunks = [
-                (c_x + 1, c_z),
-                (c_x - 1, c_z),
-                (c_x, c_z + 1),
-                (c_x, c_z - 1),
-            ];
-
-            for pos in adj_chunks {
-                self.meshes_to_load.insert(pos);
+        while let Ok((epoch, chunk)) = self.voxel_job_recv.try_recv() {
+            let pos = chunk.pos;
+            if !self.epochs.is_current(pos, epoch) {
+                continue;
             }
+
+            // Neighbours that were already meshed get marked dirty so their border faces are rebuilt
+            self.voxels.insert(pos, Arc::new(chunk.cells));
+            self.visibility.insert(pos, chunk.visibility);
+            self.states.finish_generating(pos);
+            // The chunk may have come closer while it was being generated
+            self.regenerate_if_too_coarse(pos);
         }
 
         // Queue mesh generation
-        for pos in &self.meshes_to_load {
-            if self.voxels.contains_key(pos) {
-                // queue voxel gen
-                let local = self.voxels.get(pos).unwrap().clone();
-                let pos_x = self.voxels.get(&(pos.0 + 1, pos.1)).cloned();
-                let neg_x = self.voxels.get(&(pos.0 - 1, pos.1)).cloned();
-                let pos_z = self.voxels.get(&(pos.0, pos.1 + 1)).cloned();
-                let neg_z = self.voxels.get(&(pos.0, pos.1 - 1)).cloned();
-
-                let input = MeshGenInput {
-                    local,
-                    pos_x,
-                    neg_x,
-                    pos_z,
-                    neg_z,
-                };
+        let pool = self.thread_pool.as_ref().unwrap();
+        let to_mesh: Vec<_> = self.states.needs_mesh().collect();
+
+        for pos in to_mesh {
+            let Some(ticket) = self.epochs.ticket(pos) else {
+                continue;
+            };
+            let Some((version, lod)) = self.states.start_meshing(pos) else {
+                continue;
+            };
+
+            let local = Arc::clone(self.voxels.get(&pos).unwrap());
+            let pos_x = self.voxels.get(&(pos.0 + 1, pos.1)).cloned();
+            let neg_x = self.voxels.get(&(pos.0 - 1, pos.1)).cloned();
+            let pos_z = self.voxels.get(&(pos.0, pos.1 + 1)).cloned();
+            let neg_z = self.voxels.get(&(pos.0, pos.1 - 1)).cloned();
+
+            let input = MeshGenInput {
+                pos,
+                local,
+                pos_x,
+                neg_x,
+                pos_z,
+                neg_z,
+                lod,
+            };
+
+            let rx = Sender::clone(&self.mesh_job_tx);
+            let atlas = Arc::clone(&self.texture_atlas);
+
+            pool.run(move || {
+                if ticket.is_stale() {
+                    return;
+                }
 
-                let rx = Sender::clone(&self.mesh_job_tx);
-                let gpu_ctx = Arc::clone(&self.gpu_ctx);
-                let atlas = Arc::clone(&self.texture_atlas);
+                let output = generate_mesh(input, atlas);
+                let _ = rx.send(MeshJobOutput {
+                    epoch: ticket.epoch(),
+                    version,
+                    output,
+                });
+            })
+        }
 
-                pool.run(move || {
-                    let mesh = generate_mesh(input, atlas, gpu_ctx);
-                    let _ = rx.send(mesh);
-                })
+        // Receive mesh data
+        while let Ok(MeshJobOutput {
+            epoch,
+            version,
+            mut output,
+        }) = self.mesh_job_recv.try_recv()
+        {
+            let pos = output.pos;
+            if !self.epochs.is_current(pos, epoch) {
+                continue;
             }
-        }
 
-        self.meshes_to_load.clear();
+            // Chunks that moved further away drop the detail they no longer need
+            if let Some(cells) = output.downsampled.take()
+                && self
+                    .states
+                    .lod(pos)
+                    .is_some_and(|lod| lod.scale() >= cells.scale())
+                && self
+                    .voxels
+                    .get(&pos)
+                    .is_some_and(|stored| stored.scale() < cells.scale())
+            {
+                self.voxels.insert(pos, cells);
+            }
 
-        // Receive mesh data
-        while let Ok((pos, mesh, priority)) = self.mesh_job_recv.try_recv() {
-            if self.mesh_priorities.get(&pos).map(|prev| *prev <= priority).unwrap_or(true) && self.voxels.contains_key(&pos) {
-                self.mesh_priorities.insert(pos, priority);
-                self.meshes.insert(pos, mesh);
+            // Only the newest mesh waiting for upload is worth keeping
+            match self.pending_uploads.get(&pos) {
+                Some(pending) if pending.version > version => (),
+                _ => {
+                    self.pending_uploads
+                        .insert(pos, PendingUpload { version, output });
+                }
             }
         }
+
+        self.upload_meshes();
     }
 
-    fn get_meshes(&self) -> Vec<&GpuMesh> {
+    fn get_meshes(&self) -> Vec<&ChunkMesh> {
         self.meshes.values().collect()
     }
+
+    fn get_mesh(&self, pos: (i32, i32)) -> Option<&ChunkMesh> {
+        self.meshes.get(&pos)
+    }
+
+    fn get_visibility(&self, pos: (i32, i32)) -> Option<&ChunkVisibility> {
+        self.visibility.get(&pos)
+    }
+
+    fn get_mesh_revision(&self) -> u64 {
+        self.mesh_revision
+    }
+
+    fn get_mesh_arena(&self) -> &MeshArena<ChunkVertex> {
+        &self.mesh_arena
+    }
+
+    fn is_idle(&self) -> bool {
+        self.pending_uploads.is_empty() && self.states.all_meshed()
+    }
+
+    fn get_load_stats(&self) -> ChunkLoadStats {
+        let pending = [ChunkState::Queued, ChunkState::Generating];
+        let meshing = [ChunkState::Generated, ChunkState::Meshing, ChunkState::Dirty];
+        ChunkLoadStats {
+            loaded: self.voxels.len(),
+            pending: self.states.count(&pending),
+            meshing: self.states.count(&meshing),
+            queued_jobs: self
+                .thread_pool
+                .as_ref()
+                .map_or(0, ThreadPool::queued_jobs),
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn set_block_faces(&mut self, faces: HashMap<BlockType, BlockFaces>) {
+        // Jobs already running keep the old atlas, marking them dirty meshes them again afterwards
+        self.texture_atlas = Arc::new(TextureAtlas::new(faces));
+        for &pos in self.voxels.keys() {
+            self.states.mark_dirty(pos);
+        }
+    }
 }
 
 struct MeshGenInput {
-    pub local: VoxelData,
-    pub pos_x: Option<VoxelData>,
-    pub neg_x: Option<VoxelData>,
-    pub pos_z: Option<VoxelData>,
-    pub neg_z: Option<VoxelData>,
+    pub pos: (i32, i32),
+    pub local: Arc<CellGrid>,
+    pub pos_x: Option<Arc<CellGrid>>,
+    pub neg_x: Option<Arc<CellGrid>>,
+    pub pos_z: Option<Arc<CellGrid>>,
+    pub neg_z: Option<Arc<CellGrid>>,
+    pub lod: Lod,
+}
+
+struct MeshGenOutput {
+    pos: (i32, i32),
+    mesh: CpuMesh<ChunkVertex>,
+    bounds: Option<Aabb>,
+    sections: [Range<u32>; SECTION_COUNT],
+    /// The chunk's voxels at the detail they were meshed at, when that's coarser than what's stored
+    downsampled: Option<Arc<CellGrid>>,
 }
 
 fn generate_voxels((c_x, c_z): (i32, i32)) -> VoxelData {
@@ -175,16 +401,16 @@ fn generate_voxels((c_x, c_z): (i32, i32)) -> VoxelData {
     let ptr = uninit_chunk.as_mut_ptr();
 
     for z in 0..16 {
-        for y in 0..256 {
-            for x in 0..16 {
-                let v_x = x as i32 + 16 * c_x;
-                let v_z = z as i32 + 16 * c_z;
+        for x in 0..16 {
+            let v_x = x as i32 + 16 * c_x;
+            let v_z = z as i32 + 16 * c_z;
 
-                let math_x = v_x as f32 / 16.0;
-                let math_z = v_z as f32 / 16.0;
+            let math_x = v_x as f32 / 16.0;
+            let math_z = v_z as f32 / 16.0;
 
-                let y_max = 240+ ((math_x.sin() * math_z.sin() + 1.0) * 8.0).trunc() as i32;
+            let y_max = 240+ ((math_x.sin() * math_z.sin() + 1.0) * 8.0).trunc() as i32;
 
+            for y in 0..256 {
                 unsafe {
                     (*ptr)[z][y][x] = if y <= y_max as usize {
                         BlockType::Solid
@@ -197,180 +423,166 @@ fn generate_voxels((c_x, c_z): (i32, i32)) -> VoxelData {
     }
 
     let voxel_data = unsafe { uninit_chunk.assume_init() };
-    VoxelData::new(voxel_data, (c_x, c_z))
+    VoxelData::new(voxel_data)
 }
 
 fn generate_mesh(
     MeshGenInput {
+        pos: (c_x, c_z),
         local,
         pos_x,
         neg_x,
         pos_z,
         neg_z,
+        lod,
     }: MeshGenInput,
     atlas: Arc<TextureAtlas>,
-    gpu_ctx: Arc<GpuCtx>,
-) -> ((i32, i32), GpuMesh, u8) {
+) -> MeshGenOutput {
     let mut c_vertices = vec![];
     let mut c_indicies = vec![];
 
-    let (c_x, c_z) = local.pos();
-
-    for z in 0..16 {
-        for y in 0..256 {
-            for x in 0..16 {
-                if matches!(
-                    local.data()[z as usize][y as usize][x as usize],
-                    BlockType::Air
-                ) {
-                    continue;
-                }
-
-                // Ensure face needs to be generated
-                const SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN: bool = true;
-
-                // Front Face
-                let gen_front = match (z, pos_z.as_ref()) {
-                    (0..15, _) => matches!(
-                        local.data()[(z + 1) as usize][y as usize][x as usize],
-                        BlockType::Air
-                    ),
-                    (15, Some(pos_z)) => {
-                        matches!(pos_z.data()[0][y as usize][x as usize], BlockType::Air)
+    // Everything below works on cubes `scale` blocks wide, at full detail a cube is a single block.
+    // Neighbours may be stored at another detail, their borders are compared at this one
+    let scale = lod.scale();
+    let at_scale = |cells: Arc<CellGrid>| {
+        if cells.scale() == scale {
+            cells
+        } else {
+            Arc::new(cells.resample(scale))
+        }
+    };
+    let downsampled = local.scale() < scale;
+    let cells = at_scale(local);
+    let pos_x = pos_x.map(at_scale);
+    let neg_x = neg_x.map(at_scale);
+    let pos_z = pos_z.map(at_scale);
+    let neg_z = neg_z.map(at_scale);
+
+    let last = cells.width() - 1;
+    let height = cells.height();
+    let section_cells = SECTION_SIZE / scale;
+    let skirt_cells = SKIRT_DEPTH / scale;
+
+    // Indices are laid out one section after another so each section can be drawn on its own
+    let mut sections: [Range<u32>; SECTION_COUNT] = Default::default();
+    for (section, indices) in sections.iter_mut().enumerate() {
+        let section_start = c_indicies.len() as u32;
+
+        for z in 0..cells.width() {
+            for y in section * section_cells..(section + 1) * section_cells {
+                for x in 0..cells.width() {
+                    if !cells.is_solid(x, y, z) {
+                        continue;
                     }
-                    (15, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
-                    _ => unreachable!(),
-                };
 
-                // Right Face
-                let gen_right = match (x, pos_x.as_ref()) {
-                    (0..15, _) => matches!(
-                        local.data()[z as usize][y as usize][(x + 1) as usize],
-                        BlockType::Air
-                    ),
-                    (15, Some(pos_x)) => {
-                        matches!(pos_x.data()[z as usize][y as usize][0], BlockType::Air)
+                    // Ensure face needs to be generated, faces towards unknown chunks are
+                    // dropped when the neighbour arrives and this chunk gets remeshed
+                    const SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN: bool = true;
+
+                    // Downsampled chunks sit a little higher than full detail ones, so their border faces
+                    // near the surface are always generated and hang down as a skirt covering the gap
+                    let at_border = x == 0 || x == last || z == 0 || z == last;
+                    let is_skirt = scale > 1
+                        && at_border
+                        && (1..=skirt_cells).any(|d| y + d >= height || !cells.is_solid(x, y + d, z));
+
+                    // Front Face
+                    let gen_front = match (z == last, pos_z.as_ref()) {
+                        (false, _) => !cells.is_solid(x, y, z + 1),
+                        (true, Some(pos_z)) => is_skirt || !pos_z.is_solid(x, y, 0),
+                        (true, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
+                    };
+
+                    // Right Face
+                    let gen_right = match (x == last, pos_x.as_ref()) {
+                        (false, _) => !cells.is_solid(x + 1, y, z),
+                        (true, Some(pos_x)) => is_skirt || !pos_x.is_solid(0, y, z),
+                        (true, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
+                    };
+
+                    // Back Face
+                    let gen_back = match (z == 0, neg_z.as_ref()) {
+                        (false, _) => !cells.is_solid(x, y, z - 1),
+                        (true, Some(neg_z)) => is_skirt || !neg_z.is_solid(x, y, last),
+                        (true, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
+                    };
+
+                    // Left Face
+                    let gen_left = match (x == 0, neg_x.as_ref()) {
+                        (false, _) => !cells.is_solid(x - 1, y, z),
+                        (true, Some(neg_x)) => is_skirt || !neg_x.is_solid(last, y, z),
+                        (true, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
+                    };
+
+                    // Top Face
+                    let gen_top = y + 1 >= height || !cells.is_solid(x, y + 1, z);
+                    // Bottom Face
+                    let gen_bottom = y == 0 || !cells.is_solid(x, y - 1, z);
+
+                    // generate faces
+                    let v_x = c_x * 16 + (x * scale) as i32;
+                    let v_y = (y * scale) as i32;
+                    let v_z = c_z * 16 + (z * scale) as i32;
+                    let size = scale as f32;
+
+                    let faces = atlas.get(BlockType::Solid).unwrap();
+
+                    if gen_front {
+                        let v = gen_front_face(v_x, v_y, v_z, size, faces.front);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
                     }
-                    (15, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
-                    _ => unreachable!(),
-                };
 
-                // Back Face
-                let gen_back = match (z, neg_z.as_ref()) {
-                    (1..16, _) => matches!(
-                        local.data()[(z - 1) as usize][y as usize][x as usize],
-                        BlockType::Air
-                    ),
-                    (0, Some(neg_z)) => {
-                        matches!(neg_z.data()[15][y as usize][x as usize], BlockType::Air)
+                    if gen_right {
+                        let v = gen_right_face(v_x, v_y, v_z, size, faces.right);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
                     }
-                    (0, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
-                    _ => unreachable!(),
-                };
 
-                // Left Face
-                let gen_left = match (x, neg_x.as_ref()) {
-                    (1..16, _) => matches!(
-                        local.data()[z as usize][y as usize][(x - 1) as usize],
-                        BlockType::Air
-                    ),
-                    (0, Some(neg_x)) => {
-                        matches!(neg_x.data()[z as usize][y as usize][15], BlockType::Air)
+                    if gen_back {
+                        let v = gen_back_face(v_x, v_y, v_z, size, faces.back);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
                     }
-                    (0, None) => SHOULD_GENERATE_IF_ADJACENT_CHUNK_IS_UNKNOWN,
-                    _ => unreachable!(),
-                };
-
-                // Top Face
-                let gen_top = if y < 255 {
-                    matches!(
-                        local.data()[z as usize][(y + 1) as usize][x as usize],
-                        BlockType::Air
-                    )
-                } else {
-                    true
-                };
-                // Bottom Face
-                let gen_bottom = if y > 0 {
-                    matches!(
-                        local.data()[z as usize][(y - 1) as usize][x as usize],
-                        BlockType::Air
-                    )
-                } else {
-                    true
-                };
-
-                // generate faces
-                let v_x = c_x * 16 + x;
-                let v_z = c_z * 16 + z;
-
-                let faces = atlas.get(BlockType::Solid).unwrap();
-                
-                let front = tex_index_to_coords(faces.front);
-                let back = tex_index_to_coords(faces.back);
-                let top = tex_index_to_coords(faces.top);
-                let bottom = tex_index_to_coords(faces.bottom);
-                let left = tex_index_to_coords(faces.left);
-                let right = tex_index_to_coords(faces.right);
-                
-                if gen_front {
-                    
-                    let v = gen_front_face(v_x, y, v_z, front);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
-                }
-
-                if gen_right {
-                    let v = gen_right_face(v_x, y, v_z, right);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
-                }
-
-                if gen_back {
-                    let v = gen_back_face(v_x, y, v_z, back);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
-                }
 
-                if gen_left {
-                    let v = gen_left_face(v_x, y, v_z, left);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
-                }
+                    if gen_left {
+                        let v = gen_left_face(v_x, v_y, v_z, size, faces.left);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
+                    }
 
-                if gen_top {
-                    let v = gen_top_face(v_x, y, v_z, top);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
-                }
+                    if gen_top {
+                        let v = gen_top_face(v_x, v_y, v_z, size, faces.top);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
+                    }
 
-                if gen_bottom {
-                    let v = gen_bottom_face(v_x, y, v_z, bottom);
-                    let i = gen_face_indices(c_vertices.len() as u32);
-                    c_vertices.extend_from_slice(&v);
-                    c_indicies.extend_from_slice(&i);
+                    if gen_bottom {
+                        let v = gen_bottom_face(v_x, v_y, v_z, size, faces.bottom);
+                        let i = gen_face_indices(c_vertices.len() as u32);
+                        c_vertices.extend_from_slice(&v);
+                        c_indicies.extend_from_slice(&i);
+                    }
                 }
             }
         }
+
+        *indices = section_start..c_indicies.len() as u32;
     }
 
-    let priority = pos_x.map(|_| 1).unwrap_or(0)
-        + neg_x.map(|_| 1).unwrap_or(0)
-        + pos_z.map(|_| 1).unwrap_or(0)
-        + neg_z.map(|_| 1).unwrap_or(0);
-
-    (
-        (c_x, c_z),
-        CpuMesh::new(c_vertices, c_indicies)
-            .to_gpu_mesh(&gpu_ctx)
-            .unwrap(),
-        priority,
-    )
+    let bounds = Aabb::from_points(c_vertices.iter().map(|vertex| vertex.pos));
+    MeshGenOutput {
+        pos: (c_x, c_z),
+        mesh: CpuMesh::new(c_vertices, c_indicies),
+        bounds,
+        sections,
+        downsampled: downsampled.then_some(cells),
+    }
 }
 
 fn gen_face_indices(starting_index: u32) -> [u32; 6] {
@@ -384,190 +596,394 @@ fn gen_face_indices(starting_index: u32) -> [u32; 6] {
     ]
 }
 
-fn gen_front_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_front_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let pos_z = [0.0, 0.0, 1.0];
 
     let tl = ChunkVertex {
-        pos: [x, y + 1.0, z + 1.0],
+        pos: [x, y + size, z + size],
         normal: pos_z,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
-        pos: [x, y, z + 1.0],
+        pos: [x, y, z + size],
         normal: pos_z,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z + 1.0],
+        pos: [x + size, y + size, z + size],
         normal: pos_z,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
-        pos: [x + 1.0, y, z + 1.0],
+        pos: [x + size, y, z + size],
         normal: pos_z,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn gen_right_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_right_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let pos_x = [1.0, 0.0, 0.0];
 
     let tl = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z + 1.0],
+        pos: [x + size, y + size, z + size],
         normal: pos_x,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
-        pos: [x + 1.0, y, z + 1.0],
+        pos: [x + size, y, z + size],
         normal: pos_x,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z],
+        pos: [x + size, y + size, z],
         normal: pos_x,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
-        pos: [x + 1.0, y, z],
+        pos: [x + size, y, z],
         normal: pos_x,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn gen_back_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_back_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let neg_z = [0.0, 0.0, -1.0];
 
     let tl = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z],
+        pos: [x + size, y + size, z],
         normal: neg_z,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
-        pos: [x + 1.0, y, z],
+        pos: [x + size, y, z],
         normal: neg_z,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x, y + 1.0, z],
+        pos: [x, y + size, z],
         normal: neg_z,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
         pos: [x, y, z],
         normal: neg_z,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn gen_left_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_left_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let neg_x = [-1.0, 0.0, 0.0];
 
     let tl = ChunkVertex {
-        pos: [x, y + 1.0, z],
+        pos: [x, y + size, z],
         normal: neg_x,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
         pos: [x, y, z],
         normal: neg_x,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x, y + 1.0, z + 1.0],
+        pos: [x, y + size, z + size],
         normal: neg_x,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
-        pos: [x, y, z + 1.0],
+        pos: [x, y, z + size],
         normal: neg_x,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn gen_top_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_top_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let pos_y = [0.0, 1.0, 0.0];
 
     let tl = ChunkVertex {
-        pos: [x, y + 1.0, z],
+        pos: [x, y + size, z],
         normal: pos_y,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
-        pos: [x, y + 1.0, z + 1.0],
+        pos: [x, y + size, z + size],
         normal: pos_y,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z],
+        pos: [x + size, y + size, z],
         normal: pos_y,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
-        pos: [x + 1.0, y + 1.0, z + 1.0],
+        pos: [x + size, y + size, z + size],
         normal: pos_y,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn gen_bottom_face(x: i32, y: i32, z: i32, tex_coords: [[f32; 2]; 4]) -> [ChunkVertex; 4] {
+fn gen_bottom_face(x: i32, y: i32, z: i32, size: f32, layer: u32) -> [ChunkVertex; 4] {
+    let tex_coords = face_tex_coords(size);
     let x = x as f32;
     let y = y as f32;
     let z = z as f32;
     let neg_y = [0.0, -1.0, 0.0];
 
     let tl = ChunkVertex {
-        pos: [x, y, z + 1.0],
+        pos: [x, y, z + size],
         normal: neg_y,
-        tex_coords: tex_coords[0]
+        tex_coords: tex_coords[0],
+        layer
     };
     let bl = ChunkVertex {
         pos: [x, y, z],
         normal: neg_y,
-        tex_coords: tex_coords[1]
+        tex_coords: tex_coords[1],
+        layer
     };
     let tr = ChunkVertex {
-        pos: [x + 1.0, y, z + 1.0],
+        pos: [x + size, y, z + size],
         normal: neg_y,
-        tex_coords: tex_coords[2]
+        tex_coords: tex_coords[2],
+        layer
     };
     let br = ChunkVertex {
-        pos: [x + 1.0, y, z],
+        pos: [x + size, y, z],
         normal: neg_y,
-        tex_coords: tex_coords[3]
+        tex_coords: tex_coords[3],
+        layer
     };
     [tl, bl, tr, br]
 }
 
-fn tex_index_to_coords(index: [u8; 2]) -> [[f32; 2]; 4] {
-    let scale = 16.0 / 512.0;
-
-    let x = index[0] as f32 * scale;
-    let y = index[1] as f32 * scale;
-    
+/// Texture coordinates run past 1 on downsampled faces so the texture repeats once per block
+fn face_tex_coords(size: f32) -> [[f32; 2]; 4] {
     [
-        [x, y],
-        [x, y + scale],
-        [x + scale, y],
-        [x + scale, y + scale]
+        [0.0, 0.0],
+        [0.0, size],
+        [size, 0.0],
+        [size, size]
     ]
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::chunk_system::built_in_resources;
+    use crate::engine::render_system::RenderSystem;
+
+    /// Long enough for a software adapter to mesh a few chunks
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn test_loader() -> ThreadedChunkLoader {
+        let gpu_ctx = RenderSystem::new_headless(16, 16).get_gpu_ctx();
+        ThreadedChunkLoader::new(gpu_ctx, &built_in_resources())
+    }
+
+    fn square(radius: i32) -> Vec<(i32, i32)> {
+        (-radius..=radius)
+            .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+            .collect()
+    }
+
+    fn process_until_idle(loader: &mut ThreadedChunkLoader) {
+        let start = Instant::now();
+        while !loader.is_idle() {
+            assert!(start.elapsed() < TIMEOUT, "chunks never finished loading");
+            loader.process_chunks();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Waits for every job in flight and hands their results to the loader
+    fn finish_jobs(loader: &mut ThreadedChunkLoader) {
+        drop(loader.thread_pool.replace(ThreadPool::new(1)));
+        loader.process_chunks();
+    }
+
+    fn assert_nothing_loaded(loader: &ThreadedChunkLoader) {
+        assert!(loader.meshes.is_empty());
+        assert!(loader.voxels.is_empty());
+        assert!(loader.pending_uploads.is_empty());
+        assert!(loader.visibility.is_empty());
+        let stats = loader.mesh_arena.stats();
+        assert_eq!(stats.used_bytes(), 0);
+        assert_eq!(stats.vertices.used, 0);
+        assert_eq!(stats.indices.used, 0);
+    }
+
+    #[test]
+    fn reloading_while_jobs_are_in_flight_leaks_no_meshes() {
+        let mut loader = test_loader();
+        let chunks = square(1);
+
+        for &pos in &chunks {
+            loader.queue_load_chunk(pos, Lod::default());
+        }
+        process_until_idle(&mut loader);
+        assert_eq!(loader.meshes.len(), chunks.len());
+
+        // Moving away and straight back again, with generation and meshing jobs still running
+        for _ in 0..3 {
+            for &pos in &chunks {
+                loader.queue_unload_chunk(pos);
+            }
+            for &pos in &chunks {
+                loader.queue_load_chunk(pos, Lod::default());
+            }
+            loader.process_chunks();
+        }
+        for &pos in &chunks {
+            loader.queue_unload_chunk(pos);
+        }
+
+        finish_jobs(&mut loader);
+        assert_nothing_loaded(&loader);
+    }
+
+    #[test]
+    fn meshes_that_do_not_fit_are_retried() {
+        let mut loader = test_loader();
+        loader.mesh_arena = MeshArena::new(&loader.gpu_ctx, 1, 1);
+        loader.mesh_arena.set_max_buffer_size(1);
+        loader.queue_load_chunk((0, 0), Lod::default());
+
+        let start = Instant::now();
+        while loader.mesh_arena.stats().failed_uploads < 2 {
+            assert!(start.elapsed() < TIMEOUT, "the upload was never retried");
+            loader.process_chunks();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(loader.meshes.is_empty());
+        assert!(!loader.is_idle());
+
+        loader.mesh_arena.set_max_buffer_size(u64::MAX);
+        process_until_idle(&mut loader);
+        assert_eq!(loader.meshes.len(), 1);
+    }
+
+    #[test]
+    fn stale_results_are_dropped_after_a_reload() {
+        let mut loader = test_loader();
+        let chunks = square(1);
+
+        for &pos in &chunks {
+            loader.queue_load_chunk(pos, Lod::default());
+        }
+        loader.process_chunks();
+        for &pos in &chunks {
+            loader.queue_unload_chunk(pos);
+            loader.queue_load_chunk(pos, Lod::default());
+        }
+        process_until_idle(&mut loader);
+        finish_jobs(&mut loader);
+
+        // One mesh per chunk, anything the first load produced was thrown away
+        assert_eq!(loader.meshes.len(), chunks.len());
+        let vertices: u64 = loader
+            .meshes
+            .values()
+            .map(|chunk_mesh| chunk_mesh.mesh.get_vertex_count() as u64)
+            .sum();
+        assert_eq!(loader.mesh_arena.stats().vertices.used, vertices);
+
+        for &pos in &chunks {
+            loader.queue_unload_chunk(pos);
+        }
+        assert_nothing_loaded(&loader);
+    }
+
+    fn stored_scales(loader: &ThreadedChunkLoader) -> Vec<usize> {
+        loader.voxels.values().map(|cells| cells.scale()).collect()
+    }
+
+    #[test]
+    fn chunks_moving_away_only_keep_downsampled_voxels() {
+        let mut loader = test_loader();
+        let chunks = square(1);
+
+        for &pos in &chunks {
+            loader.queue_load_chunk(pos, Lod::default());
+        }
+        process_until_idle(&mut loader);
+        assert!(stored_scales(&loader).iter().all(|&scale| scale == 1));
+
+        let far = Lod::for_distance_squared(i32::MAX);
+        for &pos in &chunks {
+            loader.set_chunk_lod(pos, far);
+        }
+        process_until_idle(&mut loader);
+
+        assert_eq!(stored_scales(&loader), vec![far.scale(); chunks.len()]);
+        assert_eq!(loader.meshes.len(), chunks.len());
+    }
+
+    #[test]
+    fn chunks_moving_closer_are_generated_again() {
+        let mut loader = test_loader();
+        let chunks = square(1);
+        let far = Lod::for_distance_squared(i32::MAX);
+
+        for &pos in &chunks {
+            loader.queue_load_chunk(pos, far);
+        }
+        process_until_idle(&mut loader);
+        assert_eq!(stored_scales(&loader), vec![far.scale(); chunks.len()]);
+        let far_vertices = loader.mesh_arena.stats().vertices.used;
+
+        for &pos in &chunks {
+            loader.set_chunk_lod(pos, Lod::default());
+        }
+        // The far meshes stay up while the chunks are generated again
+        assert_eq!(loader.meshes.len(), chunks.len());
+        process_until_idle(&mut loader);
+
+        assert!(stored_scales(&loader).iter().all(|&scale| scale == 1));
+        assert_eq!(loader.meshes.len(), chunks.len());
+        assert!(loader.mesh_arena.stats().vertices.used > far_vertices);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::engine::chunk_system::voxel_data::{BlockType, VoxelData};
+
+/// A chunk's voxels merged into cubes `scale` blocks wide, a cube is solid if any voxel in it is.
+/// Rounding up keeps a coarse chunk's surface at or above the full resolution one next to it
+pub struct CellGrid {
+    scale: usize,
+    width: usize,
+    height: usize,
+    solid: Vec<bool>,
+}
+
+impl CellGrid {
+    pub fn new(voxels: &VoxelData, scale: usize) -> Self {
+        let mut grid = Self::empty(scale);
+        let (width, height) = (grid.width, grid.height);
+
+        for (z, layer) in voxels.data().iter().enumerate() {
+            for (y, row) in layer.iter().enumerate() {
+                for (x, block) in row.iter().enumerate() {
+                    if !matches!(block, BlockType::Air) {
+                        grid.solid[((z / scale) * height + y / scale) * width + x / scale] = true;
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn empty(scale: usize) -> Self {
+        let width = 16 / scale;
+        let height = 256 / scale;
+        Self {
+            scale,
+            width,
+            height,
+            solid: vec![false; width * height * width],
+        }
+    }
+
+    /// The same grid with cubes `scale` blocks wide. Going finer can't bring back detail,
+    /// each cube is split into smaller ones that are all solid or all air
+    pub fn resample(&self, scale: usize) -> Self {
+        let mut grid = Self::empty(scale);
+        // Cells of `self` overlapping cell `i` of the new grid along one axis
+        let covered = |i: usize| (i * scale) / self.scale..=((i + 1) * scale - 1) / self.scale;
+
+        for z in 0..grid.width {
+            for y in 0..grid.height {
+                for x in 0..grid.width {
+                    grid.solid[(z * grid.height + y) * grid.width + x] = covered(z)
+                        .flat_map(|s_z| covered(y).map(move |s_y| (s_y, s_z)))
+                        .flat_map(|(s_y, s_z)| covered(x).map(move |s_x| (s_x, s_y, s_z)))
+                        .any(|(s_x, s_y, s_z)| self.is_solid(s_x, s_y, s_z));
+                }
+            }
+        }
+
+        grid
+    }
+
+    /// Width of a cube in blocks
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_solid(&self, x: usize, y: usize, z: usize) -> bool {
+        self.solid[(z * self.height + y) * self.width + x]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Solid up to a height that changes every block along x
+    fn voxels() -> VoxelData {
+        let mut voxels = Box::new([[[BlockType::Air; 16]; 256]; 16]);
+        for layer in voxels.iter_mut() {
+            for (y, row) in layer.iter_mut().enumerate() {
+                for (x, block) in row.iter_mut().enumerate() {
+                    if y <= 100 + x {
+                        *block = BlockType::Solid;
+                    }
+                }
+            }
+        }
+        VoxelData::new(voxels)
+    }
+
+    fn solid(grid: &CellGrid) -> Vec<bool> {
+        let mut solid = vec![];
+        for z in 0..grid.width() {
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    solid.push(grid.is_solid(x, y, z));
+                }
+            }
+        }
+        solid
+    }
+
+    #[test]
+    fn cell_is_solid_if_any_voxel_is() {
+        let grid = CellGrid::new(&voxels(), 4);
+        assert_eq!((grid.width(), grid.height(), grid.scale()), (4, 64, 4));
+        // Column x = 3 covers blocks 12 to 15, solid up to 115 which is in cell 28
+        assert!(grid.is_solid(3, 28, 0));
+        assert!(!grid.is_solid(3, 29, 0));
+        // Column x = 0 is solid up to 103, cell 25
+        assert!(grid.is_solid(0, 25, 0));
+        assert!(!grid.is_solid(0, 26, 0));
+    }
+
+    #[test]
+    fn downsampling_matches_building_at_that_scale() {
+        let voxels = voxels();
+        let full = CellGrid::new(&voxels, 1);
+        for scale in [2, 4, 8, 16] {
+            let resampled = full.resample(scale);
+            assert_eq!(resampled.scale(), scale);
+            assert_eq!(solid(&resampled), solid(&CellGrid::new(&voxels, scale)));
+        }
+        assert_eq!(
+            solid(&CellGrid::new(&voxels, 2).resample(8)),
+            solid(&CellGrid::new(&voxels, 8))
+        );
+    }
+
+    #[test]
+    fn upsampling_splits_cells_evenly() {
+        let coarse = CellGrid::new(&voxels(), 8);
+        let fine = coarse.resample(2);
+        assert_eq!((fine.width(), fine.height()), (8, 128));
+        for z in 0..fine.width() {
+            for y in 0..fine.height() {
+                for x in 0..fine.width() {
+                    assert_eq!(fine.is_solid(x, y, z), coarse.is_solid(x / 4, y / 4, z / 4));
+                }
+            }
+        }
+        assert_eq!(solid(&fine.resample(8)), solid(&coarse));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const INVALID_EPOCH: u64 = 0;
+
+/// Handed to worker jobs so they can tell if the chunk they were queued for is still wanted
+#[derive(Clone)]
+pub struct ChunkTicket {
+    epoch: u64,
+    current: Arc<AtomicU64>,
+}
+
+impl ChunkTicket {
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.current.load(Ordering::Acquire) != self.epoch
+    }
+}
+
+/// Tracks which load of a chunk is current, every load gets a fresh epoch and unloading invalidates it
+pub struct ChunkEpochs {
+    next_epoch: u64,
+    epochs: HashMap<(i32, i32), Arc<AtomicU64>>,
+}
+
+impl ChunkEpochs {
+    pub fn new() -> Self {
+        Self {
+            next_epoch: INVALID_EPOCH + 1,
+            epochs: HashMap::new(),
+        }
+    }
+
+    pub fn begin(&mut self, pos: (i32, i32)) -> u64 {
+        self.invalidate(pos);
+
+        let epoch = self.next_epoch;
+        self.next_epoch += 1;
+        self.epochs.insert(pos, Arc::new(AtomicU64::new(epoch)));
+        epoch
+    }
+
+    pub fn invalidate(&mut self, pos: (i32, i32)) {
+        if let Some(current) = self.epochs.remove(&pos) {
+            current.store(INVALID_EPOCH, Ordering::Release);
+        }
+    }
+
+    pub fn ticket(&self, pos: (i32, i32)) -> Option<ChunkTicket> {
+        self.epochs.get(&pos).map(|current| ChunkTicket {
+            epoch: current.load(Ordering::Acquire),
+            current: Arc::clone(current),
+        })
+    }
+
+    pub fn is_current(&self, pos: (i32, i32), epoch: u64) -> bool {
+        self.epochs
+            .get(&pos)
+            .map(|current| current.load(Ordering::Acquire) == epoch)
+            .unwrap_or(false)
+    }
+
+    pub fn contains(&self, pos: (i32, i32)) -> bool {
+        self.epochs.contains_key(&pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POS: (i32, i32) = (3, -2);
+
+    #[test]
+    fn ticket_goes_stale_on_unload() {
+        let mut epochs = ChunkEpochs::new();
+        epochs.begin(POS);
+        let ticket = epochs.ticket(POS).unwrap();
+        assert!(!ticket.is_stale());
+
+        epochs.invalidate(POS);
+        assert!(ticket.is_stale());
+        assert!(!epochs.is_current(POS, ticket.epoch()));
+        assert!(epochs.ticket(POS).is_none());
+    }
+
+    #[test]
+    fn reloading_drops_results_of_the_previous_load() {
+        let mut epochs = ChunkEpochs::new();
+        epochs.begin(POS);
+        let old = epochs.ticket(POS).unwrap();
+        epochs.invalidate(POS);
+        epochs.begin(POS);
+        let new = epochs.ticket(POS).unwrap();
+
+        assert_ne!(old.epoch(), new.epoch());
+        assert!(old.is_stale());
+        assert!(!epochs.is_current(POS, old.epoch()));
+        assert!(!new.is_stale());
+        assert!(epochs.is_current(POS, new.epoch()));
+    }
+
+    #[test]
+    fn begin_replaces_a_load_that_was_never_invalidated() {
+        let mut epochs = ChunkEpochs::new();
+        epochs.begin(POS);
+        let old = epochs.ticket(POS).unwrap();
+        let epoch = epochs.begin(POS);
+
+        assert!(old.is_stale());
+        assert!(epochs.is_current(POS, epoch));
+    }
+
+    #[test]
+    fn chunks_have_separate_epochs() {
+        let mut epochs = ChunkEpochs::new();
+        epochs.begin(POS);
+        epochs.begin((0, 0));
+        let ticket = epochs.ticket(POS).unwrap();
+
+        epochs.invalidate((0, 0));
+        assert!(!ticket.is_stale());
+        assert!(epochs.contains(POS));
+        assert!(!epochs.contains((0, 0)));
+    }
+}
@@ -0,0 +1,482 @@
+use crate::engine::chunk_system::lod::Lod;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+/// Where a chunk is in its lifetime, a chunk only ever moves forward through these
+/// except for going back to `Dirty` when one of its neighbours changes or its mesh didn't fit
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkState {
+    Queued,
+    Generating,
+    Generated,
+    Meshing,
+    Meshed,
+    Dirty,
+}
+
+struct ChunkEntry {
+    state: ChunkState,
+    /// Version of the latest mesh job handed out
+    mesh_version: u64,
+    /// Version of the mesh currently being displayed
+    meshed_version: Option<u64>,
+    lod: Lod,
+}
+
+/// Tracks the state of every loaded chunk and decides when they need to be (re)meshed
+pub struct ChunkStates {
+    chunks: HashMap<(i32, i32), ChunkEntry>,
+    /// Chunks waiting to be generated in the order they were queued
+    generation_queue: VecDeque<(i32, i32)>,
+}
+
+impl ChunkStates {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            generation_queue: VecDeque::new(),
+        }
+    }
+
+    pub fn queue(&mut self, pos: (i32, i32), lod: Lod) {
+        if let Entry::Vacant(entry) = self.chunks.entry(pos) {
+            entry.insert(ChunkEntry {
+                state: ChunkState::Queued,
+                mesh_version: 0,
+                meshed_version: None,
+                lod,
+            });
+            self.generation_queue.push_back(pos);
+        }
+    }
+
+    /// Removes the chunk, its loaded neighbours need remeshing since they now border an unknown chunk
+    pub fn remove(&mut self, pos: (i32, i32)) {
+        if let Some(entry) = self.chunks.remove(&pos)
+            && (entry.meshed_version.is_some()
+                || !matches!(entry.state, ChunkState::Queued | ChunkState::Generating))
+        {
+            self.mark_neighbours_dirty(pos);
+        }
+    }
+
+    pub fn needs_mesh(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks
+            .iter()
+            .filter(|(_, entry)| matches!(entry.state, ChunkState::Generated | ChunkState::Dirty))
+            .map(|(pos, _)| *pos)
+    }
+
+    /// Chunks currently in any of `states`
+    pub fn count(&self, states: &[ChunkState]) -> usize {
+        self.chunks
+            .values()
+            .filter(|entry| states.contains(&entry.state))
+            .count()
+    }
+
+    pub fn all_meshed(&self) -> bool {
+        self.chunks
+            .values()
+            .all(|entry| entry.state == ChunkState::Meshed)
+    }
+
+    /// Takes the oldest queued chunk and moves it into `Generating`,
+    /// chunks that were unloaded while waiting are skipped
+    pub fn start_generating(&mut self) -> Option<(i32, i32)> {
+        while let Some(pos) = self.generation_queue.pop_front() {
+            if self.transition(pos, ChunkState::Queued, ChunkState::Generating) {
+                return Some(pos);
+            }
+        }
+        None
+    }
+
+    /// Voxel data arrived, any neighbour that already has a mesh was built without it
+    pub fn finish_generating(&mut self, pos: (i32, i32)) -> bool {
+        let finished = self.transition(pos, ChunkState::Generating, ChunkState::Generated);
+        if finished {
+            self.mark_neighbours_dirty(pos);
+        }
+        finished
+    }
+
+    /// Returns the version the mesh job should be tagged with and the detail to mesh at
+    pub fn start_meshing(&mut self, pos: (i32, i32)) -> Option<(u64, Lod)> {
+        let entry = self.chunks.get_mut(&pos)?;
+        match entry.state {
+            ChunkState::Generated | ChunkState::Dirty => {
+                entry.state = ChunkState::Meshing;
+                entry.mesh_version += 1;
+                Some((entry.mesh_version, entry.lod))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether a finished mesh is newer than the one being displayed and worth uploading
+    pub fn is_newer_mesh(&self, pos: (i32, i32), version: u64) -> bool {
+        self.chunks
+            .get(&pos)
+            .is_some_and(|entry| entry.meshed_version.is_none_or(|meshed| meshed < version))
+    }
+
+    /// The mesh couldn't be uploaded, so the chunk goes back to `Dirty` to be meshed again
+    pub fn fail_meshing(&mut self, pos: (i32, i32), version: u64) {
+        if let Some(entry) = self.chunks.get_mut(&pos)
+            && entry.state == ChunkState::Meshing
+            && entry.mesh_version == version
+        {
+            entry.state = ChunkState::Dirty;
+        }
+    }
+
+    /// Returns whether the finished mesh is newer than the one being displayed and should replace it.
+    /// A chunk that went dirty while meshing keeps the mesh but stays dirty so it gets meshed again.
+    pub fn finish_meshing(&mut self, pos: (i32, i32), version: u64) -> bool {
+        let Some(entry) = self.chunks.get_mut(&pos) else {
+            return false;
+        };
+
+        if entry.meshed_version.is_some_and(|meshed| meshed >= version) {
+            return false;
+        }
+
+        if entry.state == ChunkState::Meshing && entry.mesh_version == version {
+            entry.state = ChunkState::Meshed;
+        }
+        entry.meshed_version = Some(version);
+        true
+    }
+
+    /// Sends a generated chunk back to the generation queue, the mesh it has stays until the new
+    /// one replaces it
+    pub fn regenerate(&mut self, pos: (i32, i32)) {
+        if let Some(entry) = self.chunks.get_mut(&pos)
+            && !matches!(entry.state, ChunkState::Queued | ChunkState::Generating)
+        {
+            entry.state = ChunkState::Queued;
+            self.generation_queue.push_back(pos);
+        }
+    }
+
+    pub fn lod(&self, pos: (i32, i32)) -> Option<Lod> {
+        self.chunks.get(&pos).map(|entry| entry.lod)
+    }
+
+    /// A chunk that was already meshed at a different detail gets meshed again
+    pub fn set_lod(&mut self, pos: (i32, i32), lod: Lod) {
+        if let Some(entry) = self.chunks.get_mut(&pos)
+            && entry.lod != lod
+        {
+            entry.lod = lod;
+            self.mark_dirty(pos);
+        }
+    }
+
+    pub fn mark_dirty(&mut self, pos: (i32, i32)) {
+        if let Some(entry) = self.chunks.get_mut(&pos)
+            && matches!(entry.state, ChunkState::Meshing | ChunkState::Meshed)
+        {
+            entry.state = ChunkState::Dirty;
+        }
+    }
+
+    fn mark_neighbours_dirty(&mut self, (c_x, c_z): (i32, i32)) {
+        for pos in [
+            (c_x + 1, c_z),
+            (c_x - 1, c_z),
+            (c_x, c_z + 1),
+            (c_x, c_z - 1),
+        ] {
+            self.mark_dirty(pos);
+        }
+    }
+
+    fn transition(&mut self, pos: (i32, i32), from: ChunkState, to: ChunkState) -> bool {
+        match self.chunks.get_mut(&pos) {
+            Some(entry) if entry.state == from => {
+                entry.state = to;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POS: (i32, i32) = (0, 0);
+    const NEIGHBOUR: (i32, i32) = (1, 0);
+
+    fn state(states: &ChunkStates, pos: (i32, i32)) -> Option<ChunkState> {
+        states.chunks.get(&pos).map(|entry| entry.state)
+    }
+
+    /// Takes every queued chunk through generation
+    fn generate_all(states: &mut ChunkStates) {
+        while let Some(pos) = states.start_generating() {
+            assert!(states.finish_generating(pos));
+        }
+    }
+
+    fn mesh(states: &mut ChunkStates, pos: (i32, i32)) -> u64 {
+        let (version, _) = states.start_meshing(pos).unwrap();
+        assert!(states.finish_meshing(pos, version));
+        version
+    }
+
+    /// A chunk in `target` with a meshed neighbour next to it
+    fn chunk_in_state(target: ChunkState) -> ChunkStates {
+        let mut states = ChunkStates::new();
+        states.queue(NEIGHBOUR, Lod::default());
+        generate_all(&mut states);
+        mesh(&mut states, NEIGHBOUR);
+
+        states.queue(POS, Lod::default());
+        if target != ChunkState::Queued {
+            states.start_generating();
+        }
+        if matches!(
+            target,
+            ChunkState::Generated | ChunkState::Meshing | ChunkState::Meshed | ChunkState::Dirty
+        ) {
+            states.finish_generating(POS);
+            // The neighbour went dirty when this chunk arrived
+            mesh(&mut states, NEIGHBOUR);
+        }
+        match target {
+            ChunkState::Meshing => {
+                states.start_meshing(POS);
+            }
+            ChunkState::Meshed => {
+                mesh(&mut states, POS);
+            }
+            ChunkState::Dirty => {
+                mesh(&mut states, POS);
+                states.mark_dirty(POS);
+            }
+            _ => (),
+        }
+
+        assert_eq!(state(&states, POS), Some(target));
+        assert_eq!(state(&states, NEIGHBOUR), Some(ChunkState::Meshed));
+        states
+    }
+
+    #[test]
+    fn chunk_goes_from_queued_to_meshed() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        assert_eq!(state(&states, POS), Some(ChunkState::Queued));
+        assert_eq!(states.needs_mesh().count(), 0);
+
+        assert_eq!(states.start_generating(), Some(POS));
+        assert_eq!(state(&states, POS), Some(ChunkState::Generating));
+        assert_eq!(states.start_generating(), None);
+
+        assert!(states.finish_generating(POS));
+        assert_eq!(state(&states, POS), Some(ChunkState::Generated));
+        assert_eq!(states.needs_mesh().collect::<Vec<_>>(), [POS]);
+
+        let (version, lod) = states.start_meshing(POS).unwrap();
+        assert_eq!(lod, Lod::default());
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshing));
+        assert_eq!(states.needs_mesh().count(), 0);
+        assert!(!states.all_meshed());
+
+        assert!(states.finish_meshing(POS, version));
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+        assert!(states.all_meshed());
+    }
+
+    #[test]
+    fn states_can_only_be_skipped_by_the_right_transition() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        assert!(!states.finish_generating(POS));
+        assert!(states.start_meshing(POS).is_none());
+        assert_eq!(state(&states, POS), Some(ChunkState::Queued));
+    }
+
+    #[test]
+    fn arriving_neighbour_dirties_meshed_chunk() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+        mesh(&mut states, POS);
+
+        states.queue(NEIGHBOUR, Lod::default());
+        states.start_generating();
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+
+        states.finish_generating(NEIGHBOUR);
+        assert_eq!(state(&states, POS), Some(ChunkState::Dirty));
+        let mut needs_mesh = states.needs_mesh().collect::<Vec<_>>();
+        needs_mesh.sort();
+        assert_eq!(needs_mesh, [POS, NEIGHBOUR]);
+    }
+
+    #[test]
+    fn chunks_that_are_not_adjacent_stay_meshed() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+        mesh(&mut states, POS);
+
+        states.queue((1, 1), Lod::default());
+        generate_all(&mut states);
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+    }
+
+    #[test]
+    fn out_of_date_mesh_is_rejected() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+
+        let (old, _) = states.start_meshing(POS).unwrap();
+        states.mark_dirty(POS);
+        let (new, _) = states.start_meshing(POS).unwrap();
+        assert!(new > old);
+
+        // The newer job finishes first, the older one must not replace its mesh
+        assert!(states.finish_meshing(POS, new));
+        assert!(!states.finish_meshing(POS, old));
+        assert!(!states.finish_meshing(POS, new));
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+    }
+
+    #[test]
+    fn chunk_dirtied_while_meshing_is_meshed_again() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+
+        let (first, _) = states.start_meshing(POS).unwrap();
+        states.mark_dirty(POS);
+        assert_eq!(state(&states, POS), Some(ChunkState::Dirty));
+
+        // The mesh is still better than none, but the chunk stays dirty
+        assert!(states.finish_meshing(POS, first));
+        assert_eq!(state(&states, POS), Some(ChunkState::Dirty));
+        assert_eq!(states.needs_mesh().collect::<Vec<_>>(), [POS]);
+
+        let (second, _) = states.start_meshing(POS).unwrap();
+        assert!(second > first);
+        assert!(states.finish_meshing(POS, second));
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+    }
+
+    #[test]
+    fn failed_upload_is_meshed_again() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+
+        let (first, _) = states.start_meshing(POS).unwrap();
+        assert!(states.is_newer_mesh(POS, first));
+        states.fail_meshing(POS, first);
+        assert_eq!(state(&states, POS), Some(ChunkState::Dirty));
+        assert_eq!(states.needs_mesh().collect::<Vec<_>>(), [POS]);
+        assert!(!states.all_meshed());
+
+        let (second, _) = states.start_meshing(POS).unwrap();
+        // A failure of an older job doesn't touch the one in flight
+        states.fail_meshing(POS, first);
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshing));
+        assert!(states.finish_meshing(POS, second));
+        assert!(!states.is_newer_mesh(POS, second));
+        assert!(!states.is_newer_mesh(NEIGHBOUR, second));
+    }
+
+    #[test]
+    fn lod_change_remeshes_at_the_new_detail() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+        mesh(&mut states, POS);
+
+        let far = Lod::for_distance_squared(i32::MAX);
+        states.set_lod(POS, far);
+        assert_eq!(state(&states, POS), Some(ChunkState::Dirty));
+        assert_eq!(states.start_meshing(POS).map(|(_, lod)| lod), Some(far));
+    }
+
+    #[test]
+    fn regenerated_chunk_keeps_its_mesh_until_meshed_again() {
+        let mut states = ChunkStates::new();
+        states.queue(POS, Lod::default());
+        generate_all(&mut states);
+        let old = mesh(&mut states, POS);
+
+        states.regenerate(POS);
+        assert_eq!(state(&states, POS), Some(ChunkState::Queued));
+        assert_eq!(states.needs_mesh().count(), 0);
+        assert!(!states.all_meshed());
+        // A stale job for the old mesh can't replace it
+        assert!(!states.finish_meshing(POS, old));
+
+        generate_all(&mut states);
+        assert!(mesh(&mut states, POS) > old);
+        assert_eq!(state(&states, POS), Some(ChunkState::Meshed));
+    }
+
+    #[test]
+    fn chunks_that_are_not_generated_yet_are_not_regenerated() {
+        for target in [ChunkState::Queued, ChunkState::Generating] {
+            let mut states = chunk_in_state(target);
+            states.regenerate(POS);
+            assert_eq!(state(&states, POS), Some(target));
+
+            // Only queued once
+            generate_all(&mut states);
+            assert_eq!(states.start_generating(), None);
+        }
+    }
+
+    #[test]
+    fn unloading_regenerated_chunk_dirties_neighbours() {
+        let mut states = chunk_in_state(ChunkState::Meshed);
+        states.regenerate(POS);
+        states.remove(POS);
+        assert_eq!(state(&states, NEIGHBOUR), Some(ChunkState::Dirty));
+    }
+
+    #[test]
+    fn unload_from_every_state() {
+        let all = [
+            ChunkState::Queued,
+            ChunkState::Generating,
+            ChunkState::Generated,
+            ChunkState::Meshing,
+            ChunkState::Meshed,
+            ChunkState::Dirty,
+        ];
+        for target in all {
+            let mut states = chunk_in_state(target);
+            states.remove(POS);
+            assert_eq!(state(&states, POS), None, "unloading from {target:?}");
+
+            // Neighbours only ever saw voxels that were generated
+            let neighbour = if matches!(target, ChunkState::Queued | ChunkState::Generating) {
+                ChunkState::Meshed
+            } else {
+                ChunkState::Dirty
+            };
+            assert_eq!(
+                state(&states, NEIGHBOUR),
+                Some(neighbour),
+                "unloading from {target:?}"
+            );
+
+            // Jobs still running for the unloaded chunk have nothing to report to
+            assert_eq!(states.start_generating(), None);
+            assert!(!states.finish_generating(POS));
+            assert!(states.start_meshing(POS).is_none());
+            assert!(!states.finish_meshing(POS, 1));
+        }
+    }
+}
@@ -4,22 +4,16 @@ pub enum BlockType {
     Solid,
 }
 
-#[derive(Clone)]
 pub struct VoxelData {
-    chunk_pos: (i32, i32),
     voxels: Box<[[[BlockType; 16]; 256]; 16]>,
 }
 
 impl VoxelData {
-    pub fn new(voxels: Box<[[[BlockType; 16]; 256]; 16]>, chunk_pos: (i32, i32)) -> Self {
-        Self { voxels, chunk_pos }
+    pub fn new(voxels: Box<[[[BlockType; 16]; 256]; 16]>) -> Self {
+        Self { voxels }
     }
 
     pub fn data(&self) -> &[[[BlockType; 16]; 256]; 16] {
         &self.voxels
     }
-
-    pub fn pos(&self) -> (i32, i32) {
-        self.chunk_pos
-    }
 }
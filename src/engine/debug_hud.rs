@@ -0,0 +1,183 @@
+use crate::engine::chunk_system::{ChunkLoadStats, CullMode, CullStats};
+use crate::engine::gpu::MeshArenaStats;
+use crate::engine::render_system::{Anchor, UiBatch};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Frames shown in the frame time graph, FPS is averaged over the same frames
+const FRAME_HISTORY: usize = 120;
+/// Each font texel covers this many pixels
+const TEXT_SCALE: u32 = 2;
+/// Space between the panel's edge and what's on it
+const PADDING: f32 = 6.0;
+const BAR_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 64.0;
+/// Frame time at the top of the graph, anything slower is cut off
+const GRAPH_MAX_MS: f32 = 1000.0 / 30.0;
+/// Frame time of the line across the graph
+const TARGET_MS: f32 = 1000.0 / 60.0;
+
+const MIB: f32 = 1024.0 * 1024.0;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const TARGET_LINE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.4];
+const FAST_FRAME_COLOR: [f32; 4] = [0.3, 0.85, 0.3, 1.0];
+const SLOW_FRAME_COLOR: [f32; 4] = [0.95, 0.8, 0.2, 1.0];
+const DROPPED_FRAME_COLOR: [f32; 4] = [0.95, 0.25, 0.2, 1.0];
+
+/// The last `FRAME_HISTORY` frame times, oldest first
+pub struct FrameTimes {
+    times: VecDeque<Duration>,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self {
+            times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
+    }
+
+    pub fn push(&mut self, frame_time: Duration) {
+        if self.times.len() == FRAME_HISTORY {
+            self.times.pop_front();
+        }
+        self.times.push_back(frame_time);
+    }
+
+    pub fn last(&self) -> Duration {
+        self.times.back().copied().unwrap_or_default()
+    }
+
+    pub fn average(&self) -> Duration {
+        match self.times.len() {
+            0 => Duration::ZERO,
+            len => self.times.iter().sum::<Duration>() / len as u32,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<Duration> {
+        self.times.iter().copied().collect()
+    }
+}
+
+/// Everything the debug HUD shows, see `Engine::debug_info`
+#[derive(Clone, Debug)]
+pub struct DebugInfo {
+    /// Averaged over the frame time history so it stays readable
+    pub fps: f32,
+    pub frame_time: Duration,
+    /// Oldest first
+    pub frame_times: Vec<Duration>,
+    pub camera_pos: (f32, f32, f32),
+    pub camera_chunk: (i32, i32),
+    pub chunks: ChunkLoadStats,
+    pub cull_mode: CullMode,
+    /// Only known when culling on the CPU
+    pub cull_stats: Option<CullStats>,
+    /// GPU memory used by chunk meshes
+    pub geometry: MeshArenaStats,
+}
+
+/// Text overlay in the top left corner with a frame time graph below it, drawn after
+/// post-processing so it stays sharp and isn't tonemapped
+pub struct DebugHud {
+    visible: bool,
+}
+
+impl DebugHud {
+    pub fn new() -> Self {
+        Self { visible: false }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Queues `info` for the next frame, nothing while the HUD is hidden
+    pub fn draw(&self, ui: &mut UiBatch, info: &DebugInfo) {
+        if self.visible {
+            layout(ui, info);
+        }
+    }
+}
+
+fn layout(ui: &mut UiBatch, info: &DebugInfo) {
+    let (x, y, z) = info.camera_pos;
+    let (c_x, c_z) = info.camera_chunk;
+    let chunks = info.chunks;
+    let geometry = info.geometry;
+    let culling = match info.cull_stats {
+        Some(cull_stats) => format!(
+            "{:?}, {} drawn, {} culled",
+            info.cull_mode, cull_stats.drawn, cull_stats.culled
+        ),
+        None => format!("{:?}", info.cull_mode),
+    };
+    let lines = format!(
+        "FPS: {:.0} ({:.1} ms)\n\
+         XYZ: {x:.2} / {y:.2} / {z:.2}\n\
+         Chunk: {c_x}, {c_z}\n\
+         Chunks: {} loaded, {} pending, {} meshing\n\
+         Queued jobs: {}\n\
+         Culling: {culling}\n\
+         Mesh memory: {:.1} / {:.1} MiB\n\
+         Mesh arena: {} free blocks, {:.0}% fragmented, {} failed uploads",
+        info.fps,
+        info.frame_time.as_secs_f32() * 1000.0,
+        chunks.loaded,
+        chunks.pending,
+        chunks.meshing,
+        chunks.queued_jobs,
+        geometry.used_bytes() as f32 / MIB,
+        geometry.buffer_bytes as f32 / MIB,
+        geometry.free_blocks(),
+        geometry.fragmentation() * 100.0,
+        geometry.failed_uploads,
+    );
+
+    let (text_width, text_height) = UiBatch::text_size(&lines, TEXT_SCALE);
+    let graph_width = FRAME_HISTORY as f32 * BAR_WIDTH;
+    let panel_size = (
+        text_width.max(graph_width) + 2.0 * PADDING,
+        text_height + GRAPH_HEIGHT + 3.0 * PADDING,
+    );
+    let (left, top) = ui.anchor(Anchor::TopLeft, (0.0, 0.0), panel_size);
+    ui.rect((left, top), panel_size, PANEL_COLOR);
+    ui.text(
+        (left + PADDING, top + PADDING),
+        TEXT_SCALE,
+        TEXT_COLOR,
+        &lines,
+    );
+
+    // Newest frame on the right, so the graph scrolls to the left
+    let graph_bottom = top + panel_size.1 - PADDING;
+    let graph_left = left + PADDING + graph_width - info.frame_times.len() as f32 * BAR_WIDTH;
+    for (i, frame_time) in info.frame_times.iter().enumerate() {
+        let ms = frame_time.as_secs_f32() * 1000.0;
+        let height = (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+        let color = if ms <= TARGET_MS {
+            FAST_FRAME_COLOR
+        } else if ms <= GRAPH_MAX_MS {
+            SLOW_FRAME_COLOR
+        } else {
+            DROPPED_FRAME_COLOR
+        };
+        ui.rect(
+            (graph_left + i as f32 * BAR_WIDTH, graph_bottom - height),
+            (BAR_WIDTH, height),
+            color,
+        );
+    }
+    let target_y = graph_bottom - TARGET_MS / GRAPH_MAX_MS * GRAPH_HEIGHT;
+    ui.rect(
+        (left + PADDING, target_y),
+        (graph_width, 1.0),
+        TARGET_LINE_COLOR,
+    );
+}
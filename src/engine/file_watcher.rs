@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the watched files are checked, fast enough to feel instant after saving a file
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices files being added, removed or modified under a set of roots by polling their modification
+/// times. Only meant for development, where the handful of watched files makes a scan cheap
+pub struct FileWatcher {
+    roots: Vec<PathBuf>,
+    /// Only files with this extension are watched when set
+    extension: Option<&'static str>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(roots: Vec<PathBuf>, extension: Option<&'static str>) -> Self {
+        let mut watcher = Self {
+            roots,
+            extension,
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.modified = watcher.scan();
+        watcher
+    }
+
+    /// Returns whether anything changed since the last poll that found a change
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = self.scan();
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut modified = HashMap::new();
+        for root in &self.roots {
+            self.scan_path(root, &mut modified);
+        }
+        modified
+    }
+
+    /// Missing paths are skipped, they might be created later
+    fn scan_path(&self, path: &Path, modified: &mut HashMap<PathBuf, SystemTime>) {
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+
+        if metadata.is_dir() {
+            let Ok(entries) = fs::read_dir(path) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                self.scan_path(&entry.path(), modified);
+            }
+        } else if self
+            .extension
+            .is_none_or(|extension| path.extension().is_some_and(|ext| ext == extension))
+            && let Ok(time) = metadata.modified()
+        {
+            modified.insert(path.to_path_buf(), time);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use cgmath::Point3;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box containing all the points, `None` if there are none
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = Point3::from(points.next()?);
+
+        Some(
+            points.fold(Self::new(first, first), |aabb, [x, y, z]| Self {
+                min: Point3::new(aabb.min.x.min(x), aabb.min.y.min(y), aabb.min.z.min(z)),
+                max: Point3::new(aabb.max.x.max(x), aabb.max.y.max(y), aabb.max.z.max(z)),
+            }),
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use crate::engine::gpu::camera::frustum::Frustum;
 use bytemuck::{Pod, Zeroable};
 
 #[repr(C)]
@@ -17,4 +18,12 @@ impl CameraUniform {
             view_proj: view_proj.into(),
         }
     }
+
+    pub fn view_proj(&self) -> cgmath::Matrix4<f32> {
+        self.view_proj.into()
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(self.view_proj.into())
+    }
 }
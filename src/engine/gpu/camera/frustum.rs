@@ -0,0 +1,182 @@
+use crate::engine::gpu::Aabb;
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+
+/// The six clip planes of a view projection, normals point into the frustum
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a wgpu style view projection where clip space depth goes from 0 to w.
+    /// An infinite far plane has no normal, it is swapped for a plane everything is in front of
+    pub fn from_view_proj(view_proj: Matrix4<f32>) -> Self {
+        let row_x = view_proj.row(0);
+        let row_y = view_proj.row(1);
+        let row_z = view_proj.row(2);
+        let row_w = view_proj.row(3);
+
+        let planes = [
+            row_w + row_x, // left
+            row_w - row_x, // right
+            row_w + row_y, // bottom
+            row_w - row_y, // top
+            row_z,         // near
+            row_w - row_z, // far
+        ]
+        .map(|plane| match plane.truncate().magnitude() {
+            magnitude if magnitude > f32::EPSILON => plane / magnitude,
+            _ => Vector4::unit_w(),
+        });
+
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> &[Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    /// Conservative test, boxes near the corners of the frustum may be reported as visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner of the box furthest along the plane normal
+            let pick = |normal: f32, min: f32, max: f32| if normal >= 0.0 { max } else { min };
+            let corner = Vector3::new(
+                pick(plane.x, aabb.min.x, aabb.max.x),
+                pick(plane.y, aabb.min.y, aabb.max.y),
+                pick(plane.z, aabb.min.z, aabb.max.z),
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::gpu::DepthMode;
+    use crate::engine::gpu::camera::perspective::PerspectiveProjection;
+    use crate::engine::gpu::camera::view::View;
+    use cgmath::{Deg, Point3};
+
+    const DEPTH_MODES: [DepthMode; 2] = [DepthMode::Standard, DepthMode::ReverseZ];
+    /// Yaw of a camera looking down +x, -x, +z and -z
+    const YAWS: [f32; 4] = [0.0, 180.0, 90.0, -90.0];
+    /// Half the width of the view this far in front of the camera with a 60 degree square fov
+    const DISTANCE: f32 = 50.0;
+    const HALF_WIDTH: f32 = DISTANCE * 0.577_350_3;
+
+    struct Pose {
+        frustum: Frustum,
+        pos: Point3<f32>,
+        forward: Vector3<f32>,
+        right: Vector3<f32>,
+    }
+
+    impl Pose {
+        fn new(yaw: f32, depth_mode: DepthMode) -> Self {
+            let pos = Point3::new(10.0, 64.0, -20.0);
+            let mut view = View::new();
+            view.set_pose(pos, Deg(yaw).into(), Deg(0.0).into());
+            let perspective = PerspectiveProjection::new(100, 100, depth_mode);
+
+            let forward = view.forward();
+            Self {
+                frustum: Frustum::from_view_proj(perspective.calc_matrix() * view.calc_matrix()),
+                pos,
+                forward,
+                right: forward.cross(Vector3::unit_y()),
+            }
+        }
+
+        /// Cube with sides `2 * half_size` centered `along` the view and `across` to the right
+        fn is_visible(&self, along: f32, across: f32, half_size: f32) -> bool {
+            let center = self.pos + self.forward * along + self.right * across;
+            let half = Vector3::new(half_size, half_size, half_size);
+            self.frustum
+                .intersects_aabb(&Aabb::new(center - half, center + half))
+        }
+    }
+
+    fn for_each_pose(test: impl Fn(&Pose, f32, DepthMode)) {
+        for depth_mode in DEPTH_MODES {
+            for yaw in YAWS {
+                test(&Pose::new(yaw, depth_mode), yaw, depth_mode);
+            }
+        }
+    }
+
+    #[test]
+    fn box_inside_is_visible() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            assert!(
+                pose.is_visible(DISTANCE, 0.0, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+            assert!(
+                pose.is_visible(DISTANCE, HALF_WIDTH - 5.0, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn box_around_camera_is_visible() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            assert!(pose.is_visible(0.0, 0.0, 1.0), "yaw {yaw} {depth_mode:?}");
+        });
+    }
+
+    #[test]
+    fn box_behind_is_culled() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            assert!(
+                !pose.is_visible(-DISTANCE, 0.0, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+            assert!(!pose.is_visible(-3.0, 0.0, 2.0), "yaw {yaw} {depth_mode:?}");
+        });
+    }
+
+    #[test]
+    fn box_beyond_far_plane_is_culled_unless_far_plane_is_infinite() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            // The standard projection's far plane is at 1000
+            assert_eq!(
+                pose.is_visible(1100.0, 0.0, 2.0),
+                depth_mode.is_reversed(),
+                "yaw {yaw} {depth_mode:?}"
+            );
+            assert!(pose.is_visible(990.0, 0.0, 2.0), "yaw {yaw} {depth_mode:?}");
+        });
+    }
+
+    #[test]
+    fn box_straddling_side_plane_is_visible() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            assert!(
+                pose.is_visible(DISTANCE, HALF_WIDTH, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+            assert!(
+                pose.is_visible(DISTANCE, -HALF_WIDTH, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn box_past_side_plane_is_culled() {
+        for_each_pose(|pose, yaw, depth_mode| {
+            assert!(
+                !pose.is_visible(DISTANCE, HALF_WIDTH + 10.0, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+            assert!(
+                !pose.is_visible(DISTANCE, -HALF_WIDTH - 10.0, 2.0),
+                "yaw {yaw} {depth_mode:?}"
+            );
+        });
+    }
+}
@@ -1,8 +1,8 @@
-use crate::engine::gpu::GpuCtx;
+use crate::engine::gpu::{DepthMode, GpuCtx};
 use crate::engine::gpu::camera::camera_uniform::CameraUniform;
 use crate::engine::gpu::camera::perspective::PerspectiveProjection;
 use crate::engine::gpu::camera::view::View;
-use cgmath::{Angle, InnerSpace};
+use cgmath::{Deg, Matrix4, Point3, SquareMatrix, Transform, Vector3};
 use std::time::Duration;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
@@ -12,9 +12,12 @@ use wgpu::{
 
 mod camera_movement_buffer;
 mod camera_uniform;
+mod frustum;
 mod perspective;
 mod view;
 pub use camera_movement_buffer::CameraMovementBuffer;
+pub use frustum::Frustum;
+pub use perspective::OPENGL_TO_WGPU_MATRIX;
 
 pub struct Camera {
     view: View,
@@ -24,9 +27,9 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(gpu_ctx: &GpuCtx, width: u32, height: u32) -> Self {
+    pub fn new(gpu_ctx: &GpuCtx, width: u32, height: u32, depth_mode: DepthMode) -> Self {
         let view = View::new();
-        let perspective = PerspectiveProjection::new(width, height);
+        let perspective = PerspectiveProjection::new(width, height, depth_mode);
         let uniform_data =
             CameraUniform::from_matrices(view.calc_matrix(), perspective.calc_matrix());
 
@@ -78,10 +81,59 @@ impl Camera {
         self.view.get_pos()
     }
 
+    /// Yaw 0 looks down +x and turns towards +z, positive pitch looks up
+    pub fn set_pose(&mut self, pos: Point3<f32>, yaw: Deg<f32>, pitch: Deg<f32>) {
+        self.view.set_pose(pos, yaw.into(), pitch.into());
+    }
+
+    pub fn get_forward(&self) -> Vector3<f32> {
+        self.view.forward()
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         self.perspective.resize(width, height);
     }
 
+    pub fn get_fov_y_deg(&self) -> f32 {
+        self.perspective.get_fov_y_deg()
+    }
+
+    pub fn set_fov_y_deg(&mut self, fov_y_deg: f32) {
+        self.perspective.set_fov_y_deg(fov_y_deg);
+    }
+
+    pub fn set_clip_planes(&mut self, z_near: f32, z_far: f32) {
+        self.perspective.set_clip_planes(z_near, z_far);
+    }
+
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.perspective.set_depth_mode(depth_mode);
+    }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        CameraUniform::from_matrices(self.view.calc_matrix(), self.perspective.calc_matrix())
+            .view_proj()
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        CameraUniform::from_matrices(self.view.calc_matrix(), self.perspective.calc_matrix())
+            .frustum()
+    }
+
+    /// World space corners of the part of the view between `z_near` and `z_far`
+    pub fn frustum_corners(&self, z_near: f32, z_far: f32) -> [Point3<f32>; 8] {
+        let view_proj =
+            self.perspective.calc_standard_matrix(z_near, z_far) * self.view.calc_matrix();
+        let inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity());
+
+        std::array::from_fn(|i| {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            inv_view_proj.transform_point(Point3::new(x, y, z))
+        })
+    }
+
     pub fn update_buffer(&self, gpu_ctx: &GpuCtx) {
         let uniform_data =
             CameraUniform::from_matrices(self.view.calc_matrix(), self.perspective.calc_matrix());
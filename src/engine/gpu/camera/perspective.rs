@@ -1,27 +1,35 @@
-use cgmath::{Deg, Matrix4, perspective};
+use crate::engine::gpu::DepthMode;
+use cgmath::{Angle, Deg, Matrix4, perspective};
 
+/// Maps OpenGL's -1 to 1 clip space depth onto wgpu's 0 to 1, `Matrix4::new` takes columns
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0
 );
 
+const MIN_FOV_Y_DEG: f32 = 30.0;
+const MAX_FOV_Y_DEG: f32 = 120.0;
+
 pub struct PerspectiveProjection {
     aspect: f32,
     fov_y_deg: f32,
     z_near: f32,
+    /// Unused with reverse-Z, the far plane is infinitely far away
     z_far: f32,
+    depth_mode: DepthMode,
 }
 
 impl PerspectiveProjection {
-    pub fn new(width: u32, height: u32) -> Self {
+    pub fn new(width: u32, height: u32, depth_mode: DepthMode) -> Self {
         Self {
             aspect: width as f32 / height as f32,
             fov_y_deg: 60.0,
-            z_near: 0.01,
-            z_far: 100.0,
+            z_near: 0.1,
+            z_far: 1000.0,
+            depth_mode,
         }
     }
 
@@ -29,8 +37,46 @@ impl PerspectiveProjection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn get_fov_y_deg(&self) -> f32 {
+        self.fov_y_deg
+    }
+
+    pub fn set_fov_y_deg(&mut self, fov_y_deg: f32) {
+        self.fov_y_deg = fov_y_deg.clamp(MIN_FOV_Y_DEG, MAX_FOV_Y_DEG);
+    }
+
+    pub fn set_clip_planes(&mut self, z_near: f32, z_far: f32) {
+        self.z_near = z_near.max(f32::EPSILON);
+        self.z_far = z_far.max(self.z_near * 2.0);
+    }
+
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX
-            * perspective(Deg(self.fov_y_deg), self.aspect, self.z_near, self.z_far)
+        match self.depth_mode {
+            DepthMode::Standard => self.calc_standard_matrix(self.z_near, self.z_far),
+            DepthMode::ReverseZ => self.calc_reverse_z_infinite_matrix(),
+        }
+    }
+
+    /// Standard depth between any two planes, regardless of the depth mode in use
+    pub fn calc_standard_matrix(&self, z_near: f32, z_far: f32) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * perspective(Deg(self.fov_y_deg), self.aspect, z_near, z_far)
+    }
+
+    /// Depth is `z_near / distance`, 1 on the near plane and approaching 0 at infinity
+    fn calc_reverse_z_infinite_matrix(&self) -> Matrix4<f32> {
+        let focal_length = 1.0 / (Deg(self.fov_y_deg) / 2.0).tan();
+
+        #[rustfmt::skip]
+        let matrix = Matrix4::new(
+            focal_length / self.aspect, 0.0, 0.0, 0.0,
+            0.0, focal_length, 0.0, 0.0,
+            0.0, 0.0, 0.0, -1.0,
+            0.0, 0.0, self.z_near, 0.0,
+        );
+        matrix
     }
 }
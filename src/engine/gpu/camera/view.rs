@@ -2,7 +2,6 @@ use crate::engine::gpu::camera::CameraMovementBuffer;
 use cgmath::num_traits::FloatConst;
 use cgmath::{Angle, InnerSpace, Matrix4, Point3, Rad, Vector3};
 use cgmath::{Deg, Zero};
-use std::f32::consts::PI;
 use std::time::Duration;
 
 pub struct View {
@@ -56,15 +55,22 @@ impl View {
         self.pos += buffer.speed * (xz + y) * dt;
     }
 
+    /// Pitch is clamped like mouse look is, so the view never flips over
+    pub fn set_pose(&mut self, pos: Point3<f32>, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.pos = pos;
+        self.yaw = yaw;
+        self.pitch = Rad(pitch.0.clamp(Rad::from(Deg(-89.0)).0, Rad::from(Deg(89.0)).0));
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.pos, self.forward(), Vector3::unit_y())
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
         let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
         let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
 
-        Matrix4::look_to_rh(
-            self.pos,
-            Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin),
-            Vector3::unit_y(),
-        )
+        Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin)
     }
 
     pub fn get_pos(&self) -> (f32, f32, f32) {
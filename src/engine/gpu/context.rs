@@ -4,14 +4,22 @@ pub struct GpuCtx {
     pub device: Device,
     pub queue: Queue,
     pub surface_format: TextureFormat,
+    /// MSAA sample counts the main pass's attachments support, ascending and always starting at 1
+    pub sample_counts: Vec<u32>,
 }
 
 impl crate::engine::gpu::GpuCtx {
-    pub fn new(device: Device, queue: Queue, surface_format: TextureFormat) -> Self {
+    pub fn new(
+        device: Device,
+        queue: Queue,
+        surface_format: TextureFormat,
+        sample_counts: Vec<u32>,
+    ) -> Self {
         Self {
             device,
             queue,
             surface_format,
+            sample_counts,
         }
     }
 }
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use wgpu::CompareFunction;
+
+/// How depth is laid out in the depth buffer, every pipeline that reads or writes depth has to agree on it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DepthMode {
+    /// Near plane at 0 and far plane at 1
+    Standard,
+    /// Near plane at 1 and an infinitely distant far plane at 0. Float precision is densest near 0,
+    /// which cancels out the perspective divide and stops distant surfaces from z-fighting
+    ReverseZ,
+}
+
+impl DepthMode {
+    pub fn next(self) -> Self {
+        match self {
+            DepthMode::Standard => DepthMode::ReverseZ,
+            DepthMode::ReverseZ => DepthMode::Standard,
+        }
+    }
+
+    pub fn is_reversed(self) -> bool {
+        self == DepthMode::ReverseZ
+    }
+
+    pub fn compare_function(self) -> CompareFunction {
+        match self {
+            DepthMode::Standard => CompareFunction::LessEqual,
+            DepthMode::ReverseZ => CompareFunction::Greater,
+        }
+    }
+
+    /// Depth of nothing drawn, the furthest possible value
+    pub fn clear_value(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        }
+    }
+
+    /// Sets the `reverse_z` override in shaders that need to know which way depth goes
+    pub fn pipeline_constants(self) -> HashMap<String, f64> {
+        HashMap::from([(
+            "reverse_z".to_string(),
+            if self.is_reversed() { 1.0 } else { 0.0 },
+        )])
+    }
+}
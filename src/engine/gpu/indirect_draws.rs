@@ -0,0 +1,59 @@
+use crate::engine::gpu::{GpuCtx, GpuMesh};
+use wgpu::util::DrawIndexedIndirectArgs;
+use wgpu::{Buffer, BufferDescriptor, BufferUsages};
+
+const DRAW_ARGS_SIZE: u64 = size_of::<DrawIndexedIndirectArgs>() as u64;
+
+/// Draw commands for a set of arena meshes so they can be drawn with a single `multi_draw_indexed_indirect`
+pub struct IndirectDraws {
+    buffer: Buffer,
+    capacity: u64,
+    count: u32,
+}
+
+impl IndirectDraws {
+    pub fn new(gpu_ctx: &GpuCtx, capacity: u64) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            buffer: create_indirect_buffer(gpu_ctx, capacity),
+            capacity,
+            count: 0,
+        }
+    }
+
+    pub fn write<'a>(&mut self, gpu_ctx: &GpuCtx, meshes: impl IntoIterator<Item = &'a GpuMesh>) {
+        let bytes: Vec<u8> = meshes
+            .into_iter()
+            .flat_map(|mesh| mesh.draw_args().as_bytes().to_vec())
+            .collect();
+        let count = bytes.len() as u64 / DRAW_ARGS_SIZE;
+
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.buffer = create_indirect_buffer(gpu_ctx, self.capacity);
+        }
+
+        if !bytes.is_empty() {
+            gpu_ctx.queue.write_buffer(&self.buffer, 0, &bytes);
+        }
+        self.count = count as u32;
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+fn create_indirect_buffer(gpu_ctx: &GpuCtx, capacity: u64) -> Buffer {
+    gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: capacity * DRAW_ARGS_SIZE,
+        usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -1,7 +1,6 @@
-use crate::engine::gpu::GpuCtx;
 use crate::engine::gpu::vertex::Vertex;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{Buffer, BufferUsages, RenderPass};
+use std::ops::Range;
+use wgpu::util::DrawIndexedIndirectArgs;
 
 pub struct CpuMesh<V: Vertex> {
     vertices: Vec<V>,
@@ -13,47 +12,79 @@ impl<V: Vertex> CpuMesh<V> {
         Self { vertices, indices }
     }
 
-    pub fn to_gpu_mesh(&self, gpu_ctx: &GpuCtx) -> Option<GpuMesh> {
-        if !self.vertices.is_empty() && !self.indices.is_empty() {
-            let vertex_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&self.vertices),
-                usage: BufferUsages::VERTEX,
-            });
-
-            let index_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&self.indices),
-                usage: BufferUsages::INDEX,
-            });
-
-            Some(GpuMesh {
-                vertex_buffer,
-                index_buffer,
-                index_count: self.indices.len() as u32,
-            })
-        } else {
-            None
-        }
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty() || self.indices.is_empty()
+    }
+
+    pub fn vertex_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.vertices)
+    }
+
+    pub fn index_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.indices)
+    }
+
+    pub fn byte_size(&self) -> u64 {
+        (self.vertex_bytes().len() + self.index_bytes().len()) as u64
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.indices.len() as u32
     }
 }
 
+/// A mesh living inside a [`MeshArena`](crate::engine::gpu::MeshArena), it is only a range into the arena's buffers
+#[derive(Copy, Clone)]
 pub struct GpuMesh {
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    base_vertex: u32,
+    vertex_count: u32,
+    first_index: u32,
     index_count: u32,
 }
 
 impl GpuMesh {
-    pub fn get_vertices(&self) -> &Buffer {
-        &self.vertex_buffer
+    pub fn new(base_vertex: u32, vertex_count: u32, first_index: u32, index_count: u32) -> Self {
+        Self {
+            base_vertex,
+            vertex_count,
+            first_index,
+            index_count,
+        }
+    }
+
+    pub fn get_base_vertex(&self) -> u32 {
+        self.base_vertex
+    }
+
+    pub fn get_vertex_count(&self) -> u32 {
+        self.vertex_count
     }
 
-    pub fn get_indices(&self) -> &Buffer {
-        &self.index_buffer
+    pub fn get_index_range(&self) -> Range<u32> {
+        self.first_index..self.first_index + self.index_count
     }
 
-    pub fn get_index_count(&self) -> u32 {
-        self.index_count
+    /// A mesh drawing only `indices` of this one, relative to its first index
+    pub fn sub_mesh(&self, indices: Range<u32>) -> GpuMesh {
+        debug_assert!(indices.end <= self.index_count);
+        Self {
+            first_index: self.first_index + indices.start,
+            index_count: indices.end - indices.start,
+            ..*self
+        }
+    }
+
+    pub fn draw_args(&self) -> DrawIndexedIndirectArgs {
+        DrawIndexedIndirectArgs {
+            index_count: self.index_count,
+            instance_count: 1,
+            first_index: self.first_index,
+            base_vertex: self.base_vertex as i32,
+            first_instance: 0,
+        }
     }
 }
@@ -0,0 +1,327 @@
+use crate::engine::gpu::{CpuMesh, GpuCtx, GpuMesh, Vertex};
+use crate::engine::utils::{FreeListAllocator, FreeListStats};
+use std::fmt;
+use std::marker::PhantomData;
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor};
+
+const INDEX_SIZE: u64 = size_of::<u32>() as u64;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MeshArenaStats {
+    pub vertices: FreeListStats,
+    pub indices: FreeListStats,
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    /// Both buffers in full, including the free space
+    pub buffer_bytes: u64,
+    /// Uploads turned away because the arena couldn't grow any further
+    pub failed_uploads: u64,
+}
+
+impl MeshArenaStats {
+    pub fn used_bytes(&self) -> u64 {
+        self.vertex_bytes + self.index_bytes
+    }
+
+    pub fn free_blocks(&self) -> usize {
+        self.vertices.free_blocks + self.indices.free_blocks
+    }
+
+    /// The worse of the vertex and index buffer fragmentation
+    pub fn fragmentation(&self) -> f32 {
+        self.vertices
+            .fragmentation()
+            .max(self.indices.fragmentation())
+    }
+}
+
+/// Growing a buffer enough for the mesh would take it past the device's max buffer size
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArenaFull;
+
+impl fmt::Display for ArenaFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The mesh arena can't grow past the device's max buffer size"
+        )
+    }
+}
+
+impl std::error::Error for ArenaFull {}
+
+/// One large vertex buffer and one large index buffer shared by every mesh,
+/// meshes get sub-allocated out of them so drawing needs no buffer rebinding
+pub struct MeshArena<V: Vertex> {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    vertices: FreeListAllocator,
+    indices: FreeListAllocator,
+    max_buffer_size: u64,
+    failed_uploads: u64,
+    _vertex: PhantomData<V>,
+}
+
+impl<V: Vertex> MeshArena<V> {
+    pub fn new(gpu_ctx: &GpuCtx, vertex_capacity: u64, index_capacity: u64) -> Self {
+        Self {
+            vertex_buffer: create_arena_buffer(
+                gpu_ctx,
+                vertex_capacity * vertex_size::<V>(),
+                BufferUsages::VERTEX,
+            ),
+            index_buffer: create_arena_buffer(
+                gpu_ctx,
+                index_capacity * INDEX_SIZE,
+                BufferUsages::INDEX,
+            ),
+            vertices: FreeListAllocator::new(vertex_capacity),
+            indices: FreeListAllocator::new(index_capacity),
+            max_buffer_size: gpu_ctx.device.limits().max_buffer_size,
+            failed_uploads: 0,
+            _vertex: PhantomData,
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &Buffer {
+        &self.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> &Buffer {
+        &self.index_buffer
+    }
+
+    /// Returns `Ok(None)` for empty meshes. Failed uploads leave the arena as it was and are
+    /// counted in the stats
+    pub fn upload(
+        &mut self,
+        gpu_ctx: &GpuCtx,
+        mesh: &CpuMesh<V>,
+    ) -> Result<Option<GpuMesh>, ArenaFull> {
+        if mesh.is_empty() {
+            return Ok(None);
+        }
+
+        let vertex_count = mesh.vertex_count() as u64;
+        let index_count = mesh.index_count() as u64;
+
+        let Some(base_vertex) = self.alloc_vertices(gpu_ctx, vertex_count) else {
+            self.failed_uploads += 1;
+            return Err(ArenaFull);
+        };
+        let Some(first_index) = self.alloc_indices(gpu_ctx, index_count) else {
+            self.vertices.free(base_vertex, vertex_count);
+            self.failed_uploads += 1;
+            return Err(ArenaFull);
+        };
+
+        gpu_ctx.queue.write_buffer(
+            &self.vertex_buffer,
+            base_vertex * vertex_size::<V>(),
+            mesh.vertex_bytes(),
+        );
+        gpu_ctx.queue.write_buffer(
+            &self.index_buffer,
+            first_index * INDEX_SIZE,
+            mesh.index_bytes(),
+        );
+
+        Ok(Some(GpuMesh::new(
+            base_vertex as u32,
+            vertex_count as u32,
+            first_index as u32,
+            index_count as u32,
+        )))
+    }
+
+    pub fn release(&mut self, mesh: GpuMesh) {
+        self.vertices.free(
+            mesh.get_base_vertex() as u64,
+            mesh.get_vertex_count() as u64,
+        );
+
+        let indices = mesh.get_index_range();
+        self.indices
+            .free(indices.start as u64, (indices.end - indices.start) as u64);
+    }
+
+    /// Lets tests run out of space without filling a real device's buffers
+    #[cfg(test)]
+    pub fn set_max_buffer_size(&mut self, max_buffer_size: u64) {
+        self.max_buffer_size = max_buffer_size;
+    }
+
+    pub fn stats(&self) -> MeshArenaStats {
+        let vertices = self.vertices.stats();
+        let indices = self.indices.stats();
+
+        MeshArenaStats {
+            vertices,
+            indices,
+            vertex_bytes: vertices.used * vertex_size::<V>(),
+            index_bytes: indices.used * INDEX_SIZE,
+            buffer_bytes: self.vertex_buffer.size() + self.index_buffer.size(),
+            failed_uploads: self.failed_uploads,
+        }
+    }
+
+    fn alloc_vertices(&mut self, gpu_ctx: &GpuCtx, count: u64) -> Option<u64> {
+        if let Some(offset) = self.vertices.alloc(count) {
+            return Some(offset);
+        }
+
+        let new_capacity = grown_capacity(
+            self.max_buffer_size,
+            self.vertices.capacity(),
+            count,
+            vertex_size::<V>(),
+        )?;
+        self.vertex_buffer = grow_buffer(
+            gpu_ctx,
+            &self.vertex_buffer,
+            new_capacity * vertex_size::<V>(),
+            BufferUsages::VERTEX,
+        );
+        self.vertices.grow(new_capacity);
+        self.vertices.alloc(count)
+    }
+
+    fn alloc_indices(&mut self, gpu_ctx: &GpuCtx, count: u64) -> Option<u64> {
+        if let Some(offset) = self.indices.alloc(count) {
+            return Some(offset);
+        }
+
+        let new_capacity = grown_capacity(
+            self.max_buffer_size,
+            self.indices.capacity(),
+            count,
+            INDEX_SIZE,
+        )?;
+        self.index_buffer = grow_buffer(
+            gpu_ctx,
+            &self.index_buffer,
+            new_capacity * INDEX_SIZE,
+            BufferUsages::INDEX,
+        );
+        self.indices.grow(new_capacity);
+        self.indices.alloc(count)
+    }
+}
+
+fn vertex_size<V: Vertex>() -> u64 {
+    size_of::<V>() as u64
+}
+
+/// Doubles the capacity until `required` more elements fit, capped by the device's max buffer size
+fn grown_capacity(
+    max_buffer_size: u64,
+    capacity: u64,
+    required: u64,
+    element_size: u64,
+) -> Option<u64> {
+    let max_capacity = max_buffer_size / element_size;
+    let mut new_capacity = capacity.max(1);
+    while new_capacity < capacity + required {
+        new_capacity *= 2;
+    }
+
+    let new_capacity = new_capacity.min(max_capacity);
+    (new_capacity >= capacity + required).then_some(new_capacity)
+}
+
+fn create_arena_buffer(gpu_ctx: &GpuCtx, size: u64, usage: BufferUsages) -> Buffer {
+    gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size,
+        usage: usage | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+/// Creates a bigger buffer and copies the old contents over so existing meshes stay valid
+fn grow_buffer(gpu_ctx: &GpuCtx, old: &Buffer, size: u64, usage: BufferUsages) -> Buffer {
+    let new = create_arena_buffer(gpu_ctx, size, usage);
+
+    let mut encoder = gpu_ctx
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(old, 0, &new, 0, old.size());
+    gpu_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+    new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::render_system::RenderSystem;
+    use bytemuck::{Pod, Zeroable};
+    use wgpu::{VertexBufferLayout, VertexStepMode};
+
+    #[repr(C)]
+    #[derive(Copy, Clone, Pod, Zeroable)]
+    struct TestVertex([f32; 4]);
+
+    impl Vertex for TestVertex {
+        fn layout<'a>() -> VertexBufferLayout<'a> {
+            VertexBufferLayout {
+                array_stride: size_of::<Self>() as u64,
+                step_mode: VertexStepMode::Vertex,
+                attributes: &[],
+            }
+        }
+    }
+
+    fn quad() -> CpuMesh<TestVertex> {
+        CpuMesh::new(vec![TestVertex([0.0; 4]); 4], vec![0, 1, 2, 1, 3, 2])
+    }
+
+    #[test]
+    fn capacity_doubles_until_it_fits() {
+        assert_eq!(grown_capacity(u64::MAX, 8, 1, 4), Some(16));
+        assert_eq!(grown_capacity(u64::MAX, 8, 30, 4), Some(64));
+        assert_eq!(grown_capacity(u64::MAX, 0, 3, 4), Some(4));
+    }
+
+    #[test]
+    fn capacity_is_capped_by_the_max_buffer_size() {
+        // Doubling would reach 64 elements, but only 40 fit in 160 bytes
+        assert_eq!(grown_capacity(160, 32, 1, 4), Some(40));
+        assert_eq!(grown_capacity(160, 32, 8, 4), Some(40));
+        assert_eq!(grown_capacity(160, 32, 9, 4), None);
+        assert_eq!(grown_capacity(160, 40, 1, 4), None);
+    }
+
+    #[test]
+    fn full_arena_rejects_uploads() {
+        let gpu_ctx = RenderSystem::new_headless(16, 16).get_gpu_ctx();
+        let mut arena = MeshArena::<TestVertex>::new(&gpu_ctx, 4, 6);
+        // Room for two quads worth of vertices and indices, but not three
+        arena.max_buffer_size = 2 * 4 * size_of::<TestVertex>() as u64;
+
+        let first = arena.upload(&gpu_ctx, &quad()).unwrap().unwrap();
+        let second = arena.upload(&gpu_ctx, &quad()).unwrap().unwrap();
+        assert_eq!(arena.stats().buffer_bytes, 2 * (4 * 16 + 6 * 4));
+        assert!(matches!(arena.upload(&gpu_ctx, &quad()), Err(ArenaFull)));
+        assert_eq!(arena.stats().failed_uploads, 1);
+        // Nothing is left allocated by the failed upload
+        assert_eq!(arena.stats().vertices.used, 8);
+        assert_eq!(arena.stats().indices.used, 12);
+
+        arena.release(first);
+        assert!(arena.upload(&gpu_ctx, &quad()).unwrap().is_some());
+        assert_eq!(arena.stats().failed_uploads, 1);
+        arena.release(second);
+    }
+
+    #[test]
+    fn empty_meshes_take_no_space() {
+        let gpu_ctx = RenderSystem::new_headless(16, 16).get_gpu_ctx();
+        let mut arena = MeshArena::<TestVertex>::new(&gpu_ctx, 4, 6);
+        let empty = CpuMesh::<TestVertex>::new(Vec::new(), Vec::new());
+
+        assert!(matches!(arena.upload(&gpu_ctx, &empty), Ok(None)));
+        assert_eq!(arena.stats().vertices.used, 0);
+        assert_eq!(arena.stats().failed_uploads, 0);
+    }
+}
@@ -1,9 +1,22 @@
+mod aabb;
 mod camera;
 mod context;
+mod depth_mode;
+mod indirect_draws;
 mod mesh;
+mod mesh_arena;
+mod shader_file;
 mod vertex;
 
-pub use camera::{Camera, CameraMovementBuffer};
+pub use aabb::Aabb;
+pub use camera::{Camera, CameraMovementBuffer, Frustum, OPENGL_TO_WGPU_MATRIX};
 pub use context::GpuCtx;
+pub use depth_mode::DepthMode;
+pub use indirect_draws::IndirectDraws;
 pub use mesh::{CpuMesh, GpuMesh};
+pub use mesh_arena::{MeshArena, MeshArenaStats};
+pub use shader_file::ShaderFile;
+pub(crate) use shader_file::shader_file;
+#[cfg(feature = "hot-reload")]
+pub use shader_file::{ShaderError, validate};
 pub use vertex::Vertex;
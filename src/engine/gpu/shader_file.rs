@@ -0,0 +1,92 @@
+use crate::engine::gpu::GpuCtx;
+use wgpu::{ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+#[cfg(feature = "hot-reload")]
+use std::{fmt, fs, io};
+
+/// A WGSL file compiled into the binary, `$path` is relative to the crate root
+macro_rules! shader_file {
+    ($path:literal) => {
+        $crate::engine::gpu::ShaderFile::new(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/", $path),
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)),
+        )
+    };
+}
+pub(crate) use shader_file;
+
+/// Source of a shader that's baked in at build time, but can be read from the source tree again
+/// with the `hot-reload` feature
+#[derive(Copy, Clone)]
+pub struct ShaderFile {
+    path: &'static str,
+    embedded: &'static str,
+}
+
+impl ShaderFile {
+    pub const fn new(path: &'static str, embedded: &'static str) -> Self {
+        Self { path, embedded }
+    }
+
+    /// Always uses the source the binary was built with, so startup can't fail on a half written file
+    pub fn create_module(&self, gpu_ctx: &GpuCtx) -> ShaderModule {
+        create_module(gpu_ctx, self.path, self.embedded)
+    }
+
+    /// Reads the file from disk. Invalid WGSL only shows up as an error inside `validate`
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_module(&self, gpu_ctx: &GpuCtx) -> Result<ShaderModule, ShaderError> {
+        let source = fs::read_to_string(self.path).map_err(|err| ShaderError::Io {
+            path: self.path,
+            err,
+        })?;
+        Ok(create_module(gpu_ctx, self.path, &source))
+    }
+}
+
+fn create_module(gpu_ctx: &GpuCtx, path: &str, source: &str) -> ShaderModule {
+    gpu_ctx
+        .device
+        .create_shader_module(ShaderModuleDescriptor {
+            label: Some(path),
+            source: ShaderSource::Wgsl(source.into()),
+        })
+}
+
+#[cfg(feature = "hot-reload")]
+#[derive(Debug)]
+pub enum ShaderError {
+    Io { path: &'static str, err: io::Error },
+    /// WGSL that failed to parse or validate, or a pipeline that doesn't match its shader
+    Invalid(wgpu::Error),
+}
+
+#[cfg(feature = "hot-reload")]
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "Failed to read {path}: {err}"),
+            Self::Invalid(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+impl std::error::Error for ShaderError {}
+
+/// Runs `create` inside a validation error scope, so broken shaders and pipelines are returned
+/// as an error instead of taking down the device
+#[cfg(feature = "hot-reload")]
+pub fn validate<T>(
+    gpu_ctx: &GpuCtx,
+    create: impl FnOnce() -> Result<T, ShaderError>,
+) -> Result<T, ShaderError> {
+    gpu_ctx.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = create();
+    let validation_error = pollster::block_on(gpu_ctx.device.pop_error_scope());
+
+    match validation_error {
+        Some(err) => Err(ShaderError::Invalid(err)),
+        None => result,
+    }
+}
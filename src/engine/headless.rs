@@ -0,0 +1,90 @@
+use crate::engine::chunk_system::{ChunkSystem, CullMode, ThreadedChunkLoader, built_in_resources};
+use crate::engine::render_system::RenderSystem;
+use crate::engine::update_view_distance;
+use crate::engine::world_clock::WorldClock;
+use cgmath::{Deg, Point3};
+use image::RgbaImage;
+use std::time::{Duration, Instant};
+
+/// Loading a few chunks takes well under a second in release builds, debug builds on a software
+/// adapter are a lot slower
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Everything that decides what a headless frame looks like
+#[derive(Clone, Debug)]
+pub struct HeadlessScene {
+    pub width: u32,
+    pub height: u32,
+    pub camera_pos: [f32; 3],
+    /// 0 looks down +x and turns towards +z
+    pub camera_yaw_deg: f32,
+    /// Positive looks up
+    pub camera_pitch_deg: f32,
+    /// 0 is midnight, 0.5 noon
+    pub time_of_day: f32,
+    pub render_distance: i32,
+}
+
+impl Default for HeadlessScene {
+    fn default() -> Self {
+        Self {
+            width: 320,
+            height: 240,
+            camera_pos: [0.0, 262.0, 0.0],
+            camera_yaw_deg: 45.0,
+            camera_pitch_deg: -20.0,
+            time_of_day: 0.4,
+            render_distance: 4,
+        }
+    }
+}
+
+/// Renders `scene` into an offscreen texture and reads it back. Only built-in textures are used and
+/// every chunk is loaded before drawing, so the same scene always gives the same image on one driver
+pub fn render_headless(scene: &HeadlessScene) -> RgbaImage {
+    let mut render_system = RenderSystem::new_headless(scene.width, scene.height);
+    let [x, y, z] = scene.camera_pos;
+    render_system.set_camera_pose(
+        Point3::new(x, y, z),
+        Deg(scene.camera_yaw_deg),
+        Deg(scene.camera_pitch_deg),
+    );
+
+    let block_resources = built_in_resources();
+    let chunk_loader = ThreadedChunkLoader::new(render_system.get_gpu_ctx(), &block_resources);
+    let mut chunk_system = ChunkSystem::new(
+        render_system.get_gpu_ctx(),
+        chunk_loader,
+        render_system.get_depth_mode(),
+        render_system.get_sample_count(),
+        &block_resources,
+    );
+    chunk_system.set_render_distance(scene.render_distance);
+    chunk_system.player_moved(x.floor() as i32, z.floor() as i32);
+    // GPU culling tests against the previous frame's depth, which a single frame doesn't have
+    chunk_system.set_cull_mode(CullMode::Cpu);
+    update_view_distance(&mut render_system, &chunk_system);
+
+    let mut world_clock = WorldClock::new();
+    world_clock.set_time_of_day(scene.time_of_day);
+    render_system.set_sun_and_moon(world_clock.sun_direction(), world_clock.moon_direction());
+
+    let start = Instant::now();
+    while !chunk_system.is_idle() {
+        assert!(
+            start.elapsed() < LOAD_TIMEOUT,
+            "Chunks didn't finish loading within {LOAD_TIMEOUT:?}"
+        );
+        chunk_system.handle_chunk_jobs();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    chunk_system.cull_chunks(
+        &render_system.get_camera_frustum(),
+        render_system.get_camera_pos(),
+    );
+    render_system.render(&[&chunk_system]);
+    render_system
+        .read_pixels()
+        .expect("Headless rendering always reads back an offscreen target")
+}
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+/// Declares `Action` together with `ACTIONS`, so every action has a name and default bindings
+macro_rules! actions {
+    ($($(#[$attr:meta])* $action:ident = $name:literal, $bindings:expr;)*) => {
+        /// Everything the player can do, systems ask `InputSystem` about these instead of about keys
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($(#[$attr])* $action,)*
+        }
+
+        /// Name of each action in the config file, in the order it's written, with its default
+        /// bindings
+        const ACTIONS: &[(Action, &str, &[Binding])] = &[$((Action::$action, $name, &$bindings),)*];
+    };
+}
+
+actions! {
+    MoveForward = "move_forward", [key(KeyCode::KeyW)];
+    MoveBackward = "move_backward", [key(KeyCode::KeyS)];
+    MoveLeft = "move_left", [key(KeyCode::KeyA)];
+    MoveRight = "move_right", [key(KeyCode::KeyD)];
+    Jump = "jump", [key(KeyCode::Space)];
+    Crouch = "crouch", [key(KeyCode::ControlLeft)];
+    Sprint = "sprint", [key(KeyCode::ShiftLeft)];
+    Break = "break", [mouse(MouseButton::Left)];
+    Place = "place", [mouse(MouseButton::Right)];
+    HotbarSlot1 = "hotbar_slot_1", [key(KeyCode::Digit1)];
+    HotbarSlot2 = "hotbar_slot_2", [key(KeyCode::Digit2)];
+    HotbarSlot3 = "hotbar_slot_3", [key(KeyCode::Digit3)];
+    HotbarSlot4 = "hotbar_slot_4", [key(KeyCode::Digit4)];
+    HotbarSlot5 = "hotbar_slot_5", [key(KeyCode::Digit5)];
+    HotbarSlot6 = "hotbar_slot_6", [key(KeyCode::Digit6)];
+    HotbarSlot7 = "hotbar_slot_7", [key(KeyCode::Digit7)];
+    HotbarSlot8 = "hotbar_slot_8", [key(KeyCode::Digit8)];
+    HotbarSlot9 = "hotbar_slot_9", [key(KeyCode::Digit9)];
+    HotbarNext = "hotbar_next", [scroll(Input::ScrollDown)];
+    HotbarPrevious = "hotbar_previous", [scroll(Input::ScrollUp)];
+    /// Pushes buttons in menus, on release like most UIs
+    Click = "click", [mouse(MouseButton::Left)];
+    Pause = "pause", [key(KeyCode::Escape)];
+    ToggleDebug = "toggle_debug", [key(KeyCode::F3)];
+    CycleDebugRenderMode = "cycle_debug_render_mode", [key(KeyCode::F4)];
+    Screenshot = "screenshot", [key(KeyCode::F2)];
+    HighResScreenshot = "high_res_screenshot", [shift_key(KeyCode::F2)];
+    RenderDistanceUp = "render_distance_up", [key(KeyCode::Equal)];
+    RenderDistanceDown = "render_distance_down", [key(KeyCode::Minus)];
+    FovUp = "fov_up", [key(KeyCode::BracketRight)];
+    FovDown = "fov_down", [key(KeyCode::BracketLeft)];
+    CycleCullMode = "cycle_cull_mode", [key(KeyCode::KeyC)];
+    CycleDepthMode = "cycle_depth_mode", [key(KeyCode::KeyZ)];
+    CycleShadowCascades = "cycle_shadow_cascades", [key(KeyCode::KeyX)];
+    CycleShadowResolution = "cycle_shadow_resolution", [key(KeyCode::KeyV)];
+    CycleAnisotropy = "cycle_anisotropy", [key(KeyCode::KeyG)];
+    CycleMsaa = "cycle_msaa", [key(KeyCode::KeyM)];
+    ToggleBloom = "toggle_bloom", [key(KeyCode::F5)];
+    ToggleTonemapping = "toggle_tonemapping", [key(KeyCode::F6)];
+    ToggleFxaa = "toggle_fxaa", [key(KeyCode::F7)];
+    ToggleGammaCorrection = "toggle_gamma_correction", [key(KeyCode::F8)];
+    ExposureUp = "exposure_up", [key(KeyCode::Quote)];
+    ExposureDown = "exposure_down", [key(KeyCode::Semicolon)];
+    TimeForward = "time_forward", [key(KeyCode::Period)];
+    TimeBackward = "time_backward", [key(KeyCode::Comma)];
+    ToggleTimePaused = "toggle_time_paused", [key(KeyCode::KeyP)];
+}
+
+impl Action {
+    pub const HOTBAR_SLOTS: [Action; 9] = [
+        Action::HotbarSlot1,
+        Action::HotbarSlot2,
+        Action::HotbarSlot3,
+        Action::HotbarSlot4,
+        Action::HotbarSlot5,
+        Action::HotbarSlot6,
+        Action::HotbarSlot7,
+        Action::HotbarSlot8,
+        Action::HotbarSlot9,
+    ];
+
+    /// `None` for actions that work whether or not a menu is open
+    fn context(self) -> Option<InputContext> {
+        match self {
+            Action::MoveForward
+            | Action::MoveBackward
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::Jump
+            | Action::Crouch
+            | Action::Sprint
+            | Action::Break
+            | Action::Place
+            | Action::HotbarSlot1
+            | Action::HotbarSlot2
+            | Action::HotbarSlot3
+            | Action::HotbarSlot4
+            | Action::HotbarSlot5
+            | Action::HotbarSlot6
+            | Action::HotbarSlot7
+            | Action::HotbarSlot8
+            | Action::HotbarSlot9
+            | Action::HotbarNext
+            | Action::HotbarPrevious => Some(InputContext::Gameplay),
+            Action::Click => Some(InputContext::Menu),
+            _ => None,
+        }
+    }
+}
+
+/// Menus and gameplay each only see their own actions, so they can share inputs like the left
+/// mouse button without a click in a menu also breaking a block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputContext {
+    Gameplay,
+    Menu,
+}
+
+/// Something on the keyboard or mouse an action can be bound to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A line of scrolling, pressed and released at once
+    ScrollUp,
+    ScrollDown,
+}
+
+/// With `shift` the input only counts while Shift is held, and then takes precedence over
+/// bindings of the same input without it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub input: Input,
+    pub shift: bool,
+}
+
+const fn key(code: KeyCode) -> Binding {
+    Binding {
+        input: Input::Key(code),
+        shift: false,
+    }
+}
+
+const fn shift_key(code: KeyCode) -> Binding {
+    Binding {
+        input: Input::Key(code),
+        shift: true,
+    }
+}
+
+const fn mouse(button: MouseButton) -> Binding {
+    Binding {
+        input: Input::Mouse(button),
+        shift: false,
+    }
+}
+
+const fn scroll(input: Input) -> Binding {
+    Binding {
+        input,
+        shift: false,
+    }
+}
+
+/// Keys the config file can name, by winit's name for them
+const KEYS: [(&str, KeyCode); 92] = [
+    ("KeyA", KeyCode::KeyA),
+    ("KeyB", KeyCode::KeyB),
+    ("KeyC", KeyCode::KeyC),
+    ("KeyD", KeyCode::KeyD),
+    ("KeyE", KeyCode::KeyE),
+    ("KeyF", KeyCode::KeyF),
+    ("KeyG", KeyCode::KeyG),
+    ("KeyH", KeyCode::KeyH),
+    ("KeyI", KeyCode::KeyI),
+    ("KeyJ", KeyCode::KeyJ),
+    ("KeyK", KeyCode::KeyK),
+    ("KeyL", KeyCode::KeyL),
+    ("KeyM", KeyCode::KeyM),
+    ("KeyN", KeyCode::KeyN),
+    ("KeyO", KeyCode::KeyO),
+    ("KeyP", KeyCode::KeyP),
+    ("KeyQ", KeyCode::KeyQ),
+    ("KeyR", KeyCode::KeyR),
+    ("KeyS", KeyCode::KeyS),
+    ("KeyT", KeyCode::KeyT),
+    ("KeyU", KeyCode::KeyU),
+    ("KeyV", KeyCode::KeyV),
+    ("KeyW", KeyCode::KeyW),
+    ("KeyX", KeyCode::KeyX),
+    ("KeyY", KeyCode::KeyY),
+    ("KeyZ", KeyCode::KeyZ),
+    ("Digit0", KeyCode::Digit0),
+    ("Digit1", KeyCode::Digit1),
+    ("Digit2", KeyCode::Digit2),
+    ("Digit3", KeyCode::Digit3),
+    ("Digit4", KeyCode::Digit4),
+    ("Digit5", KeyCode::Digit5),
+    ("Digit6", KeyCode::Digit6),
+    ("Digit7", KeyCode::Digit7),
+    ("Digit8", KeyCode::Digit8),
+    ("Digit9", KeyCode::Digit9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Escape", KeyCode::Escape),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("ArrowUp", KeyCode::ArrowUp),
+    ("ArrowDown", KeyCode::ArrowDown),
+    ("ArrowLeft", KeyCode::ArrowLeft),
+    ("ArrowRight", KeyCode::ArrowRight),
+    ("ShiftLeft", KeyCode::ShiftLeft),
+    ("ShiftRight", KeyCode::ShiftRight),
+    ("ControlLeft", KeyCode::ControlLeft),
+    ("ControlRight", KeyCode::ControlRight),
+    ("AltLeft", KeyCode::AltLeft),
+    ("AltRight", KeyCode::AltRight),
+    ("CapsLock", KeyCode::CapsLock),
+    ("Minus", KeyCode::Minus),
+    ("Equal", KeyCode::Equal),
+    ("BracketLeft", KeyCode::BracketLeft),
+    ("BracketRight", KeyCode::BracketRight),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Quote", KeyCode::Quote),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("Backslash", KeyCode::Backslash),
+    ("Backquote", KeyCode::Backquote),
+    ("Numpad0", KeyCode::Numpad0),
+    ("Numpad1", KeyCode::Numpad1),
+    ("Numpad2", KeyCode::Numpad2),
+    ("Numpad3", KeyCode::Numpad3),
+    ("Numpad4", KeyCode::Numpad4),
+    ("Numpad5", KeyCode::Numpad5),
+    ("Numpad6", KeyCode::Numpad6),
+    ("Numpad7", KeyCode::Numpad7),
+    ("Numpad8", KeyCode::Numpad8),
+    ("Numpad9", KeyCode::Numpad9),
+    ("NumpadEnter", KeyCode::NumpadEnter),
+];
+
+/// Mouse inputs the config file can name
+const MOUSE_INPUTS: [(&str, Input); 7] = [
+    ("MouseLeft", Input::Mouse(MouseButton::Left)),
+    ("MouseRight", Input::Mouse(MouseButton::Right)),
+    ("MouseMiddle", Input::Mouse(MouseButton::Middle)),
+    ("MouseBack", Input::Mouse(MouseButton::Back)),
+    ("MouseForward", Input::Mouse(MouseButton::Forward)),
+    ("ScrollUp", Input::ScrollUp),
+    ("ScrollDown", Input::ScrollDown),
+];
+
+const SHIFT_PREFIX: &str = "Shift+";
+
+/// Which inputs trigger each action. Saved as `action = binding, binding` lines, `#` starts a
+/// comment line and an empty right hand side unbinds the action
+#[derive(Clone)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self {
+            bindings: ACTIONS
+                .iter()
+                .map(|&(action, _, bindings)| (action, bindings.to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Actions the file leaves out keep their default bindings. Lines that can't be parsed are
+    /// skipped and described in the returned errors, the rest of the file still applies
+    pub fn load(path: &Path) -> io::Result<(Self, Vec<String>)> {
+        let contents = fs::read_to_string(path)?;
+        let mut action_map = Self::new();
+        let mut errors = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            match parse_line(line) {
+                Ok(Some((action, bindings))) => {
+                    action_map.bindings.insert(action, bindings);
+                }
+                Ok(None) => (),
+                Err(message) => {
+                    errors.push(format!("{} line {}: {message}", path.display(), index + 1));
+                }
+            }
+        }
+
+        Ok((action_map, errors))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut contents = String::from(
+            "# action = binding, binding\n\
+             # Keys use winit's names (KeyW, Space, F3, ...), prefix them with Shift+ to only\n\
+             # count while Shift is held. The mouse has MouseLeft, MouseRight, MouseMiddle,\n\
+             # MouseBack, MouseForward, ScrollUp and ScrollDown\n",
+        );
+        for &(action, name, _) in ACTIONS {
+            let bindings = self
+                .get_bindings(action)
+                .iter()
+                .filter_map(|&binding| format_binding(binding))
+                .collect::<Vec<_>>();
+            writeln!(contents, "{name} = {}", bindings.join(", ")).unwrap();
+        }
+        fs::write(path, contents)
+    }
+
+    pub fn get_bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Actions `input` triggers in `context`. Bindings that need Shift win over the rest while
+    /// it's held
+    pub fn actions_for(&self, input: Input, shift: bool, context: InputContext) -> Vec<Action> {
+        let matching = |needs_shift: bool| {
+            self.bindings
+                .iter()
+                .filter(|(action, _)| action.context().is_none_or(|c| c == context))
+                .filter(|(_, bindings)| {
+                    bindings
+                        .iter()
+                        .any(|binding| binding.input == input && binding.shift == needs_shift)
+                })
+                .map(|(&action, _)| action)
+                .collect::<Vec<_>>()
+        };
+
+        if shift {
+            let actions = matching(true);
+            if !actions.is_empty() {
+                return actions;
+            }
+        }
+        matching(false)
+    }
+}
+
+/// `Ok(None)` for blank and comment lines
+fn parse_line(line: &str) -> Result<Option<(Action, Vec<Binding>)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let Some((name, value)) = line.split_once('=') else {
+        return Err(format!("expected `action = bindings`, found {line:?}"));
+    };
+    let name = name.trim();
+    let Some(&(action, _, _)) = ACTIONS.iter().find(|(_, n, _)| *n == name) else {
+        return Err(format!("unknown action {name:?}"));
+    };
+
+    let bindings = value
+        .split(',')
+        .map(str::trim)
+        .filter(|binding| !binding.is_empty())
+        .map(|binding| parse_binding(binding).ok_or_else(|| format!("unknown binding {binding:?}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some((action, bindings)))
+}
+
+fn parse_binding(binding: &str) -> Option<Binding> {
+    let (name, shift) = match binding.strip_prefix(SHIFT_PREFIX) {
+        Some(name) => (name, true),
+        None => (binding, false),
+    };
+    let input = KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|&(_, code)| Input::Key(code))
+        .or_else(|| {
+            MOUSE_INPUTS
+                .iter()
+                .find(|(input_name, _)| *input_name == name)
+                .map(|&(_, input)| input)
+        })?;
+    Some(Binding { input, shift })
+}
+
+/// `None` for keys that can't be named in the config file
+fn format_binding(binding: Binding) -> Option<String> {
+    let name = match binding.input {
+        Input::Key(code) => KEYS.iter().find(|&&(_, key)| key == code)?.0,
+        input => MOUSE_INPUTS.iter().find(|&&(_, i)| i == input)?.0,
+    };
+    let prefix = if binding.shift { SHIFT_PREFIX } else { "" };
+    Some(format!("{prefix}{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    /// Unique to the test process, so tests running at the same time don't share files
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("action_map_{}_{name}.txt", std::process::id()))
+    }
+
+    #[test]
+    fn every_action_is_listed_once() {
+        for (index, &(action, _, _)) in ACTIONS.iter().enumerate() {
+            assert_eq!(action as usize, index, "{action:?} is out of place");
+        }
+        let names = ACTIONS
+            .iter()
+            .map(|(_, name, _)| name)
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), ACTIONS.len());
+    }
+
+    #[test]
+    fn every_action_has_default_bindings() {
+        let action_map = ActionMap::new();
+        for &(action, _, _) in ACTIONS {
+            assert!(!action_map.get_bindings(action).is_empty(), "{action:?}");
+        }
+    }
+
+    #[test]
+    fn broken_lines_are_skipped() {
+        let path = temp_path("broken_lines");
+        fs::write(
+            &path,
+            "move_forward = ArrowUp\n\
+             fly = KeyF\n\
+             jump = KeyJ, Nope\n\
+             no equals sign\n\
+             # move_left = KeyQ\n\
+             move_left = KeyJ\n",
+        )
+        .unwrap();
+        let (action_map, errors) = ActionMap::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            action_map.get_bindings(Action::MoveForward),
+            [key(KeyCode::ArrowUp)]
+        );
+        assert_eq!(
+            action_map.get_bindings(Action::MoveLeft),
+            [key(KeyCode::KeyJ)]
+        );
+        // The line with a bad binding is skipped as a whole
+        assert_eq!(action_map.get_bindings(Action::Jump), [key(KeyCode::Space)]);
+
+        assert_eq!(errors.len(), 3);
+        for (error, line) in errors.iter().zip([2, 3, 4]) {
+            assert!(error.contains(&format!("line {line}:")), "{error}");
+        }
+    }
+
+    #[test]
+    fn every_named_input_parses_back() {
+        let inputs = KEYS
+            .iter()
+            .map(|&(name, code)| (name, Input::Key(code)))
+            .chain(MOUSE_INPUTS);
+        for (name, input) in inputs {
+            for shift in [false, true] {
+                let binding = Binding { input, shift };
+                let formatted = format_binding(binding).unwrap();
+                assert_eq!(formatted.strip_prefix(SHIFT_PREFIX).is_some(), shift);
+                assert!(formatted.ends_with(name));
+                assert_eq!(parse_binding(&formatted), Some(binding));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_bindings() {
+        assert_eq!(parse_binding("KeyW"), Some(key(KeyCode::KeyW)));
+        assert_eq!(parse_binding("Shift+F2"), Some(shift_key(KeyCode::F2)));
+        assert_eq!(parse_binding("ScrollUp"), Some(scroll(Input::ScrollUp)));
+        assert_eq!(parse_binding("MouseLeft"), Some(mouse(MouseButton::Left)));
+        for unknown in [
+            "",
+            "keyw",
+            "W",
+            "Shift+",
+            "Shift+Nope",
+            "Ctrl+KeyW",
+            "Shift+Shift+KeyW",
+        ] {
+            assert_eq!(parse_binding(unknown), None, "{unknown:?}");
+        }
+    }
+
+    #[test]
+    fn keys_without_a_name_are_not_formatted() {
+        assert_eq!(format_binding(key(KeyCode::F13)), None);
+        assert_eq!(format_binding(mouse(MouseButton::Other(9))), None);
+    }
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let mut action_map = ActionMap::new();
+        action_map.bindings.insert(
+            Action::MoveForward,
+            vec![key(KeyCode::KeyW), key(KeyCode::ArrowUp)],
+        );
+        action_map
+            .bindings
+            .insert(Action::Place, vec![scroll(Input::ScrollUp)]);
+        action_map.bindings.insert(Action::Screenshot, Vec::new());
+
+        let path = temp_path("round_trip");
+        action_map.save(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let (loaded, errors) = ActionMap::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(contents.contains("\nhigh_res_screenshot = Shift+F2\n"));
+        assert!(contents.contains("\nplace = ScrollUp\n"));
+        assert!(contents.contains("\nscreenshot = \n"));
+        assert!(errors.is_empty(), "{errors:?}");
+        for &(action, _, _) in ACTIONS {
+            assert_eq!(
+                loaded.get_bindings(action),
+                action_map.get_bindings(action),
+                "{action:?}"
+            );
+        }
+        assert!(loaded.get_bindings(Action::Screenshot).is_empty());
+    }
+
+    #[test]
+    fn menus_and_gameplay_share_the_left_mouse_button() {
+        let action_map = ActionMap::new();
+        let left = Input::Mouse(MouseButton::Left);
+        assert_eq!(
+            action_map.actions_for(left, false, InputContext::Gameplay),
+            [Action::Break]
+        );
+        assert_eq!(
+            action_map.actions_for(left, false, InputContext::Menu),
+            [Action::Click]
+        );
+
+        let escape = Input::Key(KeyCode::Escape);
+        for context in [InputContext::Gameplay, InputContext::Menu] {
+            assert_eq!(
+                action_map.actions_for(escape, false, context),
+                [Action::Pause]
+            );
+        }
+    }
+
+    #[test]
+    fn shift_bindings_only_win_in_their_context() {
+        let mut action_map = ActionMap::new();
+        action_map
+            .bindings
+            .insert(Action::Place, vec![shift_key(KeyCode::F2)]);
+        let f2 = Input::Key(KeyCode::F2);
+
+        let mut gameplay = action_map.actions_for(f2, true, InputContext::Gameplay);
+        gameplay.sort_by_key(|&action| action as usize);
+        assert_eq!(gameplay, [Action::Place, Action::HighResScreenshot]);
+        assert_eq!(
+            action_map.actions_for(f2, true, InputContext::Menu),
+            [Action::HighResScreenshot]
+        );
+    }
+}
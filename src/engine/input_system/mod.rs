@@ -1,35 +1,86 @@
 use crate::engine::gpu::CameraMovementBuffer;
-use std::collections::HashMap;
-use winit::event::{ElementState, KeyEvent};
+use std::collections::{HashMap, HashSet};
+use winit::event::{KeyEvent, MouseButton, MouseScrollDelta};
 use winit::keyboard::{KeyCode, PhysicalKey};
 
+mod action_map;
+use action_map::Input;
+pub use action_map::{Action, ActionMap, InputContext};
+
+/// Scroll wheels that report pixels are turned into lines of this many pixels
+const PIXELS_PER_SCROLL_LINE: f32 = 40.0;
+const SPRINT_MULTIPLIER: f32 = 3.0;
+
+/// Turns keyboard and mouse events into actions through an `ActionMap`. Presses and releases
+/// collect between calls to `update`, which makes them visible to the `is_pressed` and
+/// `is_released` queries until the next call
 pub struct InputSystem {
+    action_map: ActionMap,
+    context: InputContext,
     camera_movement_buffer: CameraMovementBuffer,
-    states: HashMap<KeyCode, bool>,
+    /// Inputs that are down, with the actions pressing them triggered
+    held: HashMap<Input, Vec<Action>>,
+    pending_presses: HashMap<Action, u32>,
+    pending_releases: HashSet<Action>,
+    presses: HashMap<Action, u32>,
+    releases: HashSet<Action>,
+    /// Window pixels from the top left corner
+    cursor_pos: (f32, f32),
+    /// Partial lines left over from touchpads
+    scroll: f32,
 }
 
 impl InputSystem {
-    pub fn new() -> Self {
+    pub fn new(action_map: ActionMap) -> Self {
         Self {
+            action_map,
+            context: InputContext::Gameplay,
             camera_movement_buffer: CameraMovementBuffer::new(),
-            states: HashMap::new(),
+            held: HashMap::new(),
+            pending_presses: HashMap::new(),
+            pending_releases: HashSet::new(),
+            presses: HashMap::new(),
+            releases: HashSet::new(),
+            cursor_pos: (0.0, 0.0),
+            scroll: 0.0,
         }
     }
 
     pub fn handle_key_event(&mut self, event: KeyEvent) {
-        match event {
-            KeyEvent {
-                physical_key,
-                state,
-                ..
-            } => match physical_key {
-                PhysicalKey::Code(code) => {
-                    self.states.insert(code, state.is_pressed());
-                }
-                _ => (),
-            },
-            _ => (),
+        if let PhysicalKey::Code(code) = event.physical_key {
+            if event.state.is_pressed() {
+                self.press(Input::Key(code));
+            } else {
+                self.release(Input::Key(code));
+            }
+        }
+    }
+
+    pub fn handle_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        if pressed {
+            self.press(Input::Mouse(button));
+        } else {
+            self.release(Input::Mouse(button));
+        }
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_SCROLL_LINE,
+        };
+
+        let lines = self.scroll.trunc();
+        self.scroll -= lines;
+        let input = if lines > 0.0 {
+            Input::ScrollUp
+        } else {
+            Input::ScrollDown
         };
+        for _ in 0..lines.abs() as u32 {
+            self.press(input);
+            self.release(input);
+        }
     }
 
     pub fn handle_mouse_move(&mut self, x: f32, y: f32) {
@@ -37,27 +88,96 @@ impl InputSystem {
         self.camera_movement_buffer.rotate.1 -= y;
     }
 
-    pub fn get_movement(&mut self) -> CameraMovementBuffer {
-        if *self.states.get(&KeyCode::KeyW).unwrap_or(&false) {
-            self.camera_movement_buffer.forward = 1.0;
-        }
-        if *self.states.get(&KeyCode::KeyS).unwrap_or(&false) {
-            self.camera_movement_buffer.backward = 1.0;
-        }
-        if *self.states.get(&KeyCode::KeyA).unwrap_or(&false) {
-            self.camera_movement_buffer.left = 1.0;
+    pub fn handle_cursor_move(&mut self, x: f32, y: f32) {
+        self.cursor_pos = (x, y);
+    }
+
+    /// Inputs pressed from now on only trigger actions of `context`, held ones stay as they are
+    pub fn set_context(&mut self, context: InputContext) {
+        self.context = context;
+    }
+
+    /// Key repeats are ignored, an input has to be released before it presses its actions again
+    fn press(&mut self, input: Input) {
+        if self.held.contains_key(&input) {
+            return;
         }
-        if *self.states.get(&KeyCode::KeyD).unwrap_or(&false) {
-            self.camera_movement_buffer.right = 1.0;
+        let actions = self
+            .action_map
+            .actions_for(input, self.is_shift_down(), self.context);
+        for &action in &actions {
+            if !self.is_held(action) {
+                *self.pending_presses.entry(action).or_default() += 1;
+            }
         }
-        if *self.states.get(&KeyCode::ControlLeft).unwrap_or(&false) {
-            self.camera_movement_buffer.down = 1.0;
+        self.held.insert(input, actions);
+    }
+
+    /// Actions stay held while any other input bound to them is still down
+    fn release(&mut self, input: Input) {
+        let Some(actions) = self.held.remove(&input) else {
+            return;
+        };
+        for action in actions {
+            if !self.is_held(action) {
+                self.pending_releases.insert(action);
+            }
         }
-        if *self.states.get(&KeyCode::Space).unwrap_or(&false) {
-            self.camera_movement_buffer.up = 1.0;
+    }
+
+    fn is_shift_down(&self) -> bool {
+        [KeyCode::ShiftLeft, KeyCode::ShiftRight]
+            .iter()
+            .any(|&code| self.held.contains_key(&Input::Key(code)))
+    }
+
+    /// Starts a new frame of presses and releases
+    pub fn update(&mut self) {
+        self.presses = std::mem::take(&mut self.pending_presses);
+        self.releases = std::mem::take(&mut self.pending_releases);
+    }
+
+    /// Went down since the previous `update`
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.presses.contains_key(&action)
+    }
+
+    /// Times the action went down since the previous `update`, each line scrolled is a press
+    pub fn get_press_count(&self, action: Action) -> u32 {
+        self.presses.get(&action).copied().unwrap_or(0)
+    }
+
+    /// Presses of `positive` minus presses of `negative`, for actions that step a setting
+    pub fn get_press_delta(&self, positive: Action, negative: Action) -> i32 {
+        self.get_press_count(positive) as i32 - self.get_press_count(negative) as i32
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.values().any(|actions| actions.contains(&action))
+    }
+
+    /// Came back up since the previous `update`
+    pub fn is_released(&self, action: Action) -> bool {
+        self.releases.contains(&action)
+    }
+
+    pub fn get_cursor_pos(&self) -> (f32, f32) {
+        self.cursor_pos
+    }
+
+    pub fn get_movement(&mut self) -> CameraMovementBuffer {
+        let axis = |action| if self.is_held(action) { 1.0 } else { 0.0 };
+        let mut buffer = self.camera_movement_buffer;
+        buffer.forward = axis(Action::MoveForward);
+        buffer.backward = axis(Action::MoveBackward);
+        buffer.left = axis(Action::MoveLeft);
+        buffer.right = axis(Action::MoveRight);
+        buffer.up = axis(Action::Jump);
+        buffer.down = axis(Action::Crouch);
+        if self.is_held(Action::Sprint) {
+            buffer.speed *= SPRINT_MULTIPLIER;
         }
 
-        let buffer = self.camera_movement_buffer;
         self.camera_movement_buffer.reset();
         buffer
     }
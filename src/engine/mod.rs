@@ -1,22 +1,68 @@
 mod chunk_system;
+mod debug_hud;
+#[cfg(feature = "hot-reload")]
+mod file_watcher;
 mod gpu;
+mod headless;
 mod input_system;
 mod render_system;
+mod screenshot;
+mod ui;
 pub mod utils;
+mod world_clock;
+pub use headless::{HeadlessScene, render_headless};
 
-use crate::engine::chunk_system::{ChunkSystem, ThreadedChunkLoader};
-use crate::engine::input_system::InputSystem;
-use crate::engine::render_system::RenderSystem;
+use crate::engine::chunk_system::{
+    BlockResources, ChunkSystem, ThreadedChunkLoader, load_resource_packs,
+};
+#[cfg(feature = "hot-reload")]
+use crate::engine::chunk_system::DEFAULT_PACK_DIR;
+use crate::engine::debug_hud::{DebugHud, DebugInfo, FrameTimes};
+#[cfg(feature = "hot-reload")]
+use crate::engine::file_watcher::FileWatcher;
+use crate::engine::input_system::{Action, ActionMap, InputContext, InputSystem};
+use crate::engine::render_system::{PostEffect, RenderSystem};
+use crate::engine::screenshot::save_screenshot;
+use crate::engine::ui::{Hotbar, PauseMenu, PauseMenuAction, draw_crosshair};
+use crate::engine::world_clock::WorldClock;
+use std::io::ErrorKind;
+use std::path::Path;
+#[cfg(feature = "hot-reload")]
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use winit::event::KeyEvent;
+use winit::event::{KeyEvent, MouseButton, MouseScrollDelta};
 use winit::window::{CursorGrabMode, Window};
 
+const FOV_STEP_DEG: f32 = 5.0;
+/// Half a stop
+const EXPOSURE_STEP: f32 = std::f32::consts::SQRT_2;
+/// An hour of the day
+const TIME_OF_DAY_STEP: f32 = 1.0 / 24.0;
+const WORLD_CLOCK_PATH: &str = "world/clock.txt";
+const INPUT_CONFIG_PATH: &str = "config/input.txt";
+/// Directories and zip files in here are stacked on top of the built-in resource pack
+const RESOURCE_PACKS_PATH: &str = "resource_packs";
+const SCREENSHOTS_PATH: &str = "screenshots";
+/// Each side of a high resolution screenshot is this many times the window's
+const HIGH_RES_SCREENSHOT_SCALE: u32 = 4;
+
 pub struct Engine {
     window: Arc<Window>,
     render_system: RenderSystem,
     chunk_system: ChunkSystem<ThreadedChunkLoader>,
     input_system: InputSystem,
+    world_clock: WorldClock,
+    debug_hud: DebugHud,
+    frame_times: FrameTimes,
+    hotbar: Hotbar,
+    pause_menu: PauseMenu,
+    /// Set by the pause menu, the window closes after the current frame
+    exit_requested: bool,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: FileWatcher,
+    #[cfg(feature = "hot-reload")]
+    resource_pack_watcher: FileWatcher,
     prev_now: Instant,
     accumulated_dt: Duration
 }
@@ -25,23 +71,72 @@ impl Engine {
     pub fn new(window: Window) -> Self {
         let window = Arc::new(window);
 
-        let render_system = RenderSystem::new(Arc::clone(&window));
+        let mut render_system = RenderSystem::new(Arc::clone(&window));
+
+        let block_resources = load_block_resources();
+        render_system.set_ui_sprites(&block_resources.textures);
+        let chunk_loader = ThreadedChunkLoader::new(render_system.get_gpu_ctx(), &block_resources);
+        let chunk_system = ChunkSystem::new(
+            render_system.get_gpu_ctx(),
+            chunk_loader,
+            render_system.get_depth_mode(),
+            render_system.get_sample_count(),
+            &block_resources,
+        );
+        update_view_distance(&mut render_system, &chunk_system);
+
+        let input_system = InputSystem::new(load_action_map());
 
-        let chunk_loader = ThreadedChunkLoader::new(render_system.get_gpu_ctx());
-        let chunk_system = ChunkSystem::new(render_system.get_gpu_ctx(), chunk_loader);
+        let world_clock = match WorldClock::load(Path::new(WORLD_CLOCK_PATH)) {
+            Ok(world_clock) => world_clock,
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    eprintln!("Failed to load world clock, starting a new day: {err}");
+                }
+                WorldClock::new()
+            }
+        };
+        render_system.set_sun_and_moon(world_clock.sun_direction(), world_clock.moon_direction());
 
-        let input_system = InputSystem::new();
+        let debug_hud = DebugHud::new();
+        let hotbar = Hotbar::new(&block_resources.faces);
 
         Self {
             window,
             render_system,
             chunk_system,
             input_system,
+            world_clock,
+            debug_hud,
+            frame_times: FrameTimes::new(),
+            hotbar,
+            pause_menu: PauseMenu::new(),
+            exit_requested: false,
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: FileWatcher::new(
+                vec![PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"))],
+                Some("wgsl"),
+            ),
+            #[cfg(feature = "hot-reload")]
+            resource_pack_watcher: FileWatcher::new(
+                vec![
+                    PathBuf::from(RESOURCE_PACKS_PATH),
+                    PathBuf::from(DEFAULT_PACK_DIR),
+                ],
+                None,
+            ),
             prev_now: Instant::now(),
             accumulated_dt: Duration::ZERO
         }
     }
 
+    /// Called once before the window closes
+    pub fn shutdown(&mut self) {
+        if let Err(err) = self.world_clock.save(Path::new(WORLD_CLOCK_PATH)) {
+            eprintln!("Failed to save world clock: {err}");
+        }
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         self.render_system.resize(width, height);
     }
@@ -55,12 +150,37 @@ impl Engine {
     }
 
     pub fn handle_mouse_move(&mut self, x: f64, y: f64) {
-        self.input_system.handle_mouse_move(x as f32, y as f32);
+        // The cursor is free to use the pause menu, so it shouldn't turn the camera
+        if !self.pause_menu.is_open() {
+            self.input_system.handle_mouse_move(x as f32, y as f32);
+        }
+    }
+
+    pub fn handle_cursor_move(&mut self, x: f64, y: f64) {
+        self.input_system.handle_cursor_move(x as f32, y as f32);
+    }
+
+    pub fn handle_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        self.input_system.handle_mouse_button(button, pressed);
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        self.input_system.handle_mouse_wheel(delta);
     }
 
     pub fn window_focus(&mut self, flag: bool) {
-        self.window.set_cursor_visible(!flag);
-        if !flag {
+        // The cursor stays free while paused, even when the window comes back into focus
+        self.grab_cursor(flag && !self.pause_menu.is_open());
+    }
+
+    /// Whether the window should close, checked after every frame
+    pub fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    fn grab_cursor(&self, grab: bool) {
+        self.window.set_cursor_visible(!grab);
+        if !grab {
             self.window.set_cursor_grab(CursorGrabMode::None).unwrap();
         } else {
             self.window
@@ -69,6 +189,16 @@ impl Engine {
         }
     }
 
+    fn set_paused(&mut self, paused: bool) {
+        self.pause_menu.set_open(paused);
+        self.grab_cursor(!paused);
+        self.input_system.set_context(if paused {
+            InputContext::Menu
+        } else {
+            InputContext::Gameplay
+        });
+    }
+
     pub fn run_frame(&mut self) {
         // Calculate delta time
         let now = Instant::now();
@@ -76,29 +206,300 @@ impl Engine {
         self.prev_now = Instant::now();
         self.accumulated_dt += dt;
 
-        // print fps
-        let fps = 1.0 / dt.as_secs_f64();
-        println!(
-            "FPS: {}, Render Frametime: {}ms",
-            fps.trunc(),
-            dt.as_millis()
-        );
+        self.frame_times.push(dt);
+
+        self.input_system.update();
+        self.handle_actions();
 
         // Run fixed time step
         let fixed_time_step = Duration::from_secs_f32(1.0 / 60.0);
         while self.accumulated_dt >= fixed_time_step {
-            println!("Fixed Frame!");
-            let movement = self.input_system.get_movement();
-            self.render_system.move_camera(movement, fixed_time_step);
+            let paused = self.pause_menu.is_open();
+
+            if !paused {
+                let movement = self.input_system.get_movement();
+                self.render_system.move_camera(movement, fixed_time_step);
+            }
 
             let (p_x, _, p_z) = self.render_system.get_camera_pos();
-            self.chunk_system.player_moved(p_x.trunc() as i32, p_z.trunc() as i32);
+            self.chunk_system.player_moved(p_x.floor() as i32, p_z.floor() as i32);
+
+            if !paused {
+                self.world_clock.tick();
+            }
+            self.render_system.set_sun_and_moon(
+                self.world_clock.sun_direction(),
+                self.world_clock.moon_direction(),
+            );
 
             self.accumulated_dt -= fixed_time_step;
         }
 
+        #[cfg(feature = "hot-reload")]
+        self.hot_reload();
+
         // Run frame step
         self.chunk_system.handle_chunk_jobs();
-        self.render_system.render(&self.chunk_system);
+        self.chunk_system.cull_chunks(
+            &self.render_system.get_camera_frustum(),
+            self.render_system.get_camera_pos(),
+        );
+        self.update_ui();
+        self.render_system.render(&[&self.chunk_system]);
+
+        let screenshot_scale = if self.input_system.is_pressed(Action::HighResScreenshot) {
+            Some(HIGH_RES_SCREENSHOT_SCALE)
+        } else if self.input_system.is_pressed(Action::Screenshot) {
+            Some(1)
+        } else {
+            None
+        };
+        if let Some(scale) = screenshot_scale {
+            match self.render_system.screenshot(&[&self.chunk_system], scale) {
+                Some(image) => save_screenshot(image, Path::new(SCREENSHOTS_PATH)),
+                None => eprintln!("Screenshots aren't supported for this surface format"),
+            }
+        }
     }
+
+    /// Settings and toggles, once per frame so every press is seen exactly once
+    fn handle_actions(&mut self) {
+        if self.input_system.is_pressed(Action::Pause) {
+            self.set_paused(!self.pause_menu.is_open());
+        }
+
+        // Hotbar actions are gameplay only, the pause menu doesn't see them
+        for (slot, &action) in Action::HOTBAR_SLOTS.iter().enumerate() {
+            if self.input_system.is_pressed(action) {
+                self.hotbar.select(slot);
+            }
+        }
+        self.hotbar.scroll(
+            self.input_system
+                .get_press_delta(Action::HotbarNext, Action::HotbarPrevious),
+        );
+
+        let render_distance_change = self
+            .input_system
+            .get_press_delta(Action::RenderDistanceUp, Action::RenderDistanceDown);
+        if render_distance_change != 0 {
+            let render_distance = self.chunk_system.get_render_distance();
+            self.chunk_system
+                .set_render_distance(render_distance + render_distance_change);
+            update_view_distance(&mut self.render_system, &self.chunk_system);
+        }
+
+        let fov_change = self
+            .input_system
+            .get_press_delta(Action::FovUp, Action::FovDown);
+        if fov_change != 0 {
+            let fov_y_deg = self.render_system.get_fov_y_deg();
+            self.render_system
+                .set_fov_y_deg(fov_y_deg + fov_change as f32 * FOV_STEP_DEG);
+        }
+
+        if self.input_system.is_pressed(Action::CycleCullMode) {
+            let cull_mode = self.chunk_system.get_cull_mode();
+            self.chunk_system.set_cull_mode(cull_mode.next());
+        }
+
+        if self.input_system.is_pressed(Action::CycleDepthMode) {
+            let depth_mode = self.render_system.get_depth_mode().next();
+            self.render_system.set_depth_mode(depth_mode);
+            self.chunk_system.set_depth_mode(depth_mode);
+        }
+
+        if self.input_system.is_pressed(Action::CycleShadowCascades) {
+            let settings = self.render_system.get_shadow_settings();
+            self.render_system
+                .set_shadow_settings(settings.next_cascade_count());
+        }
+        if self.input_system.is_pressed(Action::CycleShadowResolution) {
+            let settings = self.render_system.get_shadow_settings();
+            self.render_system.set_shadow_settings(settings.next_resolution());
+        }
+
+        if self.input_system.is_pressed(Action::CycleAnisotropy) {
+            // Steps through 1x, 2x, 4x, 8x and 16x
+            let anisotropy = match self.chunk_system.get_anisotropy() {
+                16.. => 1,
+                anisotropy => anisotropy * 2,
+            };
+            self.chunk_system.set_anisotropy(anisotropy);
+        }
+
+        if self.input_system.is_pressed(Action::CycleMsaa) {
+            // Steps through every supported count and back to no MSAA
+            let sample_count = self.render_system.get_sample_count();
+            let next = self
+                .render_system
+                .get_supported_sample_counts()
+                .iter()
+                .copied()
+                .find(|&count| count > sample_count)
+                .unwrap_or(1);
+            self.render_system.set_sample_count(next);
+            self.chunk_system
+                .set_sample_count(self.render_system.get_sample_count());
+        }
+
+        if self.input_system.is_pressed(Action::CycleDebugRenderMode) {
+            let debug_render_mode = self.chunk_system.get_debug_render_mode();
+            self.chunk_system
+                .set_debug_render_mode(debug_render_mode.next());
+        }
+
+        if self.input_system.is_pressed(Action::ToggleDebug) {
+            self.debug_hud.set_visible(!self.debug_hud.is_visible());
+        }
+
+        let post_effects = [
+            (Action::ToggleBloom, PostEffect::Bloom),
+            (Action::ToggleTonemapping, PostEffect::Tonemapping),
+            (Action::ToggleFxaa, PostEffect::Fxaa),
+            (Action::ToggleGammaCorrection, PostEffect::GammaCorrection),
+        ];
+        for (action, effect) in post_effects {
+            if self.input_system.is_pressed(action) {
+                let settings = self.render_system.get_post_settings();
+                self.render_system.set_post_settings(settings.toggled(effect));
+            }
+        }
+        let exposure_change = self
+            .input_system
+            .get_press_delta(Action::ExposureUp, Action::ExposureDown);
+        if exposure_change != 0 {
+            let mut settings = self.render_system.get_post_settings();
+            settings.exposure *= EXPOSURE_STEP.powi(exposure_change);
+            self.render_system.set_post_settings(settings);
+        }
+
+        if self.input_system.is_pressed(Action::ToggleTimePaused) {
+            self.world_clock.set_paused(!self.world_clock.is_paused());
+        }
+        let time_of_day_change = self
+            .input_system
+            .get_press_delta(Action::TimeForward, Action::TimeBackward);
+        if time_of_day_change != 0 {
+            let time_of_day = self.world_clock.get_time_of_day();
+            self.world_clock
+                .set_time_of_day(time_of_day + time_of_day_change as f32 * TIME_OF_DAY_STEP);
+        }
+    }
+
+    /// Queues this frame's UI and handles what was clicked in it
+    fn update_ui(&mut self) {
+        let cursor = self.input_system.get_cursor_pos();
+        let click = self.input_system.is_released(Action::Click);
+
+        let debug_info = self.debug_info();
+        let ui = self.render_system.ui();
+        if !self.pause_menu.is_open() {
+            draw_crosshair(ui);
+        }
+        self.hotbar.draw(ui);
+        self.debug_hud.draw(ui, &debug_info);
+        let action = self.pause_menu.draw(ui, cursor, click);
+
+        match action {
+            Some(PauseMenuAction::Resume) => self.set_paused(false),
+            Some(PauseMenuAction::Quit) => self.exit_requested = true,
+            None => (),
+        }
+    }
+
+    /// What the debug HUD shows, whether or not it is visible
+    pub fn debug_info(&self) -> DebugInfo {
+        let average = self.frame_times.average();
+        let camera_pos = self.render_system.get_camera_pos();
+        DebugInfo {
+            fps: if average.is_zero() {
+                0.0
+            } else {
+                1.0 / average.as_secs_f32()
+            },
+            frame_time: self.frame_times.last(),
+            frame_times: self.frame_times.to_vec(),
+            camera_pos,
+            camera_chunk: (
+                (camera_pos.0.floor() as i32).div_euclid(16),
+                (camera_pos.2.floor() as i32).div_euclid(16),
+            ),
+            chunks: self.chunk_system.get_load_stats(),
+            cull_mode: self.chunk_system.get_cull_mode(),
+            cull_stats: self.chunk_system.get_cull_stats(),
+            geometry: self.chunk_system.get_geometry_stats(),
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+impl Engine {
+    fn hot_reload(&mut self) {
+        if self.shader_watcher.poll() {
+            // Everything is validated before anything is swapped in, a broken file anywhere keeps
+            // every shader as it was
+            let result = self.chunk_system.reload_shaders().and_then(|apply_chunks| {
+                Ok((apply_chunks, self.render_system.reload_shaders()?))
+            });
+            match result {
+                Ok((apply_chunks, apply_render)) => {
+                    apply_chunks(&mut self.chunk_system);
+                    apply_render(&mut self.render_system);
+                    println!("Reloaded shaders");
+                }
+                Err(err) => eprintln!("Keeping the previous shaders: {err}"),
+            }
+        }
+
+        if self.resource_pack_watcher.poll() {
+            let block_resources = load_block_resources();
+            self.chunk_system.set_block_resources(&block_resources);
+            self.render_system.set_ui_sprites(&block_resources.textures);
+            self.hotbar.set_block_faces(&block_resources.faces);
+            println!("Reloaded resource packs");
+        }
+    }
+}
+
+/// A missing config file is written with the default bindings so there's something to edit
+fn load_action_map() -> ActionMap {
+    let path = Path::new(INPUT_CONFIG_PATH);
+    match ActionMap::load(path) {
+        Ok((action_map, errors)) => {
+            for err in errors {
+                eprintln!("Skipped an input binding: {err}");
+            }
+            action_map
+        }
+        Err(err) => {
+            let action_map = ActionMap::new();
+            if err.kind() == ErrorKind::NotFound {
+                if let Err(err) = action_map.save(path) {
+                    eprintln!("Failed to save the default input bindings: {err}");
+                }
+            } else {
+                eprintln!("Failed to load input bindings, using the defaults: {err}");
+            }
+            action_map
+        }
+    }
+}
+
+/// Problems with individual packs are reported but never fatal, the built-in pack fills any gaps
+fn load_block_resources() -> BlockResources {
+    let (block_resources, errors) = load_resource_packs(Path::new(RESOURCE_PACKS_PATH));
+    for err in errors {
+        eprintln!("{err}");
+    }
+    block_resources
+}
+
+/// Clip planes and fog follow how far chunks are loaded
+fn update_view_distance(
+    render_system: &mut RenderSystem,
+    chunk_system: &ChunkSystem<ThreadedChunkLoader>,
+) {
+    render_system.set_view_distance(chunk_system.get_view_distance());
+    render_system.set_fog_distance(chunk_system.get_fog_distance());
 }
@@ -0,0 +1,219 @@
+use crate::engine::gpu::{DepthMode, GpuCtx, ShaderFile, shader_file};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, CommandEncoder,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Extent3d, FilterMode,
+    Origin3d, PipelineCompilationOptions, Sampler, SamplerDescriptor, ShaderModule,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+pub const HI_Z_SHADER: ShaderFile = shader_file!("src/engine/render_system/hi_z.wgsl");
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Max depth mip chain built from the depth buffer at the end of each frame,
+/// the next frame uses it to test if something is hidden behind what was drawn
+pub struct HiZPyramid {
+    texture: Texture,
+    view: TextureView,
+    /// Each level is built in a texture of its own and copied into `texture` afterwards. GL can't
+    /// write one mip of a texture while reading another, binding a view moves the texture's base level
+    levels: Vec<Texture>,
+    sampler: Sampler,
+    mip_sizes: Vec<(u32, u32)>,
+    copy_pipeline: ComputePipeline,
+    downsample_pipeline: ComputePipeline,
+    bind_groups: Vec<BindGroup>,
+}
+
+impl HiZPyramid {
+    /// `shader` is a module of `HI_Z_SHADER`
+    pub fn new(
+        gpu_ctx: &GpuCtx,
+        shader: &ShaderModule,
+        depth_view: &TextureView,
+        width: u32,
+        height: u32,
+        depth_mode: DepthMode,
+        sample_count: u32,
+    ) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+        let mip_level_count = width.max(height).ilog2() + 1;
+
+        let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let mip_sizes: Vec<_> = (0..mip_level_count)
+            .map(|mip| ((width >> mip).max(1), (height >> mip).max(1)))
+            .collect();
+        let levels: Vec<_> = mip_sizes
+            .iter()
+            .map(|&(width, height)| {
+                gpu_ctx.device.create_texture(&TextureDescriptor {
+                    label: None,
+                    size: Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::R32Float,
+                    usage: TextureUsages::STORAGE_BINDING
+                        | TextureUsages::TEXTURE_BINDING
+                        | TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                })
+            })
+            .collect();
+        let sampler = gpu_ctx.device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+        let mip_views: Vec<_> = levels
+            .iter()
+            .map(|level| level.create_view(&TextureViewDescriptor::default()))
+            .collect();
+
+        let constants = depth_mode.pipeline_constants();
+        let create_pipeline = |entry_point| {
+            gpu_ctx
+                .device
+                .create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: None,
+                    layout: None,
+                    module: shader,
+                    entry_point: Some(entry_point),
+                    compilation_options: PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    cache: None,
+                })
+        };
+        let (copy_pipeline, depth_binding) = if sample_count > 1 {
+            (create_pipeline("copy_depth_multisampled"), 3)
+        } else {
+            (create_pipeline("copy_depth"), 0)
+        };
+        let downsample_pipeline = create_pipeline("downsample");
+
+        let mut bind_groups = Vec::with_capacity(mip_views.len());
+        bind_groups.push(gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &copy_pipeline.get_bind_group_layout(0),
+            entries: &[
+                BindGroupEntry {
+                    binding: depth_binding,
+                    resource: BindingResource::TextureView(depth_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&mip_views[0]),
+                },
+            ],
+        }));
+        for mip in 1..mip_views.len() {
+            bind_groups.push(gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &downsample_pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&mip_views[mip - 1]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&mip_views[mip]),
+                    },
+                ],
+            }));
+        }
+
+        Self {
+            texture,
+            view,
+            levels,
+            sampler,
+            mip_sizes,
+            copy_pipeline,
+            downsample_pipeline,
+            bind_groups,
+        }
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// Nearest everything, the pyramid must never be filtered
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// Must run after the depth buffer has been written for the frame
+    pub fn build(&self, encoder: &mut CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+
+        for (mip, (bind_group, (width, height))) in
+            self.bind_groups.iter().zip(&self.mip_sizes).enumerate()
+        {
+            let pipeline = if mip == 0 {
+                &self.copy_pipeline
+            } else {
+                &self.downsample_pipeline
+            };
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        drop(pass);
+
+        for (mip, (level, &(width, height))) in self.levels.iter().zip(&self.mip_sizes).enumerate()
+        {
+            encoder.copy_texture_to_texture(
+                level.as_image_copy(),
+                TexelCopyTextureInfo {
+                    texture: &self.texture,
+                    mip_level: mip as u32,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+}
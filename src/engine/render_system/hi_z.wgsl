@@ -0,0 +1,79 @@
+// Builds a max depth pyramid, each texel holds the furthest depth of the texels it covers in the level below.
+// Reverse-Z depth is flipped on the way in so larger is always further
+override reverse_z: bool = false;
+
+// Bound as a plain float texture since `textureLoad` on depth textures isn't supported everywhere
+@group(0) @binding(0)
+var depth: texture_2d<f32>;
+
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+
+@group(0) @binding(2)
+var dst: texture_storage_2d<r32float, write>;
+
+// Replaces `depth` with MSAA
+@group(0) @binding(3)
+var depth_multisampled: texture_multisampled_2d<f32>;
+
+@compute @workgroup_size(8, 8)
+fn copy_depth(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(dst);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    var d = textureLoad(depth, id.xy, 0).r;
+    if (reverse_z) {
+        d = 1.0 - d;
+    }
+    textureStore(dst, id.xy, vec4f(d, 0.0, 0.0, 1.0));
+}
+
+// The furthest of a texel's samples, so the pyramid never claims something is hidden that isn't
+@compute @workgroup_size(8, 8)
+fn copy_depth_multisampled(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(dst);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    var furthest = 0.0;
+    for (var i = 0u; i < textureNumSamples(depth_multisampled); i++) {
+        var d = textureLoad(depth_multisampled, id.xy, i32(i)).r;
+        if (reverse_z) {
+            d = 1.0 - d;
+        }
+        furthest = max(furthest, d);
+    }
+    textureStore(dst, id.xy, vec4f(furthest, 0.0, 0.0, 1.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn downsample(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(dst);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    let src_size = textureDimensions(src);
+
+    // Odd sized levels need the extra row/column folded into the last texel
+    var footprint = vec2u(2u, 2u);
+    if (id.x == size.x - 1u && (src_size.x & 1u) == 1u) {
+        footprint.x = 3u;
+    }
+    if (id.y == size.y - 1u && (src_size.y & 1u) == 1u) {
+        footprint.y = 3u;
+    }
+
+    var max_depth = 0.0;
+    for (var y = 0u; y < footprint.y; y++) {
+        for (var x = 0u; x < footprint.x; x++) {
+            let coords = min(id.xy * 2u + vec2u(x, y), src_size - vec2u(1u, 1u));
+            max_depth = max(max_depth, textureLoad(src, coords, 0).r);
+        }
+    }
+
+    textureStore(dst, id.xy, vec4f(max_depth, 0.0, 0.0, 1.0));
+}
@@ -1,22 +1,56 @@
-use crate::engine::gpu::{Camera, CameraMovementBuffer, GpuCtx};
+use crate::engine::gpu::{Camera, CameraMovementBuffer, DepthMode, Frustum, GpuCtx};
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use cgmath::{Deg, Point3, Vector3};
+use image::RgbaImage;
 use pollster::FutureExt;
-use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
 use wgpu::{
-    AddressMode, Backends, Color, CommandEncoderDescriptor, CompareFunction, DeviceDescriptor,
+    Adapter, AddressMode, Backend, Backends, Color, CommandEncoderDescriptor, Device, DeviceDescriptor,
     Extent3d, Features, FilterMode, Instance, InstanceDescriptor, Limits, LoadOp, MemoryHints,
-    Operations, PowerPreference, PresentMode, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RequestAdapterOptions, Sampler,
-    SamplerDescriptor, StoreOp, Surface, SurfaceConfiguration, Texture, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+    Operations, PowerPreference, PresentMode, Queue, RenderPassColorAttachment,
+    RenderPass, RenderPassDepthStencilAttachment, RenderPassDescriptor, RequestAdapterOptions, Sampler,
+    SamplerDescriptor, ShaderModule, StoreOp, SurfaceConfiguration, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
 };
 use winit::window::Window;
 
+mod font;
+mod hi_z;
+mod post_processing;
+mod render_graph;
+mod render_target;
 mod renderable;
-pub use renderable::Renderable;
+mod shadows;
+mod sky;
+mod ui;
+pub use hi_z::HiZPyramid;
+use hi_z::HI_Z_SHADER;
+pub use post_processing::{HDR_FORMAT, PostEffect, PostSettings};
+use post_processing::{PostBindGroups, PostProcessing};
+pub use render_graph::PassName;
+use render_graph::{AttachmentDesc, PassContext, PassDesc, RenderGraph, Resource};
+pub use renderable::{FrameContext, Renderable};
+pub use shadows::{ShadowSettings, create_cascade_bind_group_layout, create_shadow_bind_group_layout};
+use render_target::RenderTarget;
+use shadows::ShadowMaps;
+pub use sky::create_environment_bind_group_layout;
+use sky::Sky;
+pub use ui::{Anchor, UiBatch};
 
-async fn initialize_wgpu(window: Arc<Window>) -> (GpuCtx, Surface<'static>, SurfaceConfiguration) {
+/// Reverse-Z keeps distant terrain from z-fighting, standard depth can still be switched to at runtime
+const DEFAULT_DEPTH_MODE: DepthMode = DepthMode::ReverseZ;
+/// Close enough that the camera can't see through blocks it is pressed against
+const Z_NEAR: f32 = 0.1;
+/// Fraction of the view distance over which terrain is still clear before it fades into the sky
+const FOG_START: f32 = 0.7;
+/// Lowered to the highest count the adapter supports
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
+/// Headless rendering has no surface to pick a format from
+const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+async fn initialize_wgpu(window: Arc<Window>) -> (GpuCtx, RenderTarget) {
     let instance = Instance::new(&InstanceDescriptor {
         backends: Backends::PRIMARY,
         ..Default::default()
@@ -38,18 +72,7 @@ async fn initialize_wgpu(window: Arc<Window>) -> (GpuCtx, Surface<'static>, Surf
         .await
         .expect("Failed to receive gpu adapter!");
 
-    let (device, queue) = adapter
-        .request_device(
-            &DeviceDescriptor {
-                label: None,
-                required_features: Features::POLYGON_MODE_LINE,
-                required_limits: Limits::default(),
-                memory_hints: MemoryHints::Performance,
-            },
-            None,
-        )
-        .await
-        .expect("Failed to receive gpu device!");
+    let (device, queue) = request_device(&adapter).await;
 
     let surface_caps = surface.get_capabilities(&adapter);
     let format = surface_caps
@@ -72,19 +95,114 @@ async fn initialize_wgpu(window: Arc<Window>) -> (GpuCtx, Surface<'static>, Surf
 
     surface.configure(&device, &surface_config);
 
-    let gpu_ctx = GpuCtx::new(device, queue, format);
-    (gpu_ctx, surface, surface_config)
+    let sample_counts = supported_sample_counts(&adapter, &device);
+    let gpu_ctx = GpuCtx::new(device, queue, format, sample_counts);
+    let target = RenderTarget::Window {
+        window,
+        surface,
+        config: surface_config,
+    };
+    (gpu_ctx, target)
+}
+
+/// Prefers a software adapter like lavapipe or llvmpipe, so output only depends on the driver version
+async fn initialize_headless_wgpu(width: u32, height: u32) -> (GpuCtx, RenderTarget) {
+    let instance = Instance::new(&InstanceDescriptor {
+        backends: Backends::all(),
+        ..Default::default()
+    });
+
+    let mut adapter = None;
+    for force_fallback_adapter in [true, false] {
+        adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await;
+        if adapter.is_some() {
+            break;
+        }
+    }
+    let adapter = adapter.expect("Failed to receive gpu adapter!");
+
+    let (device, queue) = request_device(&adapter).await;
+    let sample_counts = supported_sample_counts(&adapter, &device);
+    let gpu_ctx = GpuCtx::new(device, queue, OFFSCREEN_FORMAT, sample_counts);
+    let target = RenderTarget::offscreen(&gpu_ctx, width, height);
+    (gpu_ctx, target)
+}
+
+async fn request_device(adapter: &Adapter) -> (Device, Queue) {
+    // Optional features are only requested when the adapter has them
+    let optional_features = adapter.features()
+        & (Features::MULTI_DRAW_INDIRECT | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+    adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                required_features: Features::POLYGON_MODE_LINE | optional_features,
+                // The mesh arena grows as far as the adapter allows, the default caps buffers at 256 MiB
+                required_limits: Limits {
+                    max_buffer_size: adapter.limits().max_buffer_size,
+                    ..Limits::default()
+                },
+                memory_hints: MemoryHints::Performance,
+            },
+            None,
+        )
+        .await
+        .expect("Failed to receive gpu device!")
+}
+
+/// MSAA sample counts out of 1, 2, 4 and 8 that both the HDR target and the depth buffer support.
+/// Without adapter specific format features the device only allows what WebGPU guarantees
+fn supported_sample_counts(adapter: &Adapter, device: &Device) -> Vec<u32> {
+    // The GL backend can't allocate multisampled textures shaders read from, and the Hi-Z pyramid
+    // reads the multisampled depth buffer
+    if adapter.get_info().backend == Backend::Gl {
+        return vec![1];
+    }
+
+    let format_flags = |format: TextureFormat| {
+        if device
+            .features()
+            .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            adapter.get_texture_format_features(format).flags
+        } else {
+            format.guaranteed_format_features(device.features()).flags
+        }
+    };
+    let hdr_flags = format_flags(HDR_FORMAT);
+    let depth_flags = format_flags(TextureFormat::Depth32Float);
+
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&count| {
+            count == 1
+                || (hdr_flags.sample_count_supported(count)
+                    && hdr_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && depth_flags.sample_count_supported(count))
+        })
+        .collect()
 }
 
+/// `layers` above one make an array texture, which needs a `D2Array` view even with a single layer
 fn create_depth_texture(
     gpu_ctx: &GpuCtx,
     width: u32,
     height: u32,
+    layers: u32,
+    view_dimension: TextureViewDimension,
+    depth_mode: DepthMode,
 ) -> (Texture, TextureView, Sampler) {
     let size = Extent3d {
         width: width.max(1),
         height: height.max(1),
-        depth_or_array_layers: 1,
+        depth_or_array_layers: layers.max(1),
     };
 
     let desc = TextureDescriptor {
@@ -98,7 +216,10 @@ fn create_depth_texture(
         view_formats: &[],
     };
     let texture = gpu_ctx.device.create_texture(&desc);
-    let view = texture.create_view(&TextureViewDescriptor::default());
+    let view = texture.create_view(&TextureViewDescriptor {
+        dimension: Some(view_dimension),
+        ..Default::default()
+    });
     let sampler = gpu_ctx.device.create_sampler(&SamplerDescriptor {
         address_mode_u: AddressMode::ClampToEdge,
         address_mode_v: AddressMode::ClampToEdge,
@@ -106,7 +227,7 @@ fn create_depth_texture(
         mag_filter: FilterMode::Linear,
         min_filter: FilterMode::Linear,
         mipmap_filter: FilterMode::Nearest,
-        compare: Some(CompareFunction::LessEqual),
+        compare: Some(depth_mode.compare_function()),
         lod_min_clamp: 0.0,
         lod_max_clamp: 100.0,
         ..Default::default()
@@ -114,35 +235,174 @@ fn create_depth_texture(
     (texture, view, sampler)
 }
 
+/// Attachments of the opaque pass in the render graph
+#[derive(Copy, Clone)]
+struct MainAttachments {
+    depth: Resource,
+    /// What post-processing starts from
+    hdr: Resource,
+    /// Drawn into instead of `hdr` with MSAA and resolved into it at the end of the pass
+    hdr_multisampled: Option<Resource>,
+}
+
+/// Shadows feed the opaque pass, whose depth feeds the Hi-Z pyramid for the next frame's culling.
+/// Post-processing adds its own passes after the opaque one, starting from the HDR target
+fn create_render_graph(
+    gpu_ctx: &GpuCtx,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> (RenderGraph, MainAttachments) {
+    let mut graph = RenderGraph::new(width, height);
+    let mut add_attachment = |label, format, sample_count| {
+        graph.add_attachment(
+            gpu_ctx,
+            AttachmentDesc {
+                label,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                size_divisor: 1,
+                sample_count,
+            },
+        )
+    };
+    let attachments = MainAttachments {
+        depth: add_attachment("depth", TextureFormat::Depth32Float, sample_count),
+        hdr: add_attachment("hdr", HDR_FORMAT, 1),
+        hdr_multisampled: (sample_count > 1)
+            .then(|| add_attachment("hdr multisampled", HDR_FORMAT, sample_count)),
+    };
+
+    graph.add_pass(PassDesc {
+        name: PassName::Shadow,
+        reads: vec![],
+        writes: vec![Resource::ShadowMaps],
+        execute: RenderSystem::draw_shadows,
+    });
+    graph.add_pass(PassDesc {
+        name: PassName::Opaque,
+        reads: vec![Resource::ShadowMaps],
+        writes: [attachments.hdr, attachments.depth]
+            .into_iter()
+            .chain(attachments.hdr_multisampled)
+            .collect(),
+        execute: RenderSystem::draw_opaque,
+    });
+    graph.add_pass(PassDesc {
+        name: PassName::HiZ,
+        reads: vec![attachments.depth],
+        writes: vec![],
+        execute: RenderSystem::build_hi_z,
+    });
+    (graph, attachments)
+}
+
+/// Goes after post-processing, which has to be registered first since both write the target
+fn add_overlay_pass(graph: &mut RenderGraph) {
+    graph.add_pass(PassDesc {
+        name: PassName::Overlay,
+        reads: vec![],
+        writes: vec![Resource::Target],
+        execute: RenderSystem::draw_overlay,
+    });
+}
+
+/// Highest supported sample count that isn't above `sample_count`
+fn clamp_sample_count(gpu_ctx: &GpuCtx, sample_count: u32) -> u32 {
+    gpu_ctx
+        .sample_counts
+        .iter()
+        .copied()
+        .filter(|&count| count <= sample_count)
+        .max()
+        .unwrap_or(1)
+}
+
+/// Draws every renderable that registered for `pass_name`
+fn draw_pass(
+    renderables: &[&dyn Renderable],
+    pass_name: PassName,
+    pass: &mut RenderPass,
+    frustum: &Frustum,
+) {
+    for renderable in renderables {
+        if renderable.passes().contains(&pass_name) {
+            renderable.render(pass_name, pass, frustum);
+        }
+    }
+}
+
 pub struct RenderSystem {
     gpu_ctx: Arc<GpuCtx>,
-    surface: Surface<'static>,
-    surface_config: SurfaceConfiguration,
-    window: Arc<Window>,
+    target: RenderTarget,
     camera: Camera,
-    depth_texture: Texture,
-    depth_texture_view: TextureView,
-    depth_sampler: Sampler,
+    graph: RenderGraph,
+    attachments: MainAttachments,
+    hi_z: HiZPyramid,
+    /// Kept so the pyramid can be recreated on resize with a reloaded shader
+    hi_z_shader: ShaderModule,
+    depth_mode: DepthMode,
+    /// MSAA samples of the opaque pass, 1 without MSAA
+    sample_count: u32,
+    view_distance: f32,
+    sky: Sky,
+    shadows: ShadowMaps,
+    post: PostProcessing,
+    post_bind_groups: PostBindGroups,
+    ui: UiBatch,
 }
 
 impl RenderSystem {
     pub fn new(window: Arc<Window>) -> Self {
-        let (gpu_ctx, surface, surface_config) = initialize_wgpu(Arc::clone(&window)).block_on();
-        let width = surface_config.width;
-        let height = surface_config.height;
-        let camera = Camera::new(&gpu_ctx, width, height);
-        let (depth_texture, depth_texture_view, depth_sampler) =
-            create_depth_texture(&gpu_ctx, width, height);
+        let (gpu_ctx, target) = initialize_wgpu(window).block_on();
+        Self::with_target(gpu_ctx, target)
+    }
+
+    /// Draws into a texture instead of a window, read it back with `read_pixels`
+    pub fn new_headless(width: u32, height: u32) -> Self {
+        let (gpu_ctx, target) = initialize_headless_wgpu(width, height).block_on();
+        Self::with_target(gpu_ctx, target)
+    }
+
+    fn with_target(gpu_ctx: GpuCtx, target: RenderTarget) -> Self {
+        let (width, height) = target.size();
+        let depth_mode = DEFAULT_DEPTH_MODE;
+        let camera = Camera::new(&gpu_ctx, width, height, depth_mode);
+        let sample_count = clamp_sample_count(&gpu_ctx, DEFAULT_SAMPLE_COUNT);
+        let (mut graph, attachments) = create_render_graph(&gpu_ctx, width, height, sample_count);
+        let post = PostProcessing::new(&gpu_ctx, &mut graph, attachments.hdr);
+        add_overlay_pass(&mut graph);
+        let post_bind_groups = post.bind(&gpu_ctx, &graph);
+        let hi_z_shader = HI_Z_SHADER.create_module(&gpu_ctx);
+        let hi_z = HiZPyramid::new(
+            &gpu_ctx,
+            &hi_z_shader,
+            graph.view(attachments.depth),
+            width,
+            height,
+            depth_mode,
+            sample_count,
+        );
+        let sky = Sky::new(&gpu_ctx, sample_count);
+        let shadows = ShadowMaps::new(&gpu_ctx, ShadowSettings::default());
+        let ui = UiBatch::new(&gpu_ctx, width, height);
 
         Self {
             gpu_ctx: Arc::new(gpu_ctx),
-            surface,
-            surface_config,
-            window,
+            target,
             camera,
-            depth_texture,
-            depth_texture_view,
-            depth_sampler,
+            graph,
+            attachments,
+            hi_z,
+            hi_z_shader,
+            depth_mode,
+            sample_count,
+            view_distance: f32::MAX,
+            sky,
+            shadows,
+            post,
+            post_bind_groups,
+            ui,
         }
     }
 
@@ -152,73 +412,330 @@ impl RenderSystem {
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            self.surface_config.width = width;
-            self.surface_config.height = height;
-            self.surface
-                .configure(&self.gpu_ctx.device, &self.surface_config);
+            self.target.resize(&self.gpu_ctx, width, height);
             self.camera.resize(width, height);
-            let (depth_texture, depth_texture_view, depth_sampler) =
-                create_depth_texture(&self.gpu_ctx, width, height);
-            self.depth_texture = depth_texture;
-            self.depth_texture_view = depth_texture_view;
-            self.depth_sampler = depth_sampler;
+            self.graph.resize(&self.gpu_ctx, width, height);
+            self.post_bind_groups = self.post.bind(&self.gpu_ctx, &self.graph);
+            self.recreate_hi_z();
+            self.ui.resize(width, height);
         }
     }
 
+    pub fn get_depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// Anything drawing into the depth buffer has to be switched over as well
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        if depth_mode != self.depth_mode {
+            self.depth_mode = depth_mode;
+            self.camera.set_depth_mode(depth_mode);
+            self.recreate_hi_z();
+        }
+    }
+
+    fn recreate_hi_z(&mut self) {
+        self.hi_z = self.create_hi_z(&self.hi_z_shader);
+    }
+
+    fn create_hi_z(&self, shader: &ShaderModule) -> HiZPyramid {
+        let (width, height) = self.target.size();
+        HiZPyramid::new(
+            &self.gpu_ctx,
+            shader,
+            self.graph.view(self.attachments.depth),
+            width,
+            height,
+            self.depth_mode,
+            self.sample_count,
+        )
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Ascending, always starting at 1
+    pub fn get_supported_sample_counts(&self) -> &[u32] {
+        &self.gpu_ctx.sample_counts
+    }
+
+    /// Lowered to what the adapter supports. Renderables drawing into the opaque pass need
+    /// pipelines with the same count, check `get_sample_count` afterwards
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        let sample_count = clamp_sample_count(&self.gpu_ctx, sample_count);
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.sky.set_sample_count(&self.gpu_ctx, sample_count);
+            self.rebuild_graph();
+        }
+    }
+
+    /// The graph's attachments change with the sample count, so it is built again from scratch
+    fn rebuild_graph(&mut self) {
+        let (width, height) = self.target.size();
+        let (mut graph, attachments) =
+            create_render_graph(&self.gpu_ctx, width, height, self.sample_count);
+        self.post
+            .register(&self.gpu_ctx, &mut graph, attachments.hdr);
+        add_overlay_pass(&mut graph);
+        self.post_bind_groups = self.post.bind(&self.gpu_ctx, &graph);
+        self.graph = graph;
+        self.attachments = attachments;
+        self.recreate_hi_z();
+    }
+
     pub fn move_camera(&mut self, movement: CameraMovementBuffer, dt: Duration) {
         self.camera.move_camera(movement, dt);
     }
 
+    /// Yaw 0 looks down +x and turns towards +z, positive pitch looks up
+    pub fn set_camera_pose(&mut self, pos: Point3<f32>, yaw: Deg<f32>, pitch: Deg<f32>) {
+        self.camera.set_pose(pos, yaw, pitch);
+    }
+
     pub fn get_camera_pos(&self) -> (f32, f32, f32) {
         self.camera.get_pos()
     }
 
-    pub fn render(&self, renderable: &impl Renderable) {
-        self.camera.update_buffer(&self.gpu_ctx);
+    pub fn get_fov_y_deg(&self) -> f32 {
+        self.camera.get_fov_y_deg()
+    }
+
+    pub fn set_fov_y_deg(&mut self, fov_y_deg: f32) {
+        self.camera.set_fov_y_deg(fov_y_deg);
+    }
+
+    /// Anything further than `view_distance` blocks is clipped, unless using an infinite reverse-Z projection
+    pub fn set_view_distance(&mut self, view_distance: f32) {
+        self.view_distance = view_distance;
+        self.camera.set_clip_planes(Z_NEAR, view_distance);
+    }
+
+    pub fn get_shadow_settings(&self) -> ShadowSettings {
+        self.shadows.settings()
+    }
+
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) {
+        if settings != self.shadows.settings() {
+            self.shadows = ShadowMaps::new(&self.gpu_ctx, settings);
+        }
+    }
+
+    /// Terrain is fully fogged at `fog_distance` blocks, which should be where chunks stop loading
+    pub fn set_fog_distance(&mut self, fog_distance: f32) {
+        self.sky.set_fog(fog_distance * FOG_START, fog_distance);
+    }
+
+    pub fn get_post_settings(&self) -> PostSettings {
+        self.post.settings()
+    }
+
+    pub fn set_post_settings(&mut self, settings: PostSettings) {
+        self.post.set_settings(settings);
+    }
+
+    /// Sky colors and terrain lighting follow the sun
+    pub fn set_sun_and_moon(&mut self, sun_direction: Vector3<f32>, moon_direction: Vector3<f32>) {
+        self.sky.set_sun_and_moon(sun_direction, moon_direction);
+    }
 
-        let target = match self.surface.get_current_texture() {
-            Ok(target) => target,
-            _ => return,
+    /// Screen space quads drawn over the next frame, anchored to the window's size
+    pub fn ui(&mut self) -> &mut UiBatch {
+        &mut self.ui
+    }
+
+    /// Sprites `UiBatch::sprite` draws from, all the same size
+    pub fn set_ui_sprites(&mut self, sprites: &[RgbaImage]) {
+        self.ui.set_sprites(&self.gpu_ctx, sprites);
+    }
+
+    /// Builds every pipeline from the shaders on disk without touching the current ones, the
+    /// returned function swaps them in. On any error nothing is swapped
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&self) -> Result<impl FnOnce(&mut Self) + use<>, ShaderError> {
+        let apply_sky = self.sky.reload_shader(&self.gpu_ctx)?;
+        let apply_post = self.post.reload_shader(&self.gpu_ctx)?;
+        let apply_ui = self.ui.reload_shader(&self.gpu_ctx)?;
+        let (hi_z_shader, hi_z) = validate(&self.gpu_ctx, || {
+            let shader = HI_Z_SHADER.reload_module(&self.gpu_ctx)?;
+            let hi_z = self.create_hi_z(&shader);
+            Ok((shader, hi_z))
+        })?;
+
+        Ok(move |render_system: &mut Self| {
+            apply_sky(&mut render_system.sky);
+            apply_post(&mut render_system.post);
+            apply_ui(&mut render_system.ui);
+            render_system.hi_z_shader = hi_z_shader;
+            render_system.hi_z = hi_z;
+        })
+    }
+
+    pub fn get_camera_frustum(&self) -> Frustum {
+        self.camera.frustum()
+    }
+
+    /// Takes everything queued in `ui` since the last frame
+    pub fn render(&mut self, renderables: &[&dyn Renderable]) {
+        self.ui.upload(&self.gpu_ctx);
+        let Some(frame) = self.target.begin_frame() else {
+            return;
         };
+        self.draw(
+            renderables,
+            &self.graph,
+            &self.post_bind_groups,
+            &frame.view,
+            true,
+        );
+        self.target.present(frame);
+    }
 
-        let view = target
-            .texture
-            .create_view(&TextureViewDescriptor::default());
+    /// Draws the current view again into a texture `scale` times the size of the target and reads
+    /// it back, so it also works for window targets whose swapchain can't be copied from. The scale
+    /// is lowered to what the device can allocate
+    pub fn screenshot(&self, renderables: &[&dyn Renderable], scale: u32) -> Option<RgbaImage> {
+        let (width, height) = self.target.size();
+        let max_scale = self.gpu_ctx.device.limits().max_texture_dimension_2d / width.max(height);
+        let scale = scale.min(max_scale).max(1);
+        let (width, height) = (width * scale, height * scale);
+
+        let target = RenderTarget::offscreen(&self.gpu_ctx, width, height);
+        let graph = self.graph.with_size(&self.gpu_ctx, width, height);
+        let post_bind_groups = self.post.bind(&self.gpu_ctx, &graph);
+        let frame = target.begin_frame()?;
+        // The Hi-Z pyramid keeps the window's size and last frame, culling next frame relies on it
+        self.draw(renderables, &graph, &post_bind_groups, &frame.view, false);
+        target.read_pixels(&self.gpu_ctx)
+    }
+
+    fn draw(
+        &self,
+        renderables: &[&dyn Renderable],
+        graph: &RenderGraph,
+        post_bind_groups: &PostBindGroups,
+        target_view: &TextureView,
+        build_hi_z: bool,
+    ) {
+        self.camera.update_buffer(&self.gpu_ctx);
+        self.post.update_buffer(&self.gpu_ctx);
+        self.ui.update_buffer(&self.gpu_ctx);
+        self.sky
+            .update_buffer(&self.gpu_ctx, self.camera.view_proj(), self.camera.get_pos());
 
         let mut encoder = self
             .gpu_ctx
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
 
-        {
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::BLACK),
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        let frame_ctx = FrameContext {
+            gpu_ctx: &self.gpu_ctx,
+            view_proj: self.camera.view_proj(),
+            frustum: self.camera.frustum(),
+            hi_z: &self.hi_z,
+            viewport: self.target.size(),
+            depth_mode: self.depth_mode,
+        };
+        for renderable in renderables {
+            renderable.prepare(&mut encoder, &frame_ctx);
+        }
 
-            pass.set_bind_group(0, self.camera.bind_group(), &[]);
-            renderable.render(&mut pass);
+        let mut ctx = PassContext {
+            encoder: &mut encoder,
+            graph,
+            renderables,
+            frustum: frame_ctx.frustum,
+            post_bind_groups,
+            target_view,
+            build_hi_z,
+        };
+        for pass in graph.passes() {
+            (pass.execute)(self, &mut ctx);
         }
 
         self.gpu_ctx.queue.submit(std::iter::once(encoder.finish()));
-        self.window.pre_present_notify();
-        target.present();
+    }
+
+    fn draw_shadows(&self, ctx: &mut PassContext) {
+        self.shadows.render(
+            &self.gpu_ctx,
+            ctx.encoder,
+            &self.camera,
+            (Z_NEAR, self.view_distance),
+            self.sky.shadow_light_direction(),
+            ctx.renderables,
+        );
+    }
+
+    fn draw_opaque(&self, ctx: &mut PassContext) {
+        // Only the resolved color is needed afterwards, depth is kept for Hi-Z
+        let (color, resolve_target, color_store) = match self.attachments.hdr_multisampled {
+            Some(hdr_multisampled) => (
+                hdr_multisampled,
+                Some(ctx.graph.view(self.attachments.hdr)),
+                StoreOp::Discard,
+            ),
+            None => (self.attachments.hdr, None, StoreOp::Store),
+        };
+        let mut pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: ctx.graph.view(color),
+                resolve_target,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: color_store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: ctx.graph.view(self.attachments.depth),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(self.depth_mode.clear_value()),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        self.sky.render(&mut pass);
+
+        pass.set_bind_group(0, self.camera.bind_group(), &[]);
+        pass.set_bind_group(2, self.sky.bind_group(), &[]);
+        pass.set_bind_group(3, self.shadows.bind_group(), &[]);
+        draw_pass(ctx.renderables, PassName::Opaque, &mut pass, &ctx.frustum);
+    }
+
+    /// Next frame culls against what ended up in the depth buffer this frame
+    fn build_hi_z(&self, ctx: &mut PassContext) {
+        if ctx.build_hi_z {
+            self.hi_z.build(ctx.encoder);
+        }
+    }
+
+    fn draw_overlay(&self, ctx: &mut PassContext) {
+        let mut pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: ctx.target_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        draw_pass(ctx.renderables, PassName::Overlay, &mut pass, &ctx.frustum);
+        self.ui.render(&mut pass);
+    }
+
+    /// Pixels of the last rendered frame, `None` when rendering to a window
+    pub fn read_pixels(&self) -> Option<RgbaImage> {
+        self.target.read_pixels(&self.gpu_ctx)
     }
 }
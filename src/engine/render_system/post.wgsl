@@ -0,0 +1,161 @@
+// Fullscreen post-processing passes, each reads `src` and writes one color target
+struct Post {
+    // x: exposure, y: gamma, z: bloom threshold, w: bloom intensity
+    params: vec4f,
+    // x: gamma correction, y: the target encodes sRGB itself
+    flags: vec4u,
+}
+
+// FXAA tuning from the original console version
+const FXAA_SPAN_MAX = 8.0;
+const FXAA_REDUCE_MUL = 1.0 / 8.0;
+const FXAA_REDUCE_MIN = 1.0 / 128.0;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@group(0) @binding(0)
+var<uniform> post: Post;
+
+@group(0) @binding(1)
+var linear_sampler: sampler;
+
+@group(0) @binding(2)
+var src: texture_2d<f32>;
+
+// Only bound for tonemapping
+@group(0) @binding(3)
+var bloom: texture_2d<f32>;
+
+// One triangle covering the whole screen
+@vertex
+fn v_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2f(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.pos = vec4f(ndc, 0.0, 1.0);
+    out.uv = vec2f(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    return out;
+}
+
+fn luminance(color: vec3f) -> f32 {
+    return dot(color, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+// Averages a 2x2 block of the full size source, so nothing thin flickers when the camera moves
+@fragment
+fn bloom_threshold(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(src));
+    var color = vec3f(0.0);
+    color += textureSample(src, linear_sampler, in.uv + texel * vec2f(-0.5, -0.5)).rgb;
+    color += textureSample(src, linear_sampler, in.uv + texel * vec2f(0.5, -0.5)).rgb;
+    color += textureSample(src, linear_sampler, in.uv + texel * vec2f(-0.5, 0.5)).rgb;
+    color += textureSample(src, linear_sampler, in.uv + texel * vec2f(0.5, 0.5)).rgb;
+    color *= 0.25;
+
+    let luma = luminance(color);
+    let bright = max(luma - post.params.z, 0.0) / max(luma, 0.0001);
+    return vec4f(color * bright, 1.0);
+}
+
+// 9 tap gaussian folded into 5 bilinear samples
+fn blur(uv: vec2f, direction: vec2f) -> vec4f {
+    let texel = direction / vec2f(textureDimensions(src));
+    var color = textureSample(src, linear_sampler, uv).rgb * 0.2270270270;
+    color += textureSample(src, linear_sampler, uv + texel * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(src, linear_sampler, uv - texel * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(src, linear_sampler, uv + texel * 3.2307692308).rgb * 0.0702702703;
+    color += textureSample(src, linear_sampler, uv - texel * 3.2307692308).rgb * 0.0702702703;
+    return vec4f(color, 1.0);
+}
+
+@fragment
+fn blur_horizontal(in: VertexOutput) -> @location(0) vec4f {
+    return blur(in.uv, vec2f(1.0, 0.0));
+}
+
+@fragment
+fn blur_vertical(in: VertexOutput) -> @location(0) vec4f {
+    return blur(in.uv, vec2f(0.0, 1.0));
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3f) -> vec3f {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3f(0.0), vec3f(1.0));
+}
+
+@fragment
+fn tonemap(in: VertexOutput) -> @location(0) vec4f {
+    var color = textureSample(src, linear_sampler, in.uv).rgb;
+    color += textureSample(bloom, linear_sampler, in.uv).rgb * post.params.w;
+    return vec4f(aces(color * post.params.x), 1.0);
+}
+
+// Works on perceptual luma, edges in dark areas would be missed on linear values
+fn fxaa_luma(color: vec3f) -> f32 {
+    return sqrt(luminance(color));
+}
+
+@fragment
+fn fxaa(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(src));
+    let rgb_nw = textureSample(src, linear_sampler, in.uv + vec2f(-1.0, -1.0) * texel).rgb;
+    let rgb_ne = textureSample(src, linear_sampler, in.uv + vec2f(1.0, -1.0) * texel).rgb;
+    let rgb_sw = textureSample(src, linear_sampler, in.uv + vec2f(-1.0, 1.0) * texel).rgb;
+    let rgb_se = textureSample(src, linear_sampler, in.uv + vec2f(1.0, 1.0) * texel).rgb;
+    let rgb_m = textureSample(src, linear_sampler, in.uv).rgb;
+
+    let luma_nw = fxaa_luma(rgb_nw);
+    let luma_ne = fxaa_luma(rgb_ne);
+    let luma_sw = fxaa_luma(rgb_sw);
+    let luma_se = fxaa_luma(rgb_se);
+    let luma_m = fxaa_luma(rgb_m);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blur along the edge, which runs perpendicular to the luma gradient
+    var dir = vec2f(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2f(-FXAA_SPAN_MAX), vec2f(FXAA_SPAN_MAX)) * texel;
+
+    let rgb_a = 0.5 * (
+        textureSample(src, linear_sampler, in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(src, linear_sampler, in.uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        textureSample(src, linear_sampler, in.uv + dir * -0.5).rgb +
+        textureSample(src, linear_sampler, in.uv + dir * 0.5).rgb
+    );
+
+    // The wider blur crossed another edge, fall back to the narrow one
+    let luma_b = fxaa_luma(rgb_b);
+    let color = select(rgb_b, rgb_a, luma_b < luma_min || luma_b > luma_max);
+    return vec4f(color, 1.0);
+}
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
+// Anything past 1 clips here when tonemapping is off. Without gamma correction the linear values go
+// to the screen as they are, which shows how much darker the image gets without it
+@fragment
+fn output(in: VertexOutput) -> @location(0) vec4f {
+    var color = clamp(textureSample(src, linear_sampler, in.uv).rgb, vec3f(0.0), vec3f(1.0));
+    if (post.flags.x != 0u) {
+        color = pow(color, vec3f(1.0 / post.params.y));
+    }
+    // An sRGB target encodes again on write, undo that so the color above is what ends up on screen
+    if (post.flags.y != 0u) {
+        color = srgb_to_linear(color);
+    }
+    return vec4f(color, 1.0);
+}
@@ -0,0 +1,521 @@
+use crate::engine::gpu::{GpuCtx, ShaderFile, shader_file};
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use crate::engine::render_system::render_graph::{
+    AttachmentDesc, PassContext, PassDesc, PassName, RenderGraph, Resource,
+};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoder,
+    FilterMode, FragmentState, LoadOp, MultisampleState, Operations, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModule, ShaderStages, StoreOp, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension, VertexState,
+};
+
+/// The world is drawn into this, so lighting can go past 1 until tonemapping brings it back
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Bloom is blurred at a fraction of the target's size, which also widens the blur for free
+const BLOOM_SIZE_DIVISOR: u32 = 2;
+
+const POST_SHADER: ShaderFile = shader_file!("src/engine/render_system/post.wgsl");
+
+/// Effects of the post-processing chain that can be toggled at runtime
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PostEffect {
+    Bloom,
+    Tonemapping,
+    Fxaa,
+    GammaCorrection,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PostSettings {
+    /// Multiplies the HDR color before tonemapping
+    pub exposure: f32,
+    pub gamma: f32,
+    /// Luminance above which pixels start to bloom
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Bloom is added during tonemapping, so it needs both enabled
+    pub bloom: bool,
+    /// Without it anything brighter than 1 clips
+    pub tonemapping: bool,
+    pub fxaa: bool,
+    pub gamma_correction: bool,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            gamma: 2.2,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.5,
+            bloom: false,
+            tonemapping: true,
+            fxaa: true,
+            gamma_correction: true,
+        }
+    }
+}
+
+impl PostSettings {
+    pub fn toggled(self, effect: PostEffect) -> Self {
+        match effect {
+            PostEffect::Bloom => Self {
+                bloom: !self.bloom,
+                ..self
+            },
+            PostEffect::Tonemapping => Self {
+                tonemapping: !self.tonemapping,
+                ..self
+            },
+            PostEffect::Fxaa => Self {
+                fxaa: !self.fxaa,
+                ..self
+            },
+            PostEffect::GammaCorrection => Self {
+                gamma_correction: !self.gamma_correction,
+                ..self
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct PostUniform {
+    /// Exposure, gamma, bloom threshold and bloom intensity
+    params: [f32; 4],
+    /// Gamma correction and whether the target is sRGB
+    flags: [u32; 4],
+}
+
+/// Textures the chain passes the frame along in, allocated by the render graph
+#[derive(Copy, Clone)]
+struct PostAttachments {
+    hdr: Resource,
+    /// Thresholded at half size, then blurred back into itself through `bloom_blur`
+    bloom: Resource,
+    bloom_blur: Resource,
+    tonemapped: Resource,
+    antialiased: Resource,
+}
+
+/// One bind group for every texture a pass can read, they point into a particular graph's
+/// attachments and have to be recreated along with them
+pub struct PostBindGroups {
+    hdr: BindGroup,
+    bloom: BindGroup,
+    bloom_blur: BindGroup,
+    tonemapped: BindGroup,
+    antialiased: BindGroup,
+    /// Reads the HDR target with the blurred bloom on top
+    tonemap: BindGroup,
+}
+
+struct PostPipelines {
+    bloom_threshold: RenderPipeline,
+    blur_horizontal: RenderPipeline,
+    blur_vertical: RenderPipeline,
+    tonemap: RenderPipeline,
+    fxaa: RenderPipeline,
+    output: RenderPipeline,
+}
+
+/// Fullscreen passes taking the HDR frame to the target: bloom, exposure and tonemapping, FXAA and
+/// gamma. Disabled effects are skipped and the next pass reads whatever came before
+pub struct PostProcessing {
+    settings: PostSettings,
+    attachments: PostAttachments,
+    uniform_buffer: Buffer,
+    sampler: Sampler,
+    layout: BindGroupLayout,
+    tonemap_layout: BindGroupLayout,
+    pipelines: PostPipelines,
+}
+
+impl PostProcessing {
+    /// Adds the chain's passes and attachments to `graph`, `hdr` is what the world was drawn into
+    pub fn new(gpu_ctx: &GpuCtx, graph: &mut RenderGraph, hdr: Resource) -> Self {
+        let attachments = add_post_passes(gpu_ctx, graph, hdr);
+
+        let uniform_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[PostUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let sampler = gpu_ctx.device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let layout = create_post_bind_group_layout(gpu_ctx, false);
+        let tonemap_layout = create_post_bind_group_layout(gpu_ctx, true);
+        let pipelines = create_post_pipelines(
+            gpu_ctx,
+            &layout,
+            &tonemap_layout,
+            &POST_SHADER.create_module(gpu_ctx),
+        );
+
+        Self {
+            settings: PostSettings::default(),
+            attachments,
+            uniform_buffer,
+            sampler,
+            layout,
+            tonemap_layout,
+            pipelines,
+        }
+    }
+
+    /// Adds the chain to a graph that was rebuilt from scratch, bind groups have to be recreated
+    pub fn register(&mut self, gpu_ctx: &GpuCtx, graph: &mut RenderGraph, hdr: Resource) {
+        self.attachments = add_post_passes(gpu_ctx, graph, hdr);
+    }
+
+    pub fn settings(&self) -> PostSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: PostSettings) {
+        self.settings = settings;
+    }
+
+    /// Builds the pipelines from the shader on disk without touching the current ones,
+    /// the returned function swaps them in
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &self,
+        gpu_ctx: &GpuCtx,
+    ) -> Result<impl FnOnce(&mut Self) + use<>, ShaderError> {
+        let pipelines = validate(gpu_ctx, || {
+            let shader = POST_SHADER.reload_module(gpu_ctx)?;
+            Ok(create_post_pipelines(
+                gpu_ctx,
+                &self.layout,
+                &self.tonemap_layout,
+                &shader,
+            ))
+        })?;
+        Ok(move |post: &mut Self| post.pipelines = pipelines)
+    }
+
+    /// Bind groups reading `graph`'s attachments, which have to come from the graph passed to `new`
+    /// or a resized copy of it
+    pub fn bind(&self, gpu_ctx: &GpuCtx, graph: &RenderGraph) -> PostBindGroups {
+        let bind = |layout: &BindGroupLayout, src: Resource, bloom: Option<Resource>| {
+            let mut entries = vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(graph.view(src)),
+                },
+            ];
+            if let Some(bloom) = bloom {
+                entries.push(BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(graph.view(bloom)),
+                });
+            }
+            gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &entries,
+            })
+        };
+
+        let attachments = self.attachments;
+        PostBindGroups {
+            hdr: bind(&self.layout, attachments.hdr, None),
+            bloom: bind(&self.layout, attachments.bloom, None),
+            bloom_blur: bind(&self.layout, attachments.bloom_blur, None),
+            tonemapped: bind(&self.layout, attachments.tonemapped, None),
+            antialiased: bind(&self.layout, attachments.antialiased, None),
+            tonemap: bind(
+                &self.tonemap_layout,
+                attachments.hdr,
+                Some(attachments.bloom),
+            ),
+        }
+    }
+
+    pub fn update_buffer(&self, gpu_ctx: &GpuCtx) {
+        let settings = self.settings;
+        let bloom_intensity = if settings.bloom {
+            settings.bloom_intensity
+        } else {
+            0.0
+        };
+        let uniform = PostUniform {
+            params: [
+                settings.exposure,
+                settings.gamma,
+                settings.bloom_threshold,
+                bloom_intensity,
+            ],
+            flags: [
+                settings.gamma_correction as u32,
+                gpu_ctx.surface_format.is_srgb() as u32,
+                0,
+                0,
+            ],
+        };
+        gpu_ctx
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Skipped without bloom or tonemapping, which the bloom gets added in
+    fn draw_bloom(&self, ctx: &mut PassContext) {
+        if !(self.settings.bloom && self.settings.tonemapping) {
+            return;
+        }
+        let (graph, bind_groups) = (ctx.graph, ctx.post_bind_groups);
+        let bloom = graph.view(self.attachments.bloom);
+        let bloom_blur = graph.view(self.attachments.bloom_blur);
+        let pipelines = &self.pipelines;
+        draw_fullscreen(
+            ctx.encoder,
+            bloom,
+            &pipelines.bloom_threshold,
+            &bind_groups.hdr,
+        );
+        draw_fullscreen(
+            ctx.encoder,
+            bloom_blur,
+            &pipelines.blur_horizontal,
+            &bind_groups.bloom,
+        );
+        draw_fullscreen(
+            ctx.encoder,
+            bloom,
+            &pipelines.blur_vertical,
+            &bind_groups.bloom_blur,
+        );
+    }
+
+    fn draw_tonemap(&self, ctx: &mut PassContext) {
+        if self.settings.tonemapping {
+            draw_fullscreen(
+                ctx.encoder,
+                ctx.graph.view(self.attachments.tonemapped),
+                &self.pipelines.tonemap,
+                &ctx.post_bind_groups.tonemap,
+            );
+        }
+    }
+
+    fn draw_fxaa(&self, ctx: &mut PassContext) {
+        if self.settings.fxaa {
+            draw_fullscreen(
+                ctx.encoder,
+                ctx.graph.view(self.attachments.antialiased),
+                &self.pipelines.fxaa,
+                self.fxaa_source(ctx.post_bind_groups),
+            );
+        }
+    }
+
+    /// The only one drawing into the target
+    fn draw_output(&self, ctx: &mut PassContext) {
+        let source = if self.settings.fxaa {
+            &ctx.post_bind_groups.antialiased
+        } else {
+            self.fxaa_source(ctx.post_bind_groups)
+        };
+        draw_fullscreen(ctx.encoder, ctx.target_view, &self.pipelines.output, source);
+    }
+
+    fn fxaa_source<'a>(&self, bind_groups: &'a PostBindGroups) -> &'a BindGroup {
+        if self.settings.tonemapping {
+            &bind_groups.tonemapped
+        } else {
+            &bind_groups.hdr
+        }
+    }
+}
+
+fn add_post_passes(gpu_ctx: &GpuCtx, graph: &mut RenderGraph, hdr: Resource) -> PostAttachments {
+    let mut add_attachment = |label, size_divisor| {
+        graph.add_attachment(
+            gpu_ctx,
+            AttachmentDesc {
+                label,
+                format: HDR_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                size_divisor,
+                sample_count: 1,
+            },
+        )
+    };
+    let attachments = PostAttachments {
+        hdr,
+        bloom: add_attachment("bloom", BLOOM_SIZE_DIVISOR),
+        bloom_blur: add_attachment("bloom blur", BLOOM_SIZE_DIVISOR),
+        tonemapped: add_attachment("tonemapped", 1),
+        antialiased: add_attachment("antialiased", 1),
+    };
+
+    graph.add_pass(PassDesc {
+        name: PassName::Bloom,
+        reads: vec![hdr],
+        writes: vec![attachments.bloom, attachments.bloom_blur],
+        execute: |render_system, ctx| render_system.post.draw_bloom(ctx),
+    });
+    graph.add_pass(PassDesc {
+        name: PassName::Tonemap,
+        reads: vec![hdr, attachments.bloom],
+        writes: vec![attachments.tonemapped],
+        execute: |render_system, ctx| render_system.post.draw_tonemap(ctx),
+    });
+    graph.add_pass(PassDesc {
+        name: PassName::Fxaa,
+        reads: vec![hdr, attachments.tonemapped],
+        writes: vec![attachments.antialiased],
+        execute: |render_system, ctx| render_system.post.draw_fxaa(ctx),
+    });
+    graph.add_pass(PassDesc {
+        name: PassName::Output,
+        reads: vec![hdr, attachments.tonemapped, attachments.antialiased],
+        writes: vec![Resource::Target],
+        execute: |render_system, ctx| render_system.post.draw_output(ctx),
+    });
+    attachments
+}
+
+fn draw_fullscreen(
+    encoder: &mut CommandEncoder,
+    target: &TextureView,
+    pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+) {
+    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+/// Tonemapping reads the blurred bloom as a second texture
+fn create_post_bind_group_layout(gpu_ctx: &GpuCtx, with_bloom: bool) -> BindGroupLayout {
+    let texture_entry = |binding| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let mut entries = vec![
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        texture_entry(2),
+    ];
+    if with_bloom {
+        entries.push(texture_entry(3));
+    }
+
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        })
+}
+
+fn create_post_pipelines(
+    gpu_ctx: &GpuCtx,
+    layout: &BindGroupLayout,
+    tonemap_layout: &BindGroupLayout,
+    shader: &ShaderModule,
+) -> PostPipelines {
+    let create_pipeline = |bind_group_layout, entry_point, format| {
+        let layout = gpu_ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        gpu_ctx
+            .device
+            .create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: shader,
+                    entry_point: Some("v_main"),
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                fragment: Some(FragmentState {
+                    module: shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+    };
+
+    PostPipelines {
+        bloom_threshold: create_pipeline(layout, "bloom_threshold", HDR_FORMAT),
+        blur_horizontal: create_pipeline(layout, "blur_horizontal", HDR_FORMAT),
+        blur_vertical: create_pipeline(layout, "blur_vertical", HDR_FORMAT),
+        tonemap: create_pipeline(tonemap_layout, "tonemap", HDR_FORMAT),
+        fxaa: create_pipeline(layout, "fxaa", HDR_FORMAT),
+        output: create_pipeline(layout, "output", gpu_ctx.surface_format),
+    }
+}
@@ -0,0 +1,307 @@
+use crate::engine::gpu::{Frustum, GpuCtx};
+use crate::engine::render_system::post_processing::PostBindGroups;
+use crate::engine::render_system::{RenderSystem, Renderable};
+use wgpu::{
+    CommandEncoder, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// Passes of a frame. Renderables pick the ones they draw into, the graph decides the order
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PassName {
+    /// Depth only, once per shadow cascade with the light's view projection bound at group 0
+    Shadow,
+    /// Solid geometry, drawn into the HDR target after the sky with depth testing and writing
+    Opaque,
+    /// Builds the Hi-Z pyramid from the finished depth buffer, nothing draws into it
+    HiZ,
+    /// Post-processing, see `PostProcessing`. Nothing draws into these either
+    Bloom,
+    Tonemap,
+    Fxaa,
+    Output,
+    /// Screen space, drawn straight into the target over the finished frame without depth
+    Overlay,
+}
+
+/// Anything a pass reads or writes, used to order the passes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// The window or offscreen texture the frame ends up in
+    Target,
+    /// Cascades owned by the shadow maps, their size follows the shadow settings, not the target
+    ShadowMaps,
+    /// Texture allocated by the graph, sized after the target
+    Attachment(usize),
+}
+
+#[derive(Clone)]
+pub struct AttachmentDesc {
+    pub label: &'static str,
+    pub format: TextureFormat,
+    pub usage: TextureUsages,
+    /// Each side is the target's divided by this, rounded up
+    pub size_divisor: u32,
+    pub sample_count: u32,
+}
+
+/// What a pass records its commands with, besides the render system it belongs to
+pub struct PassContext<'a> {
+    pub encoder: &'a mut CommandEncoder,
+    pub graph: &'a RenderGraph,
+    pub renderables: &'a [&'a dyn Renderable],
+    /// The camera's
+    pub frustum: Frustum,
+    pub post_bind_groups: &'a PostBindGroups,
+    /// The window or offscreen texture the frame ends up in
+    pub target_view: &'a TextureView,
+    /// Off for screenshots, the Hi-Z pyramid keeps the window's size and last frame
+    pub build_hi_z: bool,
+}
+
+/// A plain function rather than a closure so graphs can be cloned for other sizes
+pub type ExecutePass = fn(&RenderSystem, &mut PassContext);
+
+#[derive(Clone)]
+pub struct PassDesc {
+    pub name: PassName,
+    pub reads: Vec<Resource>,
+    pub writes: Vec<Resource>,
+    pub execute: ExecutePass,
+}
+
+struct Attachment {
+    desc: AttachmentDesc,
+    /// Kept alive for `view`
+    _texture: Texture,
+    view: TextureView,
+}
+
+/// Which passes a frame runs and in what order, and the transient textures they share. A pass reads
+/// what the last pass added before it wrote, or what every writer wrote if none was added before
+/// it. Passes writing something run after the passes added before them that write or read it
+pub struct RenderGraph {
+    attachments: Vec<Attachment>,
+    passes: Vec<PassDesc>,
+    /// Indices into `passes`
+    order: Vec<usize>,
+    size: (u32, u32),
+}
+
+impl RenderGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            attachments: Vec::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+            size: (width, height),
+        }
+    }
+
+    pub fn add_attachment(&mut self, gpu_ctx: &GpuCtx, desc: AttachmentDesc) -> Resource {
+        let (width, height) = self.size;
+        self.attachments
+            .push(create_attachment(gpu_ctx, desc, width, height));
+        Resource::Attachment(self.attachments.len() - 1)
+    }
+
+    /// Panics if the pass makes the graph cyclic
+    pub fn add_pass(&mut self, desc: PassDesc) {
+        assert!(
+            !self.passes.iter().any(|pass| pass.name == desc.name),
+            "{:?} was added to the render graph twice",
+            desc.name
+        );
+        self.passes.push(desc);
+        self.order = self.sort_passes();
+    }
+
+    /// Reallocates every attachment, views from before are stale afterwards
+    pub fn resize(&mut self, gpu_ctx: &GpuCtx, width: u32, height: u32) {
+        self.size = (width, height);
+        self.attachments = std::mem::take(&mut self.attachments)
+            .into_iter()
+            .map(|attachment| create_attachment(gpu_ctx, attachment.desc, width, height))
+            .collect();
+    }
+
+    /// The same passes with attachments of their own, for drawing a frame at another size
+    pub fn with_size(&self, gpu_ctx: &GpuCtx, width: u32, height: u32) -> Self {
+        Self {
+            attachments: self
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    create_attachment(gpu_ctx, attachment.desc.clone(), width, height)
+                })
+                .collect(),
+            passes: self.passes.clone(),
+            order: self.order.clone(),
+            size: (width, height),
+        }
+    }
+
+    /// Panics for resources the graph doesn't allocate
+    pub fn view(&self, resource: Resource) -> &TextureView {
+        match resource {
+            Resource::Attachment(index) => &self.attachments[index].view,
+            _ => panic!("{resource:?} isn't allocated by the render graph"),
+        }
+    }
+
+    /// In the order they have to run
+    pub fn passes(&self) -> impl Iterator<Item = &PassDesc> + '_ {
+        self.order.iter().map(|&index| &self.passes[index])
+    }
+
+    /// Topological sort that picks the earliest added pass whenever several are ready
+    fn sort_passes(&self) -> Vec<usize> {
+        let first_writer = |resource: &Resource| {
+            self.passes
+                .iter()
+                .position(|pass| pass.writes.contains(resource))
+        };
+        let depends_on = |pass: usize, other: usize| {
+            let (desc, other_desc) = (&self.passes[pass], &self.passes[other]);
+            let written_after = desc.writes.iter().any(|resource| {
+                other < pass
+                    && (other_desc.writes.contains(resource)
+                        || other_desc.reads.contains(resource)
+                            && first_writer(resource).is_some_and(|first| first < other))
+            });
+            let read_after = desc.reads.iter().any(|resource| {
+                other_desc.writes.contains(resource)
+                    && (other < pass || first_writer(resource).is_some_and(|first| first > pass))
+            });
+            written_after || read_after
+        };
+
+        let count = self.passes.len();
+        let mut order = Vec::with_capacity(count);
+        let mut done = vec![false; count];
+        while order.len() < count {
+            let next = (0..count)
+                .find(|&pass| {
+                    !done[pass]
+                        && (0..count)
+                            .all(|other| other == pass || done[other] || !depends_on(pass, other))
+                })
+                .expect("The render graph has a cycle between its passes");
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+}
+
+fn create_attachment(
+    gpu_ctx: &GpuCtx,
+    desc: AttachmentDesc,
+    width: u32,
+    height: u32,
+) -> Attachment {
+    let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
+        label: Some(desc.label),
+        size: Extent3d {
+            width: width.div_ceil(desc.size_divisor).max(1),
+            height: height.div_ceil(desc.size_divisor).max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: desc.sample_count,
+        dimension: TextureDimension::D2,
+        format: desc.format,
+        usage: desc.usage,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    Attachment {
+        desc,
+        _texture: texture,
+        view,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Resource = Resource::Attachment(0);
+    const B: Resource = Resource::Attachment(1);
+
+    fn pass(name: PassName, reads: &[Resource], writes: &[Resource]) -> PassDesc {
+        PassDesc {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            execute: |_, _| {},
+        }
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<PassName> {
+        graph.passes().map(|pass| pass.name).collect()
+    }
+
+    #[test]
+    fn readers_run_after_writers_added_later() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Output, &[A], &[Resource::Target]));
+        graph.add_pass(pass(PassName::Tonemap, &[B], &[A]));
+        graph.add_pass(pass(PassName::Opaque, &[], &[B]));
+        assert_eq!(
+            order(&graph),
+            [PassName::Opaque, PassName::Tonemap, PassName::Output]
+        );
+    }
+
+    #[test]
+    fn writers_keep_the_order_they_were_added_in() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Output, &[], &[Resource::Target]));
+        graph.add_pass(pass(PassName::Overlay, &[], &[Resource::Target]));
+        assert_eq!(order(&graph), [PassName::Output, PassName::Overlay]);
+    }
+
+    #[test]
+    fn writers_wait_for_earlier_readers() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Opaque, &[], &[A]));
+        // Reads what Opaque wrote, but has to wait for Shadow too
+        graph.add_pass(pass(PassName::Bloom, &[A, B], &[]));
+        graph.add_pass(pass(PassName::Tonemap, &[], &[A]));
+        graph.add_pass(pass(PassName::Shadow, &[], &[B]));
+        assert_eq!(
+            order(&graph),
+            [
+                PassName::Opaque,
+                PassName::Shadow,
+                PassName::Bloom,
+                PassName::Tonemap
+            ]
+        );
+    }
+
+    #[test]
+    fn inputs_nothing_writes_are_ignored() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Opaque, &[Resource::ShadowMaps], &[A]));
+        graph.add_pass(pass(PassName::HiZ, &[A], &[]));
+        assert_eq!(order(&graph), [PassName::Opaque, PassName::HiZ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn cycles_panic() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Bloom, &[A], &[B]));
+        graph.add_pass(pass(PassName::Tonemap, &[B], &[A]));
+    }
+
+    #[test]
+    #[should_panic(expected = "added to the render graph twice")]
+    fn passes_are_added_once() {
+        let mut graph = RenderGraph::new(1, 1);
+        graph.add_pass(pass(PassName::Overlay, &[], &[Resource::Target]));
+        graph.add_pass(pass(PassName::Overlay, &[], &[Resource::Target]));
+    }
+}
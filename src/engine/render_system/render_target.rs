@@ -0,0 +1,186 @@
+use crate::engine::gpu::GpuCtx;
+use image::RgbaImage;
+use std::sync::Arc;
+use wgpu::{
+    BufferDescriptor, BufferUsages, COPY_BYTES_PER_ROW_ALIGNMENT, CommandEncoderDescriptor,
+    Extent3d, Maintain, MapMode, Origin3d, Surface, SurfaceConfiguration, SurfaceTexture,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor,
+};
+use winit::window::Window;
+
+/// Where the main pass draws to, a window's swapchain or a texture that can be read back
+pub enum RenderTarget {
+    Window {
+        window: Arc<Window>,
+        surface: Surface<'static>,
+        config: SurfaceConfiguration,
+    },
+    Offscreen {
+        texture: Texture,
+    },
+}
+
+/// The texture a single frame draws into
+pub struct TargetFrame {
+    pub view: TextureView,
+    surface_texture: Option<SurfaceTexture>,
+}
+
+impl RenderTarget {
+    pub fn offscreen(gpu_ctx: &GpuCtx, width: u32, height: u32) -> Self {
+        Self::Offscreen {
+            texture: create_offscreen_texture(gpu_ctx, width, height),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Self::Window { config, .. } => (config.width, config.height),
+            Self::Offscreen { texture } => (texture.width(), texture.height()),
+        }
+    }
+
+    pub fn resize(&mut self, gpu_ctx: &GpuCtx, width: u32, height: u32) {
+        match self {
+            Self::Window {
+                surface, config, ..
+            } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(&gpu_ctx.device, config);
+            }
+            Self::Offscreen { texture } => {
+                *texture = create_offscreen_texture(gpu_ctx, width, height);
+            }
+        }
+    }
+
+    /// `None` when the swapchain has nothing to draw into right now, e.g. while minimized
+    pub fn begin_frame(&self) -> Option<TargetFrame> {
+        match self {
+            Self::Window { surface, .. } => {
+                let surface_texture = surface.get_current_texture().ok()?;
+                let view = surface_texture
+                    .texture
+                    .create_view(&TextureViewDescriptor::default());
+                Some(TargetFrame {
+                    view,
+                    surface_texture: Some(surface_texture),
+                })
+            }
+            Self::Offscreen { texture } => Some(TargetFrame {
+                view: texture.create_view(&TextureViewDescriptor::default()),
+                surface_texture: None,
+            }),
+        }
+    }
+
+    /// Call after the frame's commands were submitted
+    pub fn present(&self, frame: TargetFrame) {
+        if let (Self::Window { window, .. }, Some(surface_texture)) = (self, frame.surface_texture)
+        {
+            window.pre_present_notify();
+            surface_texture.present();
+        }
+    }
+
+    /// Copies the last frame back to the CPU, only offscreen targets can be read
+    pub fn read_pixels(&self, gpu_ctx: &GpuCtx) -> Option<RgbaImage> {
+        match self {
+            Self::Window { .. } => None,
+            Self::Offscreen { texture } => read_texture(gpu_ctx, texture),
+        }
+    }
+}
+
+/// Copies a texture that was rendered with `surface_format` back to the CPU. sRGB and linear
+/// formats are both returned as the bytes that would end up on screen, `None` for formats that
+/// aren't 8 bits per channel
+fn read_texture(gpu_ctx: &GpuCtx, texture: &Texture) -> Option<RgbaImage> {
+    let swap_red_blue = match texture.format() {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+        _ => return None,
+    };
+    let (width, height) = (texture.width(), texture.height());
+
+    // Rows in the buffer have to be padded to the copy alignment
+    let unpadded_bytes_per_row = width * 4;
+    let bytes_per_row =
+        unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (bytes_per_row * height) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = gpu_ctx
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    gpu_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, |result| {
+        result.expect("Failed to map the readback buffer!")
+    });
+    gpu_ctx.device.poll(Maintain::Wait);
+
+    let mapped = slice.get_mapped_range();
+    let mut pixels: Vec<u8> = mapped
+        .chunks(bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect();
+    drop(mapped);
+    buffer.unmap();
+
+    if swap_red_blue {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    RgbaImage::from_raw(width, height, pixels)
+}
+
+fn create_offscreen_texture(gpu_ctx: &GpuCtx, width: u32, height: u32) -> Texture {
+    gpu_ctx.device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: gpu_ctx.surface_format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
@@ -1,5 +1,27 @@
-use wgpu::RenderPass;
+use crate::engine::gpu::{DepthMode, Frustum, GpuCtx};
+use crate::engine::render_system::{HiZPyramid, PassName};
+use cgmath::Matrix4;
+use wgpu::{CommandEncoder, RenderPass};
+
+/// What a renderable gets to know about the frame before it is drawn
+pub struct FrameContext<'a> {
+    pub gpu_ctx: &'a GpuCtx,
+    pub view_proj: Matrix4<f32>,
+    pub frustum: Frustum,
+    /// Built from the previous frame's depth buffer
+    pub hi_z: &'a HiZPyramid,
+    pub viewport: (u32, u32),
+    pub depth_mode: DepthMode,
+}
 
 pub trait Renderable {
-    fn render(&self, pass: &mut RenderPass);
+    /// Runs before the render pass, for compute work such as culling
+    fn prepare(&self, _encoder: &mut CommandEncoder, _frame: &FrameContext) {}
+
+    /// The render graph's passes `render` gets called for
+    fn passes(&self) -> &[PassName];
+
+    /// `frustum` is what the pass can see, a shadow cascade's for `PassName::Shadow` and the
+    /// camera's otherwise
+    fn render(&self, pass_name: PassName, pass: &mut RenderPass, frustum: &Frustum);
 }
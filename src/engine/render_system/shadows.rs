@@ -0,0 +1,364 @@
+use crate::engine::gpu::{Camera, DepthMode, Frustum, GpuCtx, OPENGL_TO_WGPU_MATRIX};
+use crate::engine::render_system::{PassName, Renderable, create_depth_texture, draw_pass};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, ortho,
+};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    CommandEncoder, LoadOp, Operations, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    Sampler, SamplerBindingType, ShaderStages, StoreOp, Texture, TextureSampleType, TextureView,
+    TextureViewDescriptor, TextureViewDimension,
+};
+
+pub const MAX_SHADOW_CASCADES: u32 = 4;
+const SHADOW_RESOLUTIONS: [u32; 3] = [1024, 2048, 4096];
+/// Nothing further than this many blocks from the camera is shadowed
+const SHADOW_DISTANCE: f32 = 192.0;
+/// Blend between logarithmic (1.0) and evenly spaced (0.0) cascade splits
+const SPLIT_LAMBDA: f32 = 0.5;
+/// Extra room towards the light so casters outside a cascade's view still land in its map,
+/// the world is 256 blocks tall
+const CASTER_MARGIN: f32 = 256.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ShadowSettings {
+    /// Between 1 and `MAX_SHADOW_CASCADES`
+    pub cascade_count: u32,
+    /// Width and height of each cascade's map in texels
+    pub resolution: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascade_count: 3,
+            resolution: 2048,
+        }
+    }
+}
+
+impl ShadowSettings {
+    pub fn next_cascade_count(self) -> Self {
+        Self {
+            cascade_count: self.cascade_count % MAX_SHADOW_CASCADES + 1,
+            ..self
+        }
+    }
+
+    pub fn next_resolution(self) -> Self {
+        let next = SHADOW_RESOLUTIONS
+            .iter()
+            .position(|&resolution| resolution == self.resolution)
+            .map_or(0, |i| (i + 1) % SHADOW_RESOLUTIONS.len());
+        Self {
+            resolution: SHADOW_RESOLUTIONS[next],
+            ..self
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; MAX_SHADOW_CASCADES as usize],
+    /// Far view depth of each cascade
+    splits: [f32; 4],
+    /// World size of a texel in each cascade
+    texel_sizes: [f32; 4],
+    camera_forward: [f32; 4],
+    cascade_count: u32,
+    resolution: f32,
+    _padding: [u32; 2],
+}
+
+/// Depth maps of the world from the light, each cascade covers a further slice of the view
+/// at the same resolution so shadows near the camera get the most detail
+pub struct ShadowMaps {
+    settings: ShadowSettings,
+    _texture: Texture,
+    layer_views: Vec<TextureView>,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    cascade_buffers: Vec<Buffer>,
+    cascade_bind_groups: Vec<BindGroup>,
+}
+
+impl ShadowMaps {
+    pub fn new(gpu_ctx: &GpuCtx, settings: ShadowSettings) -> Self {
+        let settings = ShadowSettings {
+            cascade_count: settings.cascade_count.clamp(1, MAX_SHADOW_CASCADES),
+            ..settings
+        };
+
+        // Always standard depth, the comparison sampler tests with `LessEqual`
+        let (texture, view, sampler) = create_depth_texture(
+            gpu_ctx,
+            settings.resolution,
+            settings.resolution,
+            settings.cascade_count,
+            TextureViewDimension::D2Array,
+            DepthMode::Standard,
+        );
+        let layer_views = (0..settings.cascade_count)
+            .map(|layer| {
+                texture.create_view(&TextureViewDescriptor {
+                    dimension: Some(TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let uniform_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[ShadowUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = create_shadow_bind_group(gpu_ctx, &uniform_buffer, &view, &sampler);
+
+        // Bound in place of the camera while drawing each cascade
+        let cascade_layout = create_cascade_bind_group_layout(gpu_ctx);
+        let identity: [[f32; 4]; 4] = Matrix4::identity().into();
+        let cascade_buffers: Vec<_> = (0..settings.cascade_count)
+            .map(|_| {
+                gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&[identity]),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout: &cascade_layout,
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                })
+            })
+            .collect();
+
+        Self {
+            settings,
+            _texture: texture,
+            layer_views,
+            uniform_buffer,
+            bind_group,
+            cascade_buffers,
+            cascade_bind_groups,
+        }
+    }
+
+    pub fn settings(&self) -> ShadowSettings {
+        self.settings
+    }
+
+    /// Fits each cascade around its slice of the camera's view and draws the shadow casters into it
+    pub fn render(
+        &self,
+        gpu_ctx: &GpuCtx,
+        encoder: &mut CommandEncoder,
+        camera: &Camera,
+        (z_near, view_distance): (f32, f32),
+        light_direction: Vector3<f32>,
+        renderables: &[&dyn Renderable],
+    ) {
+        let cascade_count = self.settings.cascade_count;
+        let z_far = view_distance.min(SHADOW_DISTANCE).max(z_near * 2.0);
+        let mut uniform = ShadowUniform {
+            camera_forward: camera.get_forward().extend(0.0).into(),
+            cascade_count,
+            resolution: self.settings.resolution as f32,
+            ..ShadowUniform::zeroed()
+        };
+
+        let mut split_near = z_near;
+        for cascade in 0..cascade_count as usize {
+            let split_far = split_distance(z_near, z_far, cascade + 1, cascade_count as usize);
+            let corners = camera.frustum_corners(split_near, split_far);
+            let (light_view_proj, texel_size) =
+                fit_cascade(&corners, light_direction, self.settings.resolution);
+
+            uniform.light_view_proj[cascade] = light_view_proj.into();
+            uniform.splits[cascade] = split_far;
+            uniform.texel_sizes[cascade] = texel_size;
+            let matrix: [[f32; 4]; 4] = light_view_proj.into();
+            gpu_ctx.queue.write_buffer(
+                &self.cascade_buffers[cascade],
+                0,
+                bytemuck::cast_slice(&[matrix]),
+            );
+
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[cascade],
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(DepthMode::Standard.clear_value()),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_bind_group(0, &self.cascade_bind_groups[cascade], &[]);
+            let frustum = Frustum::from_view_proj(light_view_proj);
+            draw_pass(renderables, PassName::Shadow, &mut pass, &frustum);
+
+            split_near = split_far;
+        }
+
+        gpu_ctx
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
+
+fn split_distance(z_near: f32, z_far: f32, split: usize, count: usize) -> f32 {
+    let t = split as f32 / count as f32;
+    let logarithmic = z_near * (z_far / z_near).powf(t);
+    let uniform = z_near + (z_far - z_near) * t;
+    SPLIT_LAMBDA * logarithmic + (1.0 - SPLIT_LAMBDA) * uniform
+}
+
+/// Orthographic projection from the light around a bounding sphere of the corners. The sphere keeps
+/// the cascade the same size as the camera turns and the center is snapped to whole texels, so
+/// shadow edges don't shimmer while moving
+fn fit_cascade(
+    corners: &[Point3<f32>; 8],
+    light_direction: Vector3<f32>,
+    resolution: u32,
+) -> (Matrix4<f32>, f32) {
+    let center = Point3::centroid(corners);
+    let radius = corners
+        .iter()
+        .map(|corner| (corner - center).magnitude())
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+    let texel_size = radius * 2.0 / resolution as f32;
+
+    let light_direction = light_direction.normalize();
+    let up = if light_direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let light_rotation = Matrix4::look_to_rh(Point3::origin(), -light_direction, up);
+    let mut light_center = light_rotation.transform_point(center);
+    light_center.x = (light_center.x / texel_size).floor() * texel_size;
+    light_center.y = (light_center.y / texel_size).floor() * texel_size;
+    let center = light_rotation
+        .invert()
+        .unwrap_or(Matrix4::identity())
+        .transform_point(light_center);
+
+    let eye = center + light_direction * (radius + CASTER_MARGIN);
+    let light_view = Matrix4::look_to_rh(eye, -light_direction, up);
+    let light_proj = OPENGL_TO_WGPU_MATRIX
+        * ortho(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            radius * 2.0 + CASTER_MARGIN,
+        );
+
+    (light_proj * light_view, texel_size)
+}
+
+/// Pipelines sampling the shadow maps need a layout matching this one
+pub fn create_shadow_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        })
+}
+
+/// Pipelines drawing into the shadow maps need this layout at group 0, matching the camera's
+pub fn create_cascade_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+}
+
+fn create_shadow_bind_group(
+    gpu_ctx: &GpuCtx,
+    uniform_buffer: &Buffer,
+    view: &TextureView,
+    sampler: &Sampler,
+) -> BindGroup {
+    gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &create_shadow_bind_group_layout(gpu_ctx),
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
@@ -0,0 +1,294 @@
+use crate::engine::gpu::{GpuCtx, ShaderFile, shader_file};
+use crate::engine::render_system::HDR_FORMAT;
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState,
+    FragmentState, MultisampleState, PipelineCompilationOptions, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderStages,
+    StencilState, TextureFormat, VertexState,
+};
+
+const DAY_SUN_COLOR: [f32; 3] = [1.0, 0.9, 0.7];
+const DAY_ZENITH_COLOR: [f32; 3] = [0.12, 0.3, 0.75];
+const DAY_HORIZON_COLOR: [f32; 3] = [0.55, 0.7, 0.9];
+const SUNSET_SUN_COLOR: [f32; 3] = [1.0, 0.45, 0.15];
+const SUNSET_ZENITH_COLOR: [f32; 3] = [0.15, 0.15, 0.4];
+const SUNSET_HORIZON_COLOR: [f32; 3] = [0.85, 0.4, 0.2];
+const NIGHT_ZENITH_COLOR: [f32; 3] = [0.004, 0.006, 0.02];
+const NIGHT_HORIZON_COLOR: [f32; 3] = [0.015, 0.02, 0.05];
+/// Terrain never gets darker than this at night
+const NIGHT_LIGHT: f32 = 0.15;
+/// How much moonlight shades faces compared to sunlight
+const MOON_STRENGTH: f32 = 0.5;
+
+const SKY_SHADER: ShaderFile = shader_file!("src/engine/render_system/sky.wgsl");
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct EnvironmentUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    camera_pos: [f32; 4],
+    sun_direction: [f32; 4],
+    moon_direction: [f32; 4],
+    sun_color: [f32; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+    fog: [f32; 4],
+    light: [f32; 4],
+}
+
+/// Sky colors, sun, moon, lighting and fog shared by every pass. The sky is drawn behind everything
+/// else and terrain fades into the horizon color, so nothing pops in at the edge of render distance
+pub struct Sky {
+    sun_direction: Vector3<f32>,
+    moon_direction: Vector3<f32>,
+    sun_color: [f32; 3],
+    zenith_color: [f32; 3],
+    horizon_color: [f32; 3],
+    /// Multiplier for all terrain lighting
+    light: f32,
+    sun_strength: f32,
+    moon_strength: f32,
+    fog_start: f32,
+    fog_end: f32,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    /// Kept for recreating the pipeline when the sample count changes
+    shader: ShaderModule,
+    sample_count: u32,
+    pipeline: RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(gpu_ctx: &GpuCtx, sample_count: u32) -> Self {
+        let uniform_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[EnvironmentUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = create_environment_bind_group_layout(gpu_ctx);
+        let bind_group = gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = SKY_SHADER.create_module(gpu_ctx);
+        let pipeline = create_sky_pipeline(gpu_ctx, &bind_group_layout, &shader, sample_count);
+        let mut sky = Self {
+            sun_direction: Vector3::unit_y(),
+            moon_direction: -Vector3::unit_y(),
+            sun_color: DAY_SUN_COLOR,
+            zenith_color: DAY_ZENITH_COLOR,
+            horizon_color: DAY_HORIZON_COLOR,
+            light: 1.0,
+            sun_strength: 1.0,
+            moon_strength: 0.0,
+            fog_start: 0.0,
+            fog_end: f32::MAX,
+            uniform_buffer,
+            bind_group,
+            shader,
+            sample_count,
+            pipeline,
+        };
+        sky.set_sun_and_moon(sky.sun_direction, sky.moon_direction);
+        sky
+    }
+
+    /// Both point from the world towards the body, colors and lighting follow how high the sun is
+    pub fn set_sun_and_moon(&mut self, sun_direction: Vector3<f32>, moon_direction: Vector3<f32>) {
+        self.sun_direction = sun_direction.normalize();
+        self.moon_direction = moon_direction.normalize();
+
+        let sun_height = self.sun_direction.y;
+        let day = smoothstep(-0.15, 0.25, sun_height);
+        // Peaks while the sun is crossing the horizon
+        let sunset = (1.0 - sun_height.abs() / 0.3).max(0.0);
+        let sun_visible = smoothstep(-0.15, 0.0, sun_height);
+
+        let zenith = mix(NIGHT_ZENITH_COLOR, DAY_ZENITH_COLOR, day);
+        let horizon = mix(NIGHT_HORIZON_COLOR, DAY_HORIZON_COLOR, day);
+        self.zenith_color = mix(zenith, SUNSET_ZENITH_COLOR, sunset * 0.5);
+        self.horizon_color = mix(horizon, SUNSET_HORIZON_COLOR, sunset * 0.6);
+        self.sun_color = mix(DAY_SUN_COLOR, SUNSET_SUN_COLOR, sunset).map(|c| c * sun_visible);
+
+        self.light = NIGHT_LIGHT + (1.0 - NIGHT_LIGHT) * day;
+        self.sun_strength = smoothstep(0.0, 0.2, sun_height);
+        self.moon_strength = smoothstep(0.0, 0.2, self.moon_direction.y) * MOON_STRENGTH;
+    }
+
+    /// Builds the pipeline from the shader on disk without touching the current one,
+    /// the returned function swaps it in
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &self,
+        gpu_ctx: &GpuCtx,
+    ) -> Result<impl FnOnce(&mut Self) + use<>, ShaderError> {
+        let (shader, pipeline) = validate(gpu_ctx, || {
+            let shader = SKY_SHADER.reload_module(gpu_ctx)?;
+            let bind_group_layout = create_environment_bind_group_layout(gpu_ctx);
+            let pipeline =
+                create_sky_pipeline(gpu_ctx, &bind_group_layout, &shader, self.sample_count);
+            Ok((shader, pipeline))
+        })?;
+        Ok(move |sky: &mut Self| {
+            sky.shader = shader;
+            sky.pipeline = pipeline;
+        })
+    }
+
+    /// Has to match the pass the sky is drawn into
+    pub fn set_sample_count(&mut self, gpu_ctx: &GpuCtx, sample_count: u32) {
+        self.sample_count = sample_count;
+        let bind_group_layout = create_environment_bind_group_layout(gpu_ctx);
+        self.pipeline =
+            create_sky_pipeline(gpu_ctx, &bind_group_layout, &self.shader, sample_count);
+    }
+
+    /// Terrain starts fading at `start` blocks from the camera and is fully hidden at `end`
+    pub fn set_fog(&mut self, start: f32, end: f32) {
+        self.fog_start = start;
+        self.fog_end = end.max(start);
+    }
+
+    /// Shadows are cast by whichever of the sun and moon is lighting the world
+    pub fn shadow_light_direction(&self) -> Vector3<f32> {
+        if self.sun_direction.y >= self.moon_direction.y {
+            self.sun_direction
+        } else {
+            self.moon_direction
+        }
+    }
+
+    pub fn update_buffer(
+        &self,
+        gpu_ctx: &GpuCtx,
+        view_proj: Matrix4<f32>,
+        camera_pos: (f32, f32, f32),
+    ) {
+        let inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity());
+        let uniform_data = EnvironmentUniform {
+            inv_view_proj: inv_view_proj.into(),
+            camera_pos: [camera_pos.0, camera_pos.1, camera_pos.2, 1.0],
+            sun_direction: self.sun_direction.extend(0.0).into(),
+            moon_direction: self.moon_direction.extend(0.0).into(),
+            sun_color: with_alpha(self.sun_color),
+            zenith_color: with_alpha(self.zenith_color),
+            horizon_color: with_alpha(self.horizon_color),
+            fog: [self.fog_start, self.fog_end, 0.0, 0.0],
+            light: [self.light, self.sun_strength, self.moon_strength, 0.0],
+        };
+        gpu_ctx.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform_data]),
+        );
+    }
+
+    /// Fills the whole screen without touching depth, so it has to be drawn first
+    pub fn render(&self, pass: &mut RenderPass) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
+
+fn with_alpha([r, g, b]: [f32; 3]) -> [f32; 4] {
+    [r, g, b, 1.0]
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Pipelines reading the environment uniform need a layout matching this one
+pub fn create_environment_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+}
+
+fn create_sky_pipeline(
+    gpu_ctx: &GpuCtx,
+    bind_group_layout: &BindGroupLayout,
+    shader: &ShaderModule,
+    sample_count: u32,
+) -> RenderPipeline {
+    let layout = gpu_ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+    gpu_ctx
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: None,
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: None,
+                targets: &[Some(ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            // Shares the main pass's depth buffer but neither tests nor writes it
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
@@ -0,0 +1,53 @@
+struct Environment {
+    inv_view_proj: mat4x4f,
+    camera_pos: vec4f,
+    // Points towards the sun
+    sun_direction: vec4f,
+    moon_direction: vec4f,
+    sun_color: vec4f,
+    zenith_color: vec4f,
+    horizon_color: vec4f,
+    // x: distance fog starts at, y: distance it is fully opaque at
+    fog: vec4f,
+    // x: multiplier for all lighting, y: sunlight strength, z: moonlight strength
+    light: vec4f,
+}
+
+const MOON_COLOR = vec3f(0.8, 0.85, 1.0);
+
+struct VertexOutput {
+    @builtin(position) pos: vec4f,
+    @location(0) ndc: vec2f,
+}
+
+@group(0) @binding(0)
+var<uniform> environment: Environment;
+
+// One triangle covering the whole screen
+@vertex
+fn v_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2f(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.pos = vec4f(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn f_main(in: VertexOutput) -> @location(0) vec4f {
+    // Any depth strictly inside the clip volume unprojects onto the same view ray
+    let world = environment.inv_view_proj * vec4f(in.ndc, 0.5, 1.0);
+    let direction = normalize(world.xyz / world.w - environment.camera_pos.xyz);
+
+    let height = clamp(direction.y, 0.0, 1.0);
+    var color = mix(environment.horizon_color.rgb, environment.zenith_color.rgb, sqrt(height));
+
+    let towards_sun = max(dot(direction, environment.sun_direction.xyz), 0.0);
+    color += environment.sun_color.rgb * (pow(towards_sun, 8.0) * 0.25 + smoothstep(0.9995, 0.9998, towards_sun));
+
+    let towards_moon = max(dot(direction, environment.moon_direction.xyz), 0.0);
+    color += MOON_COLOR * smoothstep(0.9996, 0.9998, towards_moon) * (1.0 - environment.light.y);
+
+    return vec4f(color, 1.0);
+}
@@ -0,0 +1,502 @@
+use crate::engine::gpu::{GpuCtx, ShaderFile, Vertex, shader_file};
+#[cfg(feature = "hot-reload")]
+use crate::engine::gpu::{ShaderError, validate};
+use crate::engine::render_system::font::{
+    ATLAS_SIZE, GLYPH_SIZE, SOLID, create_atlas, glyph_texel,
+};
+use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferAddress,
+    BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, Extent3d,
+    FragmentState, MultisampleState, Origin3d, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPass, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+const UI_SHADER: ShaderFile = shader_file!("src/engine/render_system/ui.wgsl");
+
+/// Font texels from one line to the next, one more than a glyph so descenders don't touch the
+/// line below
+const LINE_HEIGHT: u32 = GLYPH_SIZE + 1;
+/// Enough for a screen of debug text before the buffer has to grow
+const INITIAL_VERTEX_CAPACITY: u64 = 6 * 4096;
+/// `layer` of vertices reading the font instead of a sprite
+const FONT_LAYER: i32 = -1;
+
+/// Where on the screen a box is placed, offsets move it from there in pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Center,
+    Bottom,
+}
+
+impl Anchor {
+    /// How far along each axis of the screen, and of the box, the anchor is
+    fn fraction(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Bottom => (0.5, 1.0),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct UiVertex {
+    pos: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+    layer: i32,
+}
+
+impl UiVertex {
+    const ATTRIBS: [VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Sint32];
+}
+
+impl Vertex for UiVertex {
+    fn layout<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            attributes: &Self::ATTRIBS,
+            array_stride: size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct UiUniform {
+    screen_size: [f32; 4],
+    flags: [u32; 4],
+}
+
+/// Immediate mode batch of screen space quads, drawn in one call after post-processing. Everything
+/// queued since the last `upload` is drawn until the next one. Positions are in pixels from the top
+/// left corner of the window and colors are sRGB with straight alpha
+pub struct UiBatch {
+    vertices: Vec<UiVertex>,
+    vertex_buffer: Buffer,
+    /// Vertices that fit in `vertex_buffer`
+    vertex_capacity: u64,
+    /// Vertices in `vertex_buffer` from the last upload
+    vertex_count: u32,
+    screen_size: (u32, u32),
+    uniform_buffer: Buffer,
+    _font: Texture,
+    font_view: TextureView,
+    _sprites: Texture,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl UiBatch {
+    pub fn new(gpu_ctx: &GpuCtx, width: u32, height: u32) -> Self {
+        let uniform_buffer = gpu_ctx.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[UiUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let font = create_font_texture(gpu_ctx);
+        let font_view = font.create_view(&TextureViewDescriptor::default());
+        let (sprites, sprites_view) = create_sprite_array(gpu_ctx, &[]);
+        let bind_group = create_ui_bind_group(gpu_ctx, &uniform_buffer, &font_view, &sprites_view);
+
+        let shader = UI_SHADER.create_module(gpu_ctx);
+        let bind_group_layout = create_ui_bind_group_layout(gpu_ctx);
+        let pipeline = create_ui_pipeline(gpu_ctx, &bind_group_layout, &shader);
+
+        Self {
+            vertices: Vec::new(),
+            vertex_buffer: create_vertex_buffer(gpu_ctx, INITIAL_VERTEX_CAPACITY),
+            vertex_capacity: INITIAL_VERTEX_CAPACITY,
+            vertex_count: 0,
+            screen_size: (width, height),
+            uniform_buffer,
+            _font: font,
+            font_view,
+            _sprites: sprites,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Builds the pipeline from the shader on disk without touching the current one,
+    /// the returned function swaps it in
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &self,
+        gpu_ctx: &GpuCtx,
+    ) -> Result<impl FnOnce(&mut Self) + use<>, ShaderError> {
+        let pipeline = validate(gpu_ctx, || {
+            let shader = UI_SHADER.reload_module(gpu_ctx)?;
+            let bind_group_layout = create_ui_bind_group_layout(gpu_ctx);
+            Ok(create_ui_pipeline(gpu_ctx, &bind_group_layout, &shader))
+        })?;
+        Ok(move |ui: &mut Self| ui.pipeline = pipeline)
+    }
+
+    /// Replaces the sprites `sprite` draws from, they become the layers in order and must all
+    /// have the same size
+    pub fn set_sprites(&mut self, gpu_ctx: &GpuCtx, sprites: &[RgbaImage]) {
+        let (texture, view) = create_sprite_array(gpu_ctx, sprites);
+        self.bind_group =
+            create_ui_bind_group(gpu_ctx, &self.uniform_buffer, &self.font_view, &view);
+        self._sprites = texture;
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.screen_size = (width, height);
+    }
+
+    pub fn screen_size(&self) -> (f32, f32) {
+        (self.screen_size.0 as f32, self.screen_size.1 as f32)
+    }
+
+    /// Top left corner of a box of `size` placed at `anchor`, e.g. `Anchor::Bottom` centers it
+    /// horizontally with its bottom edge on the bottom of the screen
+    pub fn anchor(&self, anchor: Anchor, offset: (f32, f32), size: (f32, f32)) -> (f32, f32) {
+        let (screen_width, screen_height) = self.screen_size();
+        let (f_x, f_y) = anchor.fraction();
+        (
+            (f_x * (screen_width - size.0) + offset.0).round(),
+            (f_y * (screen_height - size.1) + offset.1).round(),
+        )
+    }
+
+    /// Pixels `text` covers, each glyph is `scale` pixels per font texel
+    pub fn text_size(text: &str, scale: u32) -> (f32, f32) {
+        let columns = text.lines().map(|line| line.chars().count()).max();
+        let rows = text.lines().count();
+        let glyph = (GLYPH_SIZE * scale) as f32;
+        let line = (LINE_HEIGHT * scale) as f32;
+        (columns.unwrap_or(0) as f32 * glyph, rows as f32 * line)
+    }
+
+    /// `pos` is the top left corner of the first line, every '\n' starts a new one below it
+    pub fn text(&mut self, pos: (f32, f32), scale: u32, color: [f32; 4], text: &str) {
+        let glyph = (GLYPH_SIZE * scale) as f32;
+        let line_height = (LINE_HEIGHT * scale) as f32;
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let (u, v) = glyph_texel(c);
+                self.quad(
+                    (
+                        pos.0 + column as f32 * glyph,
+                        pos.1 + row as f32 * line_height,
+                    ),
+                    (glyph, glyph),
+                    (u as f32, v as f32),
+                    (GLYPH_SIZE as f32, GLYPH_SIZE as f32),
+                    color,
+                    FONT_LAYER,
+                );
+            }
+        }
+    }
+
+    pub fn rect(&mut self, pos: (f32, f32), size: (f32, f32), color: [f32; 4]) {
+        // The middle of the solid glyph, so every fragment reads a set texel
+        let (u, v) = glyph_texel(SOLID);
+        let center = (GLYPH_SIZE / 2) as f32;
+        self.quad(
+            pos,
+            size,
+            (u as f32 + center, v as f32 + center),
+            (0.0, 0.0),
+            color,
+            FONT_LAYER,
+        );
+    }
+
+    /// `layer` picks one of the sprites from `set_sprites`, `tint` multiplies it
+    pub fn sprite(&mut self, pos: (f32, f32), size: (f32, f32), layer: u32, tint: [f32; 4]) {
+        self.quad(pos, size, (0.0, 0.0), (1.0, 1.0), tint, layer as i32);
+    }
+
+    fn quad(
+        &mut self,
+        (x, y): (f32, f32),
+        (width, height): (f32, f32),
+        (u, v): (f32, f32),
+        (tex_width, tex_height): (f32, f32),
+        color: [f32; 4],
+        layer: i32,
+    ) {
+        let vertex = |d_x: f32, d_y: f32| UiVertex {
+            pos: [x + d_x * width, y + d_y * height],
+            tex_coords: [u + d_x * tex_width, v + d_y * tex_height],
+            color,
+            layer,
+        };
+        self.vertices.extend([
+            vertex(0.0, 0.0),
+            vertex(0.0, 1.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(1.0, 0.0),
+        ]);
+    }
+
+    /// Hands everything queued so far to the GPU, it keeps being drawn until the next upload
+    pub fn upload(&mut self, gpu_ctx: &GpuCtx) {
+        let count = self.vertices.len() as u64;
+        if count > self.vertex_capacity {
+            self.vertex_capacity = count.next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(gpu_ctx, self.vertex_capacity);
+        }
+        if count > 0 {
+            gpu_ctx.queue.write_buffer(
+                &self.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.vertices),
+            );
+        }
+        self.vertex_count = count as u32;
+        self.vertices.clear();
+    }
+
+    /// Positions stay in pixels of the window, so a larger screenshot scales the UI up with
+    /// everything else
+    pub fn update_buffer(&self, gpu_ctx: &GpuCtx) {
+        let (width, height) = self.screen_size();
+        let uniform_data = UiUniform {
+            screen_size: [width, height, 0.0, 0.0],
+            flags: [gpu_ctx.surface_format.is_srgb() as u32, 0, 0, 0],
+        };
+        gpu_ctx.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform_data]),
+        );
+    }
+
+    pub fn render(&self, pass: &mut RenderPass) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+fn create_vertex_buffer(gpu_ctx: &GpuCtx, capacity: u64) -> Buffer {
+    gpu_ctx.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: capacity * size_of::<UiVertex>() as u64,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_font_texture(gpu_ctx: &GpuCtx) -> Texture {
+    let (width, height) = ATLAS_SIZE;
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::R8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    gpu_ctx.queue.write_texture(
+        TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        &create_atlas(),
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+    texture
+}
+
+/// A transparent layer is added after the sprites, the GL backend can only tell a texture is an
+/// array from it having more than one layer
+fn create_sprite_array(gpu_ctx: &GpuCtx, sprites: &[RgbaImage]) -> (Texture, TextureView) {
+    let (width, height) = sprites
+        .first()
+        .map_or((1, 1), |sprite| (sprite.width(), sprite.height()));
+    let layers = sprites.len() as u32 + 1;
+
+    let texture = gpu_ctx.device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for (layer, sprite) in sprites.iter().enumerate() {
+        assert_eq!(
+            sprite.dimensions(),
+            (width, height),
+            "Sprites must all have the same size"
+        );
+        gpu_ctx.queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+                aspect: TextureAspect::All,
+            },
+            sprite,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    let view = texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+fn create_ui_bind_group_layout(gpu_ctx: &GpuCtx) -> BindGroupLayout {
+    let texture_entry = |binding, view_dimension| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: false },
+            view_dimension,
+            multisampled: false,
+        },
+        count: None,
+    };
+
+    gpu_ctx
+        .device
+        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1, TextureViewDimension::D2),
+                texture_entry(2, TextureViewDimension::D2Array),
+            ],
+        })
+}
+
+fn create_ui_bind_group(
+    gpu_ctx: &GpuCtx,
+    uniform_buffer: &Buffer,
+    font_view: &TextureView,
+    sprites_view: &TextureView,
+) -> BindGroup {
+    gpu_ctx.device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &create_ui_bind_group_layout(gpu_ctx),
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(font_view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::TextureView(sprites_view),
+            },
+        ],
+    })
+}
+
+/// Draws straight into the target after post-processing, without depth
+fn create_ui_pipeline(
+    gpu_ctx: &GpuCtx,
+    bind_group_layout: &BindGroupLayout,
+    shader: &ShaderModule,
+) -> RenderPipeline {
+    let layout = gpu_ctx
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+    gpu_ctx
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: None,
+                buffers: &[UiVertex::layout()],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: None,
+                targets: &[Some(ColorTargetState {
+                    format: gpu_ctx.surface_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        })
+}
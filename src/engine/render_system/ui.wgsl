@@ -0,0 +1,79 @@
+// Screen space quads drawn over the finished frame: text from the bitmap font atlas, solid
+// rectangles and sprites from a texture array
+struct Ui {
+    // xy: size of the screen in the pixels positions are given in
+    screen_size: vec4f,
+    // x: the target encodes sRGB itself
+    flags: vec4u,
+}
+
+struct VertexInput {
+    // Pixels from the top left corner
+    @location(0) pos: vec2f,
+    // Texels of the font atlas, or 0-1 across the sprite
+    @location(1) tex_coords: vec2f,
+    // sRGB with straight alpha
+    @location(2) color: vec4f,
+    // Layer of the sprite array, negative for the font
+    @location(3) layer: i32,
+}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) color: vec4f,
+    @location(2) @interpolate(flat) layer: i32,
+}
+
+@group(0) @binding(0)
+var<uniform> ui: Ui;
+
+@group(0) @binding(1)
+var font: texture_2d<f32>;
+
+@group(0) @binding(2)
+var sprites: texture_2d_array<f32>;
+
+@vertex
+fn v_main(in: VertexInput) -> VertexOutput {
+    let ndc = in.pos / ui.screen_size.xy * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+
+    var out: VertexOutput;
+    out.pos = vec4f(ndc, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    out.layer = in.layer;
+    return out;
+}
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
+fn linear_to_srgb(color: vec3f) -> vec3f {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3f(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3f(0.0031308));
+}
+
+// Nearest texel only, glyphs are scaled by whole numbers and sprites are pixel art
+@fragment
+fn f_main(in: VertexOutput) -> @location(0) vec4f {
+    var texel = vec4f(1.0);
+    if (in.layer < 0) {
+        texel.a = textureLoad(font, vec2i(floor(in.tex_coords)), 0).r;
+    } else {
+        let size = vec2i(textureDimensions(sprites));
+        let coords = clamp(vec2i(floor(in.tex_coords * vec2f(size))), vec2i(0), size - 1);
+        texel = textureLoad(sprites, coords, in.layer, 0);
+    }
+
+    // Sprites are sampled as linear values, so the tint is blended in linear space as well
+    var color = texel.rgb * srgb_to_linear(in.color.rgb);
+    if (ui.flags.x == 0u) {
+        color = linear_to_srgb(color);
+    }
+    return vec4f(color, texel.a * in.color.a);
+}
@@ -0,0 +1,93 @@
+use image::RgbaImage;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes `image` to a PNG named after the current UTC time on a background thread, encoding a
+/// high resolution capture takes long enough to be noticeable as a hitch
+pub fn save_screenshot(image: RgbaImage, dir: &Path) {
+    let path = dir.join(format!("{}.png", timestamp(SystemTime::now())));
+    std::thread::spawn(move || match write_png(&image, &path) {
+        Ok(()) => println!(
+            "Saved {}x{} screenshot to {}",
+            image.width(),
+            image.height(),
+            path.display()
+        ),
+        Err(err) => eprintln!("Failed to save screenshot to {}: {err}", path.display()),
+    });
+}
+
+fn write_png(image: &RgbaImage, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    image.save(path)?;
+    Ok(())
+}
+
+/// `YYYY-MM-DD_HH-MM-SS.mmm`, sorts chronologically and is valid in file names everywhere
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}.{:03}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date, from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn epoch_is_the_first_day() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01_00-00-00.000");
+    }
+
+    #[test]
+    fn leap_days_exist() {
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(19_783), (2024, 3, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        // Centuries are only leap years every 400 years
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn years_roll_over_at_midnight() {
+        let new_year = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        assert_eq!(
+            timestamp(new_year - Duration::from_millis(1)),
+            "2023-12-31_23-59-59.999"
+        );
+        assert_eq!(timestamp(new_year), "2024-01-01_00-00-00.000");
+    }
+}
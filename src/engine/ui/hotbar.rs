@@ -0,0 +1,91 @@
+use crate::engine::chunk_system::{BlockFaces, BlockType};
+use crate::engine::render_system::{Anchor, UiBatch};
+use std::collections::HashMap;
+
+const SLOT_COUNT: usize = 9;
+const SLOT_SIZE: f32 = 44.0;
+const SLOT_GAP: f32 = 4.0;
+/// Between the block icon and the edge of its slot
+const ICON_INSET: f32 = 6.0;
+const SELECTION_BORDER: f32 = 3.0;
+/// Space between the hotbar and the bottom of the screen
+const MARGIN: f32 = 8.0;
+
+const SLOT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const SELECTION_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+const ICON_TINT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Blocks to pick from along the bottom of the screen, selected with the number keys or the
+/// scroll wheel
+pub struct Hotbar {
+    slots: [Option<BlockType>; SLOT_COUNT],
+    /// Sprite layer of each slot's block, see `RenderSystem::set_ui_sprites`
+    icons: [Option<u32>; SLOT_COUNT],
+    selected: usize,
+}
+
+impl Hotbar {
+    /// Sprites have to be the block textures for the icons to match `faces`
+    pub fn new(faces: &HashMap<BlockType, BlockFaces>) -> Self {
+        let mut slots = [None; SLOT_COUNT];
+        // The only block there is so far
+        slots[0] = Some(BlockType::Solid);
+
+        let mut hotbar = Self {
+            slots,
+            icons: [None; SLOT_COUNT],
+            selected: 0,
+        };
+        hotbar.set_block_faces(faces);
+        hotbar
+    }
+
+    /// Icons show the front face of each block
+    pub fn set_block_faces(&mut self, faces: &HashMap<BlockType, BlockFaces>) {
+        self.icons = self.slots.map(|block| {
+            block
+                .and_then(|block| faces.get(&block))
+                .map(|faces| faces.front)
+        });
+    }
+
+    /// Out of range slots are ignored
+    pub fn select(&mut self, slot: usize) {
+        if slot < SLOT_COUNT {
+            self.selected = slot;
+        }
+    }
+
+    /// Positive steps move right, wrapping around at either end
+    pub fn scroll(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(SLOT_COUNT as i32) as usize;
+    }
+
+    pub fn draw(&self, ui: &mut UiBatch) {
+        let width = SLOT_COUNT as f32 * (SLOT_SIZE + SLOT_GAP) - SLOT_GAP;
+        let (left, top) = ui.anchor(Anchor::Bottom, (0.0, -MARGIN), (width, SLOT_SIZE));
+
+        for (slot, icon) in self.icons.iter().enumerate() {
+            let pos = (left + slot as f32 * (SLOT_SIZE + SLOT_GAP), top);
+            if slot == self.selected {
+                let border = SELECTION_BORDER;
+                ui.rect(
+                    (pos.0 - border, pos.1 - border),
+                    (SLOT_SIZE + 2.0 * border, SLOT_SIZE + 2.0 * border),
+                    SELECTION_COLOR,
+                );
+            }
+            ui.rect(pos, (SLOT_SIZE, SLOT_SIZE), SLOT_COLOR);
+
+            if let Some(layer) = *icon {
+                let icon_size = SLOT_SIZE - 2.0 * ICON_INSET;
+                ui.sprite(
+                    (pos.0 + ICON_INSET, pos.1 + ICON_INSET),
+                    (icon_size, icon_size),
+                    layer,
+                    ICON_TINT,
+                );
+            }
+        }
+    }
+}
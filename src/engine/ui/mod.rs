@@ -0,0 +1,58 @@
+use crate::engine::render_system::{Anchor, UiBatch};
+
+mod hotbar;
+mod pause_menu;
+pub use hotbar::Hotbar;
+pub use pause_menu::{PauseMenu, PauseMenuAction};
+
+const CROSSHAIR_LENGTH: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+const CROSSHAIR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+const BUTTON_COLOR: [f32; 4] = [0.15, 0.15, 0.15, 0.85];
+const BUTTON_HOVER_COLOR: [f32; 4] = [0.35, 0.35, 0.35, 0.9];
+const BUTTON_TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BUTTON_TEXT_SCALE: u32 = 2;
+
+/// A plus in the middle of the screen, where blocks are aimed at
+pub fn draw_crosshair(ui: &mut UiBatch) {
+    let horizontal = (CROSSHAIR_LENGTH, CROSSHAIR_THICKNESS);
+    let vertical = (CROSSHAIR_THICKNESS, CROSSHAIR_LENGTH);
+    let pos = ui.anchor(Anchor::Center, (0.0, 0.0), horizontal);
+    ui.rect(pos, horizontal, CROSSHAIR_COLOR);
+    let pos = ui.anchor(Anchor::Center, (0.0, 0.0), vertical);
+    ui.rect(pos, vertical, CROSSHAIR_COLOR);
+}
+
+/// Rectangle with a centered label that lights up while the cursor is over it. Buttons are laid
+/// out again every frame, so clicks are tested against where they were drawn last
+pub struct Button {
+    pub pos: (f32, f32),
+    pub size: (f32, f32),
+    pub label: &'static str,
+}
+
+impl Button {
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.pos.0
+            && y >= self.pos.1
+            && x < self.pos.0 + self.size.0
+            && y < self.pos.1 + self.size.1
+    }
+
+    pub fn draw(&self, ui: &mut UiBatch, cursor: (f32, f32)) {
+        let color = if self.contains(cursor) {
+            BUTTON_HOVER_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+        ui.rect(self.pos, self.size, color);
+
+        let (text_width, text_height) = UiBatch::text_size(self.label, BUTTON_TEXT_SCALE);
+        let text_pos = (
+            (self.pos.0 + (self.size.0 - text_width) / 2.0).round(),
+            (self.pos.1 + (self.size.1 - text_height) / 2.0).round(),
+        );
+        ui.text(text_pos, BUTTON_TEXT_SCALE, BUTTON_TEXT_COLOR, self.label);
+    }
+}
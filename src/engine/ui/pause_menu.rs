@@ -0,0 +1,88 @@
+use crate::engine::render_system::{Anchor, UiBatch};
+use crate::engine::ui::Button;
+
+const BUTTON_SIZE: (f32, f32) = (240.0, 40.0);
+const BUTTON_GAP: f32 = 12.0;
+const TITLE: &str = "Paused";
+const TITLE_SCALE: u32 = 4;
+/// Space between the title and the first button
+const TITLE_GAP: f32 = 32.0;
+
+const DIM_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const TITLE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+const BUTTONS: [(&str, PauseMenuAction); 2] = [
+    ("Resume", PauseMenuAction::Resume),
+    ("Quit", PauseMenuAction::Quit),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PauseMenuAction {
+    Resume,
+    Quit,
+}
+
+/// Opened with Escape, the world stops and the cursor is released while it's open
+pub struct PauseMenu {
+    open: bool,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        Self { open: false }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Draws the menu if it's open, `click` is whether the left mouse button was clicked at
+    /// `cursor` since the last frame
+    pub fn draw(
+        &self,
+        ui: &mut UiBatch,
+        cursor: (f32, f32),
+        click: bool,
+    ) -> Option<PauseMenuAction> {
+        if !self.open {
+            return None;
+        }
+
+        let screen_size = ui.screen_size();
+        ui.rect((0.0, 0.0), screen_size, DIM_COLOR);
+
+        let title_size = UiBatch::text_size(TITLE, TITLE_SCALE);
+        let buttons_height = BUTTONS.len() as f32 * (BUTTON_SIZE.1 + BUTTON_GAP) - BUTTON_GAP;
+        let menu_size = (
+            title_size.0.max(BUTTON_SIZE.0),
+            title_size.1 + TITLE_GAP + buttons_height,
+        );
+        let (left, top) = ui.anchor(Anchor::Center, (0.0, 0.0), menu_size);
+
+        let title_left = (left + (menu_size.0 - title_size.0) / 2.0).round();
+        ui.text((title_left, top), TITLE_SCALE, TITLE_COLOR, TITLE);
+
+        let mut action = None;
+        let buttons_left = (left + (menu_size.0 - BUTTON_SIZE.0) / 2.0).round();
+        let buttons_top = top + title_size.1 + TITLE_GAP;
+        for (i, (label, button_action)) in BUTTONS.into_iter().enumerate() {
+            let button = Button {
+                pos: (
+                    buttons_left,
+                    buttons_top + i as f32 * (BUTTON_SIZE.1 + BUTTON_GAP),
+                ),
+                size: BUTTON_SIZE,
+                label,
+            };
+            button.draw(ui, cursor);
+            if click && button.contains(cursor) {
+                action = Some(button_action);
+            }
+        }
+        action
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FreeListStats {
+    pub capacity: u64,
+    pub used: u64,
+    pub free_blocks: usize,
+    pub largest_free_block: u64,
+}
+
+impl FreeListStats {
+    /// 0.0 when all free space is one contiguous block, approaching 1.0 as it gets split up
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+        if free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f32 / free as f32
+        }
+    }
+}
+
+/// First fit allocator over a range of `capacity` units, freed blocks get merged with their neighbours
+pub struct FreeListAllocator {
+    capacity: u64,
+    used: u64,
+    /// Free blocks keyed by offset, value is the size of the block
+    free: BTreeMap<u64, u64>,
+}
+
+impl FreeListAllocator {
+    pub fn new(capacity: u64) -> Self {
+        let mut free = BTreeMap::new();
+        if capacity > 0 {
+            free.insert(0, capacity);
+        }
+
+        Self {
+            capacity,
+            used: 0,
+            free,
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn alloc(&mut self, size: u64) -> Option<u64> {
+        if size == 0 {
+            return None;
+        }
+
+        let (&offset, &block_size) = self
+            .free
+            .iter()
+            .find(|(_, block_size)| **block_size >= size)?;
+        self.free.remove(&offset);
+        if block_size > size {
+            self.free.insert(offset + size, block_size - size);
+        }
+
+        self.used += size;
+        Some(offset)
+    }
+
+    pub fn free(&mut self, offset: u64, size: u64) {
+        if size == 0 {
+            return;
+        }
+
+        self.used -= size;
+        let mut offset = offset;
+        let mut size = size;
+
+        // Merge with the block right after this one
+        if let Some(next_size) = self.free.remove(&(offset + size)) {
+            size += next_size;
+        }
+
+        // Merge with the block right before this one
+        if let Some((&prev_offset, &prev_size)) = self.free.range(..offset).next_back()
+            && prev_offset + prev_size == offset
+        {
+            self.free.remove(&prev_offset);
+            offset = prev_offset;
+            size += prev_size;
+        }
+
+        self.free.insert(offset, size);
+    }
+
+    /// Extends the range, the new space is added to the end
+    pub fn grow(&mut self, new_capacity: u64) {
+        if new_capacity <= self.capacity {
+            return;
+        }
+
+        let old_capacity = self.capacity;
+        self.capacity = new_capacity;
+        self.used += new_capacity - old_capacity;
+        self.free(old_capacity, new_capacity - old_capacity);
+    }
+
+    pub fn stats(&self) -> FreeListStats {
+        FreeListStats {
+            capacity: self.capacity,
+            used: self.used,
+            free_blocks: self.free.len(),
+            largest_free_block: self.free.values().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_blocks(allocator: &FreeListAllocator) -> Vec<(u64, u64)> {
+        allocator
+            .free
+            .iter()
+            .map(|(&offset, &size)| (offset, size))
+            .collect()
+    }
+
+    #[test]
+    fn exact_fit_uses_the_whole_block() {
+        let mut allocator = FreeListAllocator::new(64);
+        assert_eq!(allocator.alloc(64), Some(0));
+        assert!(free_blocks(&allocator).is_empty());
+        assert_eq!(allocator.alloc(1), None);
+    }
+
+    #[test]
+    fn exact_fit_reuses_a_freed_hole() {
+        let mut allocator = FreeListAllocator::new(64);
+        allocator.alloc(16);
+        let hole = allocator.alloc(8).unwrap();
+        allocator.alloc(16);
+        allocator.free(hole, 8);
+
+        assert_eq!(allocator.alloc(8), Some(hole));
+        assert_eq!(free_blocks(&allocator), [(40, 24)]);
+    }
+
+    #[test]
+    fn split_leaves_the_rest_free() {
+        let mut allocator = FreeListAllocator::new(64);
+        assert_eq!(allocator.alloc(10), Some(0));
+        assert_eq!(free_blocks(&allocator), [(10, 54)]);
+        assert_eq!(allocator.alloc(20), Some(10));
+        assert_eq!(free_blocks(&allocator), [(30, 34)]);
+    }
+
+    #[test]
+    fn first_fit_skips_blocks_that_are_too_small() {
+        let mut allocator = FreeListAllocator::new(64);
+        let small = allocator.alloc(4).unwrap();
+        allocator.alloc(4);
+        allocator.free(small, 4);
+
+        assert_eq!(allocator.alloc(8), Some(8));
+        assert_eq!(free_blocks(&allocator), [(0, 4), (16, 48)]);
+    }
+
+    #[test]
+    fn zero_sized_allocations_fail() {
+        let mut allocator = FreeListAllocator::new(64);
+        assert_eq!(allocator.alloc(0), None);
+        assert_eq!(allocator.stats().used, 0);
+    }
+
+    #[test]
+    fn free_merges_with_previous_block() {
+        let mut allocator = FreeListAllocator::new(30);
+        let a = allocator.alloc(10).unwrap();
+        let b = allocator.alloc(10).unwrap();
+        allocator.alloc(10);
+
+        allocator.free(a, 10);
+        allocator.free(b, 10);
+        assert_eq!(free_blocks(&allocator), [(0, 20)]);
+    }
+
+    #[test]
+    fn free_merges_with_next_block() {
+        let mut allocator = FreeListAllocator::new(30);
+        allocator.alloc(10);
+        let b = allocator.alloc(10).unwrap();
+
+        // The tail from 20 is still free
+        allocator.free(b, 10);
+        assert_eq!(free_blocks(&allocator), [(10, 20)]);
+    }
+
+    #[test]
+    fn free_merges_with_both_neighbours() {
+        let mut allocator = FreeListAllocator::new(40);
+        allocator.alloc(10);
+        let a = allocator.alloc(10).unwrap();
+        let b = allocator.alloc(10).unwrap();
+        let c = allocator.alloc(10).unwrap();
+
+        allocator.free(a, 10);
+        allocator.free(c, 10);
+        assert_eq!(free_blocks(&allocator), [(10, 10), (30, 10)]);
+
+        allocator.free(b, 10);
+        assert_eq!(free_blocks(&allocator), [(10, 30)]);
+    }
+
+    #[test]
+    fn free_does_not_merge_with_distant_blocks() {
+        let mut allocator = FreeListAllocator::new(30);
+        let a = allocator.alloc(10).unwrap();
+        allocator.alloc(10);
+        allocator.alloc(10);
+
+        allocator.free(a, 10);
+        assert_eq!(free_blocks(&allocator), [(0, 10)]);
+    }
+
+    #[test]
+    fn grow_extends_trailing_free_block() {
+        let mut allocator = FreeListAllocator::new(32);
+        allocator.alloc(16);
+        allocator.grow(64);
+
+        assert_eq!(allocator.capacity(), 64);
+        assert_eq!(free_blocks(&allocator), [(16, 48)]);
+        assert_eq!(allocator.stats().used, 16);
+    }
+
+    #[test]
+    fn grow_when_full_adds_a_new_block() {
+        let mut allocator = FreeListAllocator::new(32);
+        allocator.alloc(32);
+        allocator.grow(48);
+
+        assert_eq!(free_blocks(&allocator), [(32, 16)]);
+        assert_eq!(allocator.alloc(16), Some(32));
+    }
+
+    #[test]
+    fn grow_never_shrinks() {
+        let mut allocator = FreeListAllocator::new(32);
+        allocator.grow(16);
+        assert_eq!(allocator.capacity(), 32);
+        assert_eq!(free_blocks(&allocator), [(0, 32)]);
+    }
+
+    #[test]
+    fn stats_after_known_sequence() {
+        let mut allocator = FreeListAllocator::new(100);
+        let a = allocator.alloc(10).unwrap();
+        allocator.alloc(20);
+        let c = allocator.alloc(30).unwrap();
+        allocator.alloc(10);
+        allocator.free(a, 10);
+        allocator.free(c, 30);
+
+        // Free: 10 at 0, 30 at 30 and the 30 left at the end
+        let stats = allocator.stats();
+        assert_eq!(stats.capacity, 100);
+        assert_eq!(stats.used, 30);
+        assert_eq!(stats.free_blocks, 3);
+        assert_eq!(stats.largest_free_block, 30);
+        assert!((stats.fragmentation() - (1.0 - 30.0 / 70.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn contiguous_free_space_is_not_fragmented() {
+        let mut allocator = FreeListAllocator::new(100);
+        assert_eq!(allocator.stats().fragmentation(), 0.0);
+
+        let a = allocator.alloc(40).unwrap();
+        assert_eq!(allocator.stats().fragmentation(), 0.0);
+
+        allocator.alloc(60);
+        let stats = allocator.stats();
+        assert_eq!(stats.free_blocks, 0);
+        assert_eq!(stats.fragmentation(), 0.0);
+
+        allocator.free(a, 40);
+        assert_eq!(allocator.stats().fragmentation(), 0.0);
+    }
+}
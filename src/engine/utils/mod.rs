@@ -1,3 +1,5 @@
+mod free_list;
 mod thread_pool;
 
+pub use free_list::{FreeListAllocator, FreeListStats};
 pub use thread_pool::ThreadPool;
@@ -1,11 +1,16 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
 
+type Job = Box<dyn FnOnce() + Send>;
+
 pub struct ThreadPool {
     threads: Vec<Option<JoinHandle<()>>>,
-    tx: Option<Sender<Box<dyn FnOnce() + Send>>>,
+    tx: Option<Sender<Job>>,
+    /// Jobs sent that no thread has picked up yet
+    queued: Arc<AtomicUsize>,
 }
 
 impl ThreadPool {
@@ -14,23 +19,41 @@ impl ThreadPool {
         let (tx, recv) = channel();
         let tx = Some(tx);
         let recv = Arc::new(Mutex::new(recv));
+        let queued = Arc::new(AtomicUsize::new(0));
 
         for _ in 0..thread_count {
             let recv = Arc::clone(&recv);
+            let queued = Arc::clone(&queued);
             let thd = thread::spawn(move || {
-                handle_jobs(recv);
+                handle_jobs(recv, queued);
             });
             threads.push(Some(thd));
         }
 
-        Self { threads, tx }
+        Self {
+            threads,
+            tx,
+            queued,
+        }
     }
 
     pub fn run<J: FnOnce() + Send + 'static>(&self, job: J) {
         if let Some(tx) = self.tx.as_ref() {
-            let _ = tx.send(Box::new(job));
+            self.queued.fetch_add(1, Ordering::Relaxed);
+            if tx.send(Box::new(job)).is_err() {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+            }
         }
     }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Jobs waiting for a free thread, the ones already running aren't counted
+    pub fn queued_jobs(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
 }
 
 impl Drop for ThreadPool {
@@ -49,7 +72,7 @@ impl Drop for ThreadPool {
     }
 }
 
-fn handle_jobs(recv: Arc<Mutex<Receiver<Box<dyn FnOnce() + Send>>>>) {
+fn handle_jobs(recv: Arc<Mutex<Receiver<Job>>>, queued: Arc<AtomicUsize>) {
     'recv: loop {
         // Scope is to ensure the lock is dropped before running the job
         let job = {
@@ -65,6 +88,7 @@ fn handle_jobs(recv: Arc<Mutex<Receiver<Box<dyn FnOnce() + Send>>>>) {
             }
         };
 
+        queued.fetch_sub(1, Ordering::Relaxed);
         job();
     }
 }
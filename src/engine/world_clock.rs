@@ -0,0 +1,173 @@
+use cgmath::{InnerSpace, Vector3};
+use std::f32::consts::TAU;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Fixed steps in a full day, 20 minutes at 60 steps a second
+pub const DAY_LENGTH: u64 = 20 * 60 * 60;
+/// Time of day the clock starts at in a new world, a little after sunrise
+const DEFAULT_TIME_OF_DAY: f32 = 0.3;
+/// Tilts the sun's path towards +z so it never passes straight overhead
+const SUN_TILT: f32 = 0.3;
+
+/// Time of day in the world, 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+pub struct WorldClock {
+    ticks: u64,
+    paused: bool,
+}
+
+impl WorldClock {
+    pub fn new() -> Self {
+        let mut clock = Self {
+            ticks: 0,
+            paused: false,
+        };
+        clock.set_time_of_day(DEFAULT_TIME_OF_DAY);
+        clock
+    }
+
+    /// Stored as `<ticks> <paused>` on a single line
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed world clock file");
+
+        let mut parts = contents.split_whitespace();
+        let ticks = parts
+            .next()
+            .and_then(|ticks| ticks.parse().ok())
+            .ok_or_else(invalid)?;
+        let paused = parts
+            .next()
+            .and_then(|paused| paused.parse().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self { ticks, paused })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format!("{} {}\n", self.ticks, self.paused))
+    }
+
+    /// Advances by one fixed time step unless paused
+    pub fn tick(&mut self) {
+        if !self.paused {
+            self.ticks += 1;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn get_time_of_day(&self) -> f32 {
+        (self.ticks % DAY_LENGTH) as f32 / DAY_LENGTH as f32
+    }
+
+    /// Keeps the day count, wraps `time_of_day` into `0.0..1.0`
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        let day = self.ticks / DAY_LENGTH;
+        let tick_of_day = (time_of_day.rem_euclid(1.0) * DAY_LENGTH as f32) as u64;
+        // `rem_euclid` rounds tiny negative values up to 1.0, which is midnight again
+        self.ticks = day * DAY_LENGTH + tick_of_day % DAY_LENGTH;
+    }
+
+    /// Points from the world towards the sun, rising in +x and setting in -x
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.get_time_of_day() - 0.25) * TAU;
+        Vector3::new(angle.cos(), angle.sin(), SUN_TILT).normalize()
+    }
+
+    /// Always opposite the sun
+    pub fn moon_direction(&self) -> Vector3<f32> {
+        -self.sun_direction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("world_clock_{}_{name}.txt", std::process::id()))
+    }
+
+    #[test]
+    fn saved_clock_loads_back() {
+        let path = temp_path("round_trip");
+        let mut clock = WorldClock::new();
+        clock.ticks = 3 * DAY_LENGTH + 1234;
+        clock.set_paused(true);
+        clock.save(&path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{} true\n", 3 * DAY_LENGTH + 1234)
+        );
+        let loaded = WorldClock::load(&path).unwrap();
+        assert_eq!(loaded.ticks, clock.ticks);
+        assert!(loaded.is_paused());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let path = temp_path("malformed");
+        for contents in [
+            "",
+            "1234",
+            "1234 maybe",
+            "-5 false",
+            "noon false",
+            "1 false 2",
+        ] {
+            fs::write(&path, contents).unwrap();
+            let err = WorldClock::load(&path).err();
+            assert_eq!(
+                err.map(|err| err.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "{contents:?}"
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn time_of_day_wraps() {
+        let mut clock = WorldClock::new();
+        clock.ticks = 2 * DAY_LENGTH;
+        for (time_of_day, expected) in [(1.25, 0.25), (-0.25, 0.75), (3.0, 0.0), (-1e-9, 0.0)] {
+            clock.set_time_of_day(time_of_day);
+            assert!(
+                (clock.get_time_of_day() - expected).abs() < 1e-4,
+                "{time_of_day} became {}",
+                clock.get_time_of_day()
+            );
+            assert_eq!(clock.ticks / DAY_LENGTH, 2, "{time_of_day} changed the day");
+        }
+    }
+
+    #[test]
+    fn paused_clock_does_not_advance() {
+        let mut clock = WorldClock::new();
+        let ticks = clock.ticks;
+        clock.set_paused(true);
+        clock.tick();
+        assert_eq!(clock.ticks, ticks);
+
+        clock.set_paused(false);
+        clock.tick();
+        assert_eq!(clock.ticks, ticks + 1);
+    }
+}
@@ -2,4 +2,5 @@ mod engine;
 mod launcher;
 mod window;
 
+pub use engine::{HeadlessScene, render_headless};
 pub use launcher::launch;
@@ -2,7 +2,7 @@ use crate::engine::Engine;
 use winit::application::ApplicationHandler;
 use winit::event::{DeviceEvent, DeviceId, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
-use winit::window::{Cursor, CursorGrabMode, Fullscreen, WindowAttributes, WindowId};
+use winit::window::{WindowAttributes, WindowId};
 
 pub struct WindowHandler {
     engine: Option<Engine>,
@@ -29,7 +29,7 @@ impl ApplicationHandler for WindowHandler {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        window_id: WindowId,
+        _window_id: WindowId,
         event: WindowEvent,
     ) {
         // Safety: Engine should be initialized if we have a window to get events from
@@ -38,8 +38,20 @@ impl ApplicationHandler for WindowHandler {
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(size) => engine.resize(size.width, size.height),
-            WindowEvent::RedrawRequested => engine.run_frame(),
+            WindowEvent::RedrawRequested => {
+                engine.run_frame();
+                if engine.exit_requested() {
+                    event_loop.exit();
+                }
+            }
             WindowEvent::KeyboardInput { event, .. } => engine.handle_key_input(event),
+            WindowEvent::CursorMoved { position, .. } => {
+                engine.handle_cursor_move(position.x, position.y)
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                engine.handle_mouse_button(button, state.is_pressed())
+            }
+            WindowEvent::MouseWheel { delta, .. } => engine.handle_mouse_wheel(delta),
             WindowEvent::Focused(flag) => engine.window_focus(flag),
             _ => (),
         };
@@ -47,27 +59,26 @@ impl ApplicationHandler for WindowHandler {
 
     fn device_event(
         &mut self,
-        event_loop: &ActiveEventLoop,
-        device_id: DeviceId,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
         event: DeviceEvent,
     ) {
         // Safety: Engine should be initialized if we have a window to get events from
         let engine = unsafe { self.engine.as_mut().unwrap_unchecked() };
 
-        match event {
-            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
-                engine.handle_mouse_move(dx, dy);
-            }
-            _ => (),
+        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            engine.handle_mouse_move(dx, dy);
         }
     }
 
-    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
         // Safety: Engine should be initialized at this point
         unsafe { self.engine.as_ref().unwrap_unchecked() }.request_redraw();
     }
 
-    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
-        // TODO: Notify engine to shutdown
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(engine) = self.engine.as_mut() {
+            engine.shutdown();
+        }
     }
 }
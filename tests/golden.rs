@@ -0,0 +1,88 @@
+//! Renders fixed scenes without a window and compares them against the PNGs in `tests/golden`.
+//! Run with `UPDATE_GOLDEN=1` to write new goldens after an intended visual change
+
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use voxel_game_v1::{HeadlessScene, render_headless};
+
+/// Largest difference in any channel that still counts as the same pixel
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed past `CHANNEL_TOLERANCE`, software rasterizers disagree slightly on edges
+const MAX_MISMATCHED_PIXELS: f64 = 0.005;
+
+#[test]
+fn morning_terrain() {
+    assert_matches_golden(
+        "morning_terrain",
+        &render_headless(&HeadlessScene::default()),
+    );
+}
+
+#[test]
+fn sunset_shadows() {
+    let scene = HeadlessScene {
+        camera_pos: [-20.0, 258.0, 12.0],
+        camera_yaw_deg: 10.0,
+        camera_pitch_deg: -15.0,
+        time_of_day: 0.72,
+        ..HeadlessScene::default()
+    };
+    assert_matches_golden("sunset_shadows", &render_headless(&scene));
+}
+
+fn assert_matches_golden(name: &str, actual: &RgbaImage) {
+    let golden_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual
+            .save(&golden_path)
+            .expect("Failed to write golden image");
+        return;
+    }
+
+    let golden = image::open(&golden_path)
+        .unwrap_or_else(|err| {
+            panic!(
+                "Failed to open {}: {err}, run with UPDATE_GOLDEN=1 to create it",
+                golden_path.display()
+            )
+        })
+        .to_rgba8();
+
+    let mismatched = if golden.dimensions() == actual.dimensions() {
+        golden
+            .pixels()
+            .zip(actual.pixels())
+            .filter(|(a, b)| {
+                a.0.iter()
+                    .zip(b.0)
+                    .any(|(&a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE)
+            })
+            .count()
+    } else {
+        actual.len()
+    };
+    let mismatched_fraction = mismatched as f64 / (actual.width() * actual.height()) as f64;
+
+    if mismatched_fraction > MAX_MISMATCHED_PIXELS {
+        let actual_path = save_actual(name, actual);
+        panic!(
+            "{name} differs from its golden in {:.2}% of pixels, the output was saved to {}",
+            mismatched_fraction * 100.0,
+            actual_path.display()
+        );
+    }
+}
+
+/// Kept next to the other build output so CI can upload it
+fn save_actual(name: &str, actual: &RgbaImage) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&dir).expect("Failed to create the golden output directory");
+    let path = dir.join(format!("{name}.png"));
+    actual
+        .save(&path)
+        .expect("Failed to write the rendered image");
+    path
+}